@@ -0,0 +1,99 @@
+// throughput of a batch run, in memory and on the key-value store
+// the same generated transactions are run on both: rounds of deposits, withdrawals and disputes
+// across the clients, each transaction being a unit of work committed to the storage
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rust_decimal_macros::dec;
+use tokio::runtime::Runtime;
+use tren::tren::client::ClientId;
+use tren::tren::engine::runner::Runner;
+use tren::tren::handlers::execute_handler::ExecuteHandler;
+use tren::tren::storage::cached_accounts_storage::CachedAccountsStorage;
+use tren::tren::storage::in_memory_accounts_storage::InMemoryAccountsStorage;
+use tren::tren::storage::kv_accounts_storage::KvAccountsStorage;
+use tren::tren::storage::store::AccountsStorage;
+use tren::tren::transactions::{Transaction, TransactionId, TransactionType};
+
+const CLIENTS: u16 = 100;
+const ROUNDS: u32 = 20;
+
+// in each group of four rounds: deposits, withdrawals, deposits, disputes of the last deposits
+fn transactions() -> Vec<Transaction> {
+    let mut transactions = vec![];
+    for round in 0..ROUNDS {
+        for client in 0..CLIENTS {
+            let client_id = ClientId::from(client);
+            let transaction_id =
+                TransactionId::from(round * u32::from(CLIENTS) + u32::from(client));
+            transactions.push(match round % 4 {
+                1 => Transaction::new(
+                    TransactionType::Withdrawal,
+                    client_id,
+                    transaction_id,
+                    Some(dec!(5)),
+                ),
+                3 => Transaction::new(
+                    TransactionType::Dispute,
+                    client_id,
+                    transaction_id - TransactionId::from(CLIENTS),
+                    None,
+                ),
+                _ => Transaction::new(
+                    TransactionType::Deposit,
+                    client_id,
+                    transaction_id,
+                    Some(dec!(10)),
+                ),
+            });
+        }
+    }
+    transactions
+}
+
+fn run<S: AccountsStorage>(runtime: &Runtime, storage: S, transactions: Vec<Transaction>) {
+    let mut runner = Runner::new(ExecuteHandler::default(), storage);
+    runtime
+        .block_on(runner.run_transactions(futures::stream::iter(
+            transactions.into_iter().map(Ok::<_, ()>),
+        )))
+        .expect("Expected an Ok value from runner");
+    // a run is over once it is durable
+    runtime
+        .block_on(runner.sync())
+        .expect("Expected the storage to be synced");
+}
+
+fn storage_benchmark(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("Expected a runtime");
+    let transactions = transactions();
+    let path = std::env::temp_dir().join(format!("tren_bench_{}.redb", std::process::id()));
+
+    let mut group = c.benchmark_group("storage");
+    group.throughput(Throughput::Elements(transactions.len() as u64));
+    group.sample_size(10);
+    group.bench_function("in_memory", |b| {
+        b.iter_batched(
+            || transactions.clone(),
+            |transactions| run(&runtime, InMemoryAccountsStorage::default(), transactions),
+            BatchSize::SmallInput,
+        );
+    });
+    group.bench_function("kv", |b| {
+        b.iter_batched(
+            || {
+                // each run starts from an empty store
+                let _ = std::fs::remove_file(&path);
+                let storage = KvAccountsStorage::open(&path).expect("Expected a storage");
+                (CachedAccountsStorage::new(storage), transactions.clone())
+            },
+            |(storage, transactions)| run(&runtime, storage, transactions),
+            BatchSize::PerIteration,
+        );
+    });
+    group.finish();
+
+    let _ = std::fs::remove_file(path);
+}
+
+criterion_group!(benches, storage_benchmark);
+criterion_main!(benches);
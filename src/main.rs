@@ -1,27 +1,456 @@
-use clap::{arg, command};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::TimeDelta;
+use clap::{arg, command, Arg, ArgMatches, Command};
+use rust_decimal::Decimal;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tren::tren::account::Account;
+use tren::tren::api::http_server;
+use tren::tren::client::ClientId;
+use tren::tren::diff::balance_diff::BalanceDiff;
+use tren::tren::diff::snapshot_reader::read_snapshots;
 use tren::tren::engine::runner::Runner;
-use tren::tren::handlers::execute_handler::ExecuteHandler;
+use tren::tren::exchange::rate_table::RateTable;
+use tren::tren::exchange::rates_reader::read_rates;
+use tren::tren::handlers::authorization_policy::AuthorizationPolicy;
+use tren::tren::handlers::dispute_policy::{
+    DisputeExpiry, DisputePolicy, ExpiryAction, OverdrawnDisputes,
+};
+use tren::tren::handlers::execute_handler::{ExecuteHandler, OutOfOrderPolicy};
+use tren::tren::handlers::fees::FeeSchedule;
+use tren::tren::handlers::interest::InterestPolicy;
+use tren::tren::handlers::limits::{Limits, WithdrawalLimits};
+use tren::tren::handlers::pipeline::{LogStage, Pipeline};
+use tren::tren::handlers::transaction_handler::TransactionHandler;
+use tren::tren::ids;
+use tren::tren::inputs::account_config::read_account_config;
+use tren::tren::inputs::fees_config::read_fee_schedule;
+use tren::tren::inputs::limits_config::read_client_limits;
 use tren::tren::output::csv_printer::CsvPrinter;
+use tren::tren::output::diff_printer::DiffPrinter;
+use tren::tren::output::json_lines_sink::JsonLinesSink;
+use tren::tren::output::overdrawn_printer::OverdrawnPrinter;
+use tren::tren::storage::bounded_accounts_storage::BoundedAccountsStorage;
+use tren::tren::storage::cached_accounts_storage::CachedAccountsStorage;
+use tren::tren::storage::history::HistoryPolicy;
 use tren::tren::storage::in_memory_accounts_storage::InMemoryAccountsStorage;
-use tren::tren::storage::store::AccountsStorage;
+use tren::tren::storage::kv_accounts_storage::KvAccountsStorage;
+use tren::tren::storage::store::{AccountsScope, AccountsStorage};
+use tren::tren::transactions::TransactionId;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let matches = command!()
         .arg(arg!(<file_path> "Filename to operate on (e.g. 'transactions.csv')"))
+        .args(engine_args())
+        .arg(arg!(--"what-if" <file_path> "Dry run these transactions on top of <file_path>, printing the resulting balances and what changed"))
+        .arg(arg!(--"overdrawn-report" <file_path> "Write the balances below zero to a csv"))
+        .arg(arg!(--summary "Print a summary of the run on stderr"))
+        .subcommand(
+            Command::new("serve")
+                .about("Serve balances and accept transactions over HTTP")
+                .arg(arg!(--bind <address> "Address to listen on").default_value("127.0.0.1:3000"))
+                .arg(arg!(--input <file_path> "Transactions to load before serving"))
+                .args(engine_args()),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare two accounts outputs, exiting with 1 if they differ beyond the tolerance")
+                .arg(arg!(<before> "Accounts output to compare from"))
+                .arg(arg!(<after> "Accounts output to compare to"))
+                .arg(
+                    arg!(--tolerance <amount> "Maximum difference allowed on each balance")
+                        .value_parser(Decimal::from_str)
+                        .default_value("0"),
+                )
+                .arg(
+                    arg!(--format <format> "Output format")
+                        .value_parser(["csv", "json"])
+                        .default_value("csv"),
+                ),
+        )
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .get_matches();
 
+    if let Some(serve_matches) = matches.subcommand_matches("serve") {
+        return serve(serve_matches).await;
+    }
+    if let Some(diff_matches) = matches.subcommand_matches("diff") {
+        // like diff(1): 0 when equal, 1 when different, 2 when in trouble
+        if let Err(err) = diff(diff_matches) {
+            eprintln!("Error: {err}");
+            std::process::exit(2);
+        }
+        return Ok(());
+    }
+
     let filename = matches
         .get_one::<String>("file_path")
         .ok_or_else(|| anyhow::anyhow!("Missing file_path argument"))?;
 
-    let handler = ExecuteHandler {};
-    let storage = InMemoryAccountsStorage::default();
+    if let Some(kv_path) = matches.get_one::<String>("kv-file") {
+        return run(filename, &matches, kv_storage(kv_path).await?).await;
+    }
+
+    match history_policy(&matches)? {
+        Some(policy) => run(filename, &matches, BoundedAccountsStorage::new(&policy)?).await,
+        None => run(filename, &matches, InMemoryAccountsStorage::default()).await,
+    }
+}
 
+/// The key-value store in the file at `path`, with the accounts of previous runs loaded so that
+/// they are printed and served too
+async fn kv_storage(
+    path: &str,
+) -> Result<CachedAccountsStorage<KvAccountsStorage>, Box<dyn std::error::Error>> {
+    let mut storage = CachedAccountsStorage::new(KvAccountsStorage::open(Path::new(path))?);
+    storage.load(&AccountsScope::All).await?;
+    Ok(storage)
+}
+
+/// How much history is kept in memory, if bounded. The spill file is scratch space that is
+/// removed at the end, an existing file is never used
+fn history_policy(
+    matches: &ArgMatches,
+) -> Result<Option<HistoryPolicy>, Box<dyn std::error::Error>> {
+    let Some(max_in_memory) = matches.get_one::<usize>("max-history") else {
+        return Ok(None);
+    };
+    let spill_path = matches
+        .get_one::<String>("spill-file")
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("Missing spill-file argument"))?;
+    if spill_path.exists() {
+        return Err(anyhow::anyhow!("Spill file [{}] already exists", spill_path.display()).into());
+    }
+    Ok(Some(HistoryPolicy {
+        max_in_memory: *max_in_memory,
+        spill_path,
+    }))
+}
+
+async fn run<S: AccountsStorage>(
+    filename: &str,
+    matches: &ArgMatches,
+    storage: S,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let handler = build_handler(matches)?;
     let mut runner = Runner::new(handler, storage);
-    let result = runner.run_from_csv(filename).await?;
+    let writing = subscribe_events_sinks(&mut runner, matches).await?;
+    let result = run_and_print(filename, matches, runner).await;
+    // the runner is gone: the events left are written, and the socket closed
+    if let Some(writing) = writing {
+        writing.await?;
+    }
+    result
+}
+
+async fn run_and_print<S: AccountsStorage>(
+    filename: &str,
+    matches: &ArgMatches,
+    mut runner: Runner<Pipeline<S, ExecuteHandler>, S>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    configure_accounts(&mut runner, matches).await?;
+    runner.run_from_csv(filename).await?;
+    // durable before anything is printed, errors included
+    runner.sync().await?;
+
+    if let Some(what_if) = matches.get_one::<String>("what-if") {
+        let mut handler = build_handler(matches)?;
+        // interest deposits of the dry run go on from the ids already posted
+        handler.inner_mut().interest = runner.handler().inner().interest;
+        let report = runner.dry_run_from_csv(handler, what_if).await?;
+
+        CsvPrinter::default().print(report.accounts.iter());
+        println!();
+        DiffPrinter::default().print(&report.diff);
+        write_overdrawn_report(report.accounts.iter(), matches)?;
+        print_summary(&runner, matches);
+        return Ok(());
+    }
+
+    CsvPrinter::default().print(runner.accounts_store().all_accounts_iter());
+    write_overdrawn_report(runner.accounts_store().all_accounts_iter(), matches)?;
+    print_summary(&runner, matches);
 
-    CsvPrinter::default().print(result.accounts_store.all_accounts_iter());
+    Ok(())
+}
+
+fn print_summary<H, S>(runner: &Runner<H, S>, matches: &ArgMatches)
+where
+    H: TransactionHandler<S>,
+    S: AccountsStorage,
+{
+    if matches.get_flag("summary") {
+        eprintln!("{}", runner.summary());
+    }
+}
+
+fn write_overdrawn_report<'a>(
+    accounts_iter: impl Iterator<Item = &'a Account>,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(path) = matches.get_one::<String>("overdrawn-report") {
+        let mut file = File::create(path)?;
+        OverdrawnPrinter::default().write(accounts_iter, &mut file)?;
+    }
+    Ok(())
+}
+
+async fn serve(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(kv_path) = matches.get_one::<String>("kv-file") {
+        return serve_from(matches, kv_storage(kv_path).await?).await;
+    }
+    match history_policy(matches)? {
+        Some(policy) => serve_from(matches, BoundedAccountsStorage::new(&policy)?).await,
+        None => serve_from(matches, InMemoryAccountsStorage::default()).await,
+    }
+}
+
+async fn serve_from<S: AccountsStorage + Send + 'static>(
+    matches: &ArgMatches,
+    storage: S,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let address = matches
+        .get_one::<String>("bind")
+        .ok_or_else(|| anyhow::anyhow!("Missing bind argument"))?;
+
+    let handler = build_handler(matches)?;
+    let mut runner = Runner::new(handler, storage);
+    let writing = subscribe_events_sinks(&mut runner, matches).await?;
+    configure_accounts(&mut runner, matches).await?;
+    if let Some(filename) = matches.get_one::<String>("input") {
+        runner.run_from_csv(filename).await?;
+    }
+
+    let listener = TcpListener::bind(address).await?;
+    // stopping syncs the storage, then drops it, e.g. removing the spill file
+    http_server::serve_until(listener, runner, async {
+        let _ = tokio::signal::ctrl_c().await;
+    })
+    .await?;
+    if let Some(writing) = writing {
+        writing.await?;
+    }
+
+    Ok(())
+}
+
+fn diff(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let before = matches
+        .get_one::<String>("before")
+        .ok_or_else(|| anyhow::anyhow!("Missing before argument"))?;
+    let after = matches
+        .get_one::<String>("after")
+        .ok_or_else(|| anyhow::anyhow!("Missing after argument"))?;
+    let tolerance = *matches
+        .get_one::<Decimal>("tolerance")
+        .ok_or_else(|| anyhow::anyhow!("Missing tolerance argument"))?;
+
+    let diff = BalanceDiff::compare(read_snapshots(before)?, read_snapshots(after)?);
+
+    match matches.get_one::<String>("format").map(String::as_str) {
+        Some("json") => DiffPrinter::default().print_json(&diff, tolerance)?,
+        _ => DiffPrinter::default().print(&diff),
+    }
+
+    if diff.exceeds(tolerance) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Arguments shared by every command running the engine
+fn engine_args() -> Vec<Arg> {
+    vec![
+        arg!(--events <file_path> "Write engine events as JSON Lines to a file"),
+        arg!(--"events-socket" <address> "Stream engine events as JSON Lines to a TCP socket"),
+        arg!(--trace "Print every transaction with its outcome on stderr"),
+        arg!(--"max-history" <count> "Transactions kept in memory per client, older ones are spilled to disk. Logs, --what-if and the API only see those in memory, without dispute, resolve and chargeback rows")
+            .value_parser(clap::value_parser!(usize))
+            .requires("spill-file"),
+        arg!(--"spill-file" <file_path> "File transactions beyond --max-history are spilled to, which must not exist and is removed at the end")
+            .requires("max-history"),
+        arg!(--"kv-file" <file_path> "Keep accounts and transactions in this key-value store file, created if needed")
+            .conflicts_with("max-history"),
+        arg!(--"out-of-order" <policy> "What to do with rows older than a previous one of the same client")
+            .value_parser(["accept", "skip", "fail"])
+            .default_value("skip"),
+        arg!(--"dispute-window" <days> "Refuse disputes on transactions older than this")
+            .value_parser(clap::value_parser!(u32)),
+        arg!(--"dispute-expiry" <days> "Close disputes left open for longer than this")
+            .value_parser(clap::value_parser!(u32)),
+        arg!(--"dispute-expiry-action" <action> "How expired disputes are closed")
+            .value_parser(["resolve", "chargeback"])
+            .default_value("resolve"),
+        arg!(--"authorization-expiry" <days> "Void authorizations neither captured nor voided for longer than this")
+            .value_parser(clap::value_parser!(u32)),
+        arg!(--"dormant-after" <days> "Operational accounts without any activity for longer than this fall dormant")
+            .value_parser(clap::value_parser!(u32)),
+        arg!(--rates <file_path> "Exchange rates csv, with from, to, rate and effective columns"),
+        arg!(--"accounts-config" <file_path> "Accounts settings csv, with client, currency, credit_limit and account_type columns"),
+        arg!(--"known-clients-only" "Skip the transactions of clients missing from the accounts settings and not opened by an open row"),
+        arg!(--"overdrawn-disputes" <action> "What to do with disputes holding more than what is left of the credit line")
+            .value_parser(["hold", "skip"])
+            .default_value("hold"),
+        arg!(--"max-withdrawal" <amount> "Largest single withdrawal")
+            .value_parser(Decimal::from_str),
+        arg!(--"max-daily-withdrawals" <count> "Number of withdrawals per client per day")
+            .value_parser(clap::value_parser!(usize)),
+        arg!(--"max-withdrawn-24h" <amount> "Amount a client can withdraw over 24 hours")
+            .value_parser(Decimal::from_str),
+        arg!(--limits <file_path> "Per client limits csv, overriding the global ones"),
+        arg!(--fees <file_path> "Fee schedule csv, with type, from, flat and rate columns")
+            .requires("house-account"),
+        arg!(--"house-account" <client> "Account fees are credited to, required with --fees")
+            .value_parser(ids::parse::<ClientId>),
+        arg!(--"interest-rate" <rate> "Interest rate on positive balances, posted on accrue rows")
+            .value_parser(Decimal::from_str),
+        arg!(--"interest-at-end" "Also post interest when the run is over")
+            .requires("interest-rate"),
+        arg!(--"interest-first-tx" <tx> "Id of the first interest deposit, the following ones counting up")
+            .value_parser(ids::parse::<TransactionId>)
+            .requires("interest-rate"),
+    ]
+}
+
+fn build_handler<S: AccountsStorage>(
+    matches: &ArgMatches,
+) -> Result<Pipeline<S, ExecuteHandler>, Box<dyn std::error::Error>> {
+    let out_of_order = match matches
+        .get_one::<String>("out-of-order")
+        .map(String::as_str)
+    {
+        Some("accept") => OutOfOrderPolicy::Accept,
+        Some("fail") => OutOfOrderPolicy::Fail,
+        _ => OutOfOrderPolicy::Skip,
+    };
+
+    let disputes = DisputePolicy {
+        window: days(matches, "dispute-window")?,
+        expiry: days(matches, "dispute-expiry")?.map(|after| DisputeExpiry {
+            after,
+            action: match matches
+                .get_one::<String>("dispute-expiry-action")
+                .map(String::as_str)
+            {
+                Some("chargeback") => ExpiryAction::Chargeback,
+                _ => ExpiryAction::Resolve,
+            },
+        }),
+        overdrawn: match matches
+            .get_one::<String>("overdrawn-disputes")
+            .map(String::as_str)
+        {
+            Some("skip") => OverdrawnDisputes::Skip,
+            _ => OverdrawnDisputes::Hold,
+        },
+    };
+
+    let rates = match matches.get_one::<String>("rates") {
+        Some(path) => read_rates(path)?,
+        None => RateTable::default(),
+    };
+
+    let limits = Limits {
+        global: WithdrawalLimits {
+            max_withdrawal: matches.get_one::<Decimal>("max-withdrawal").copied(),
+            max_daily_withdrawals: matches.get_one::<usize>("max-daily-withdrawals").copied(),
+            max_withdrawn_24h: matches.get_one::<Decimal>("max-withdrawn-24h").copied(),
+        },
+        per_client: match matches.get_one::<String>("limits") {
+            Some(path) => read_client_limits(path)?,
+            None => HashMap::new(),
+        },
+    };
+
+    let fees = match matches.get_one::<String>("fees") {
+        Some(path) => {
+            let house_account = *matches
+                .get_one::<ClientId>("house-account")
+                .ok_or_else(|| anyhow::anyhow!("Missing house-account argument"))?;
+            read_fee_schedule(path, house_account)?
+        }
+        None => FeeSchedule::default(),
+    };
+
+    let interest = matches.get_one::<Decimal>("interest-rate").map(|rate| {
+        let mut interest = InterestPolicy::new(*rate);
+        interest.at_end_of_run = matches.get_flag("interest-at-end");
+        if let Some(first) = matches.get_one::<TransactionId>("interest-first-tx") {
+            interest = interest.starting_at(*first);
+        }
+        interest
+    });
+
+    let mut pipeline = Pipeline::new(ExecuteHandler {
+        out_of_order,
+        disputes,
+        rates,
+        limits,
+        fees,
+        interest,
+        authorizations: AuthorizationPolicy {
+            expiry: days(matches, "authorization-expiry")?,
+        },
+        dormant_after: days(matches, "dormant-after")?,
+    });
+    if matches.get_flag("trace") {
+        pipeline.add_stage(LogStage {});
+    }
+    Ok(pipeline)
+}
+
+/// A number of days given to `name`, parsed as a `u32`
+fn days(matches: &ArgMatches, name: &str) -> Result<Option<TimeDelta>, Box<dyn std::error::Error>> {
+    matches
+        .get_one::<u32>(name)
+        .map(|days| {
+            TimeDelta::try_days(i64::from(*days))
+                .ok_or_else(|| anyhow::anyhow!("Too many days for {name} [{days}]").into())
+        })
+        .transpose()
+}
+
+/// Subscribe the sinks asked for. Events sent to a socket are written by the task returned,
+/// to be awaited once the runner is dropped
+async fn subscribe_events_sinks<H, S>(
+    runner: &mut Runner<H, S>,
+    matches: &ArgMatches,
+) -> Result<Option<JoinHandle<()>>, Box<dyn std::error::Error>>
+where
+    H: TransactionHandler<S>,
+    S: AccountsStorage,
+{
+    if let Some(path) = matches.get_one::<String>("events") {
+        runner.subscribe(Box::new(JsonLinesSink::to_file(path)?));
+    }
+    let Some(address) = matches.get_one::<String>("events-socket") else {
+        return Ok(None);
+    };
+    let (sink, writing) = JsonLinesSink::to_socket(address.as_str()).await?;
+    runner.subscribe(Box::new(sink));
+    Ok(Some(writing))
+}
 
+async fn configure_accounts<H, S>(
+    runner: &mut Runner<H, S>,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    H: TransactionHandler<S>,
+    S: AccountsStorage,
+{
+    if let Some(path) = matches.get_one::<String>("accounts-config") {
+        runner
+            .configure_accounts(read_account_config(path)?)
+            .await?;
+    }
+    runner.known_clients_only(matches.get_flag("known-clients-only"));
     Ok(())
 }
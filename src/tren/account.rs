@@ -1,86 +1,275 @@
 // Account representation for transactional state
+use std::collections::BTreeMap;
+
+use std::fmt;
+
 use crate::tren::client::ClientId;
-use crate::tren::transactions::Amount;
+use crate::tren::transactions::{Amount, Currency, Timestamp};
 use rust_decimal::Decimal;
-use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq)]
 pub enum AccountOperationError {
     #[error("Not enough funds for the operation")]
     NotEnoughFunds,
+    #[error("Amount must be positive")]
+    InvalidAmount,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Error, Debug, PartialEq)]
+pub enum AccountStatusError {
+    #[error("Invalid status transition [{0} -> {1}]")]
+    InvalidTransition(AccountStatus, AccountStatus),
+    #[error("Balance must be zero to close the account")]
+    BalanceNotZero,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
 pub enum AccountStatus {
     /// the account is operational
+    #[serde(rename = "operational")]
     Operational,
+    /// deposits are allowed, withdrawals are blocked
+    #[serde(rename = "suspended")]
+    Suspended,
+    /// the account has been inactive for too long, nothing is allowed until it is activated again
+    #[serde(rename = "dormant")]
+    Dormant,
+    /// the account has been closed for good, with a zero balance
+    #[serde(rename = "closed")]
+    Closed,
     /// the account has been frozen and no operation shall be performed on it
+    #[serde(rename = "frozen")]
     Frozen,
 }
 
-#[derive(Clone, Debug)]
-pub struct Account {
-    pub client_id: ClientId,
-    /// amount that the account has on hold until a dispute is resolved
-    pub held_amount: Decimal,
-    /// total amount available for the account to use
-    pub amount: Decimal,
-    pub status: AccountStatus,
+impl AccountStatus {
+    /// Can an account go from this status to `to`? Closed accounts stay closed, only
+    /// operational accounts are suspended or fall dormant, and any other account can be
+    /// activated again, closed or frozen
+    #[must_use]
+    pub fn can_become(&self, to: &AccountStatus) -> bool {
+        match (self, to) {
+            (AccountStatus::Closed, _) => false,
+            (from, to) if from == to => false,
+            (AccountStatus::Operational, AccountStatus::Suspended | AccountStatus::Dormant)
+            | (_, AccountStatus::Operational | AccountStatus::Closed | AccountStatus::Frozen) => {
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
-impl Account {
-    #[must_use]
-    pub fn new(client_id: ClientId) -> Self {
-        Account {
-            client_id,
-            held_amount: dec!(0),
-            amount: dec!(0),
-            status: AccountStatus::Operational,
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountStatus::Operational => write!(f, "operational"),
+            AccountStatus::Suspended => write!(f, "suspended"),
+            AccountStatus::Dormant => write!(f, "dormant"),
+            AccountStatus::Closed => write!(f, "closed"),
+            AccountStatus::Frozen => write!(f, "frozen"),
         }
     }
+}
 
+/// Funds of an account in a single currency
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Balance {
+    /// total amount available for the account to use
+    pub available: Decimal,
+    /// amount that the account has on hold until a dispute is resolved
+    pub held: Decimal,
+    /// amount reserved by card authorizations until they are captured or voided
+    pub reserved: Decimal,
+    /// how far below zero withdrawals may bring the available amount
+    pub credit_limit: Decimal,
+}
+
+// what accounts that never moved any money own
+static NO_BALANCE: Balance = Balance {
+    available: Decimal::ZERO,
+    held: Decimal::ZERO,
+    reserved: Decimal::ZERO,
+    credit_limit: Decimal::ZERO,
+};
+
+impl Balance {
     /// Deposit funds
     pub fn deposit(&mut self, amount: Amount) {
-        self.amount += amount;
+        self.available += amount;
     }
 
-    /// Withdraw funds
+    /// Withdraw funds, possibly drawing down the credit line
     ///
     /// # Errors
     ///
-    /// Return error if amount to withdraw is incompatible with current balance
+    /// Return error if amount to withdraw is incompatible with current balance and credit limit
     pub fn withdraw(&mut self, amount: Amount) -> Result<(), AccountOperationError> {
-        if self.amount < amount {
+        if self.available + self.credit_limit < amount {
             return Err(AccountOperationError::NotEnoughFunds);
         }
-        self.amount -= amount;
+        self.available -= amount;
         Ok(())
     }
 
-    /// Total of available amount plus the amount on hold for disputes
+    /// Charge a fee: unlike withdrawals, it is taken even if it brings the available amount below zero
+    pub fn charge(&mut self, amount: Amount) {
+        self.available -= amount;
+    }
+
+    /// is the available amount below zero, because of the credit line or of disputes?
+    #[must_use]
+    pub fn overdrawn(&self) -> bool {
+        self.available < Decimal::ZERO
+    }
+
+    /// Would holding `amount` bring the available amount beyond the credit limit?
+    #[must_use]
+    pub fn hold_exceeds_credit(&self, amount: Amount) -> bool {
+        self.available - amount < -self.credit_limit
+    }
+
+    /// Total of available amount plus the amounts on hold for disputes and reserved by
+    /// authorizations
     #[must_use]
     pub fn total(&self) -> Decimal {
-        self.held_amount + self.amount
+        self.held + self.reserved + self.available
     }
 
     /// Hold some funds. This means the available amount will be reduced by held amount
     /// Note that funds could become negative this way, since we could be e.g. disputing a transaction
     /// happened before withrawn events
     pub fn hold(&mut self, amount_to_hold: Amount) {
-        self.amount -= amount_to_hold;
-        self.held_amount += amount_to_hold;
+        self.available -= amount_to_hold;
+        self.held += amount_to_hold;
     }
 
     /// Release some held funds. This means the available amount will be augmented by held amount
     pub fn release(&mut self, amount_to_release: Amount) {
-        self.amount += amount_to_release;
-        self.held_amount -= amount_to_release;
+        self.available += amount_to_release;
+        self.held -= amount_to_release;
     }
 
     /// Forget some held funds. This means the total available amount will decrease
     pub fn chargeback(&mut self, amount_to_chargeback: Amount) {
-        self.held_amount -= amount_to_chargeback;
+        self.held -= amount_to_chargeback;
+    }
+
+    /// Reserve funds for an authorization, possibly drawing down the credit line like a withdrawal
+    ///
+    /// # Errors
+    ///
+    /// Return error if amount to reserve is not positive, or is incompatible with current balance
+    /// and credit limit
+    pub fn reserve(&mut self, amount_to_reserve: Amount) -> Result<(), AccountOperationError> {
+        if amount_to_reserve <= Decimal::ZERO {
+            return Err(AccountOperationError::InvalidAmount);
+        }
+        self.withdraw(amount_to_reserve)?;
+        self.reserved += amount_to_reserve;
+        Ok(())
+    }
+
+    /// Release reserved funds back to the available amount
+    pub fn unreserve(&mut self, amount_to_unreserve: Amount) {
+        self.available += amount_to_unreserve;
+        self.reserved -= amount_to_unreserve;
+    }
+
+    /// Settle reserved funds. This means the total amount will decrease
+    pub fn capture(&mut self, amount_to_capture: Amount) {
+        self.reserved -= amount_to_capture;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Account {
+    pub client_id: ClientId,
+    /// funds per currency. Transactions without a currency move the `None` balance
+    pub balances: BTreeMap<Option<Currency>, Balance>,
+    pub status: AccountStatus,
+    /// time of the latest transaction seen for this account, if transactions have timestamps
+    pub last_activity: Option<Timestamp>,
+}
+
+impl Account {
+    #[must_use]
+    pub fn new(client_id: ClientId) -> Self {
+        Account {
+            client_id,
+            balances: BTreeMap::new(),
+            status: AccountStatus::Operational,
+            last_activity: None,
+        }
+    }
+
+    /// Funds in `currency`, zero if the account never had any
+    #[must_use]
+    pub fn balance(&self, currency: Option<&Currency>) -> Balance {
+        self.balances
+            .get(&currency.cloned())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Funds in `currency`, to operate on them
+    pub fn balance_mut(&mut self, currency: Option<&Currency>) -> &mut Balance {
+        self.balances.entry(currency.cloned()).or_default()
+    }
+
+    /// All the balances, ordered by currency. An account that never had any funds
+    /// still has a zero balance without currency, so that it shows up in outputs
+    #[must_use]
+    pub fn per_currency(&self) -> Vec<(Option<&Currency>, &Balance)> {
+        if self.balances.is_empty() {
+            return vec![(None, &NO_BALANCE)];
+        }
+        self.balances
+            .iter()
+            .map(|(currency, balance)| (currency.as_ref(), balance))
+            .collect()
+    }
+
+    /// does the account hold funds in some explicit currency?
+    #[must_use]
+    pub fn has_currencies(&self) -> bool {
+        self.balances.keys().any(Option::is_some)
+    }
+
+    /// are some funds reserved by authorizations?
+    #[must_use]
+    pub fn has_reserved(&self) -> bool {
+        self.balances
+            .values()
+            .any(|balance| !balance.reserved.is_zero())
+    }
+
+    /// Move the account to `to`, following the rules of `AccountStatus::can_become`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the transition is not allowed, or if closing an account
+    /// with funds in any balance
+    pub fn transition(&mut self, to: AccountStatus) -> Result<(), AccountStatusError> {
+        if !self.status.can_become(&to) {
+            return Err(AccountStatusError::InvalidTransition(
+                self.status.clone(),
+                to,
+            ));
+        }
+        if to == AccountStatus::Closed
+            && self.balances.values().any(|balance| {
+                !(balance.available.is_zero()
+                    && balance.held.is_zero()
+                    && balance.reserved.is_zero())
+            })
+        {
+            return Err(AccountStatusError::BalanceNotZero);
+        }
+        self.status = to;
+        Ok(())
     }
 
     /// set the status of an account to `Frozen`
@@ -103,26 +292,26 @@ impl Account {
 #[cfg(test)]
 mod test {
     use super::*;
+    use rust_decimal_macros::dec;
 
     #[test]
     fn holding_funds_shall_keep_total_unchanged_test() {
         // with
         let total = dec!(100);
 
-        let mut account = Account {
-            client_id: 12,
-            held_amount: dec!(0),
-            amount: total,
-            status: AccountStatus::Operational,
+        let mut balance = Balance {
+            available: total,
+            held: dec!(0),
+            ..Default::default()
         };
 
-        assert_eq!(account.total(), total);
+        assert_eq!(balance.total(), total);
 
         // when
-        account.hold(dec!(51));
+        balance.hold(dec!(51));
 
         // then
-        assert_eq!(account.total(), total);
+        assert_eq!(balance.total(), total);
     }
 
     #[test]
@@ -130,19 +319,18 @@ mod test {
         // with
         let amount_to_hold = dec!(51);
 
-        let mut account = Account {
-            client_id: 12,
-            held_amount: dec!(0),
-            amount: dec!(100),
-            status: AccountStatus::Operational,
+        let mut balance = Balance {
+            available: dec!(100),
+            held: dec!(0),
+            ..Default::default()
         };
 
         // when
-        account.hold(amount_to_hold);
+        balance.hold(amount_to_hold);
 
         // then
-        assert_eq!(account.held_amount, amount_to_hold);
-        assert_eq!(account.amount, dec!(49));
+        assert_eq!(balance.held, amount_to_hold);
+        assert_eq!(balance.available, dec!(49));
     }
 
     #[test]
@@ -152,36 +340,153 @@ mod test {
         let initial_amount = dec!(100);
         let amount_to_withdraw = dec!(149);
 
-        let mut account = Account {
-            client_id: 12,
-            held_amount: dec!(0),
-            amount: initial_amount,
-            status: AccountStatus::Operational,
+        let mut balance = Balance {
+            available: initial_amount,
+            held: dec!(0),
+            ..Default::default()
         };
 
         // when
-        account.deposit(amount_to_deposit);
+        balance.deposit(amount_to_deposit);
 
         // then
-        assert_eq!(account.amount, initial_amount + amount_to_deposit);
+        assert_eq!(balance.available, initial_amount + amount_to_deposit);
 
         // when
-        account
+        balance
             .withdraw(amount_to_withdraw)
             .expect("Expect to be withdrawable");
 
         // then
         assert_eq!(
-            account.amount,
+            balance.available,
             initial_amount + amount_to_deposit - amount_to_withdraw
         );
 
         // when
         let too_much_to_withdraw = dec!(100000);
-        let prev_total = account.total();
-        assert!(account.withdraw(too_much_to_withdraw).is_err());
+        let prev_total = balance.total();
+        assert!(balance.withdraw(too_much_to_withdraw).is_err());
 
         // then
-        assert_eq!(account.total(), prev_total);
+        assert_eq!(balance.total(), prev_total);
+    }
+
+    #[test]
+    fn credit_limit_test() {
+        // with
+        let mut balance = Balance {
+            available: dec!(10),
+            credit_limit: dec!(50),
+            ..Default::default()
+        };
+
+        // when
+        balance
+            .withdraw(dec!(40))
+            .expect("Expect to be withdrawable");
+
+        // then
+        assert!(balance.overdrawn());
+        assert_eq!(balance.available, dec!(-30));
+        assert!(balance.withdraw(dec!(20.01)).is_err());
+        assert!(balance.hold_exceeds_credit(dec!(20.01)));
+        assert!(!balance.hold_exceeds_credit(dec!(20)));
+    }
+
+    #[test]
+    fn reserving_funds_test() {
+        // with
+        let mut balance = Balance {
+            available: dec!(100),
+            ..Default::default()
+        };
+
+        // when
+        balance.reserve(dec!(60)).expect("Expect to be reservable");
+
+        // then
+        assert_eq!(balance.available, dec!(40));
+        assert_eq!(balance.reserved, dec!(60));
+        assert_eq!(balance.total(), dec!(100));
+        assert!(balance.reserve(dec!(40.01)).is_err());
+        assert_eq!(
+            balance.reserve(dec!(-10)),
+            Err(AccountOperationError::InvalidAmount)
+        );
+        assert_eq!(balance.available, dec!(40));
+
+        // when
+        balance.capture(dec!(50));
+        balance.unreserve(dec!(10));
+
+        // then
+        assert_eq!(balance.available, dec!(50));
+        assert_eq!(balance.reserved, dec!(0));
+        assert_eq!(balance.total(), dec!(50));
+    }
+
+    #[test]
+    fn status_transitions_test() {
+        // with
+        let mut account = Account::new(12);
+        account.balance_mut(None).deposit(dec!(10));
+
+        // when/then
+        assert_eq!(account.transition(AccountStatus::Suspended), Ok(()));
+        assert_eq!(
+            account.transition(AccountStatus::Dormant),
+            Err(AccountStatusError::InvalidTransition(
+                AccountStatus::Suspended,
+                AccountStatus::Dormant
+            ))
+        );
+        assert_eq!(
+            account.transition(AccountStatus::Closed),
+            Err(AccountStatusError::BalanceNotZero)
+        );
+
+        // when
+        account
+            .balance_mut(None)
+            .withdraw(dec!(10))
+            .expect("Expect to be withdrawable");
+
+        // then
+        assert_eq!(account.transition(AccountStatus::Closed), Ok(()));
+        assert!(account.transition(AccountStatus::Operational).is_err());
+        assert_eq!(account.status.to_string(), "closed");
+    }
+
+    #[test]
+    fn balances_per_currency_test() {
+        // with
+        let eur = Currency::from("EUR");
+        let usd = Currency::from("USD");
+        let mut account = Account::new(12);
+
+        // then
+        assert_eq!(account.per_currency(), vec![(None, &Balance::default())]);
+        assert!(!account.has_currencies());
+
+        // when
+        account.balance_mut(Some(&usd)).deposit(dec!(10));
+        account.balance_mut(Some(&eur)).deposit(dec!(5));
+        account.balance_mut(Some(&eur)).hold(dec!(2));
+
+        // then
+        assert!(account.has_currencies());
+        assert_eq!(account.balance(None), Balance::default());
+        assert_eq!(account.balance(Some(&eur)).available, dec!(3));
+        assert_eq!(account.balance(Some(&eur)).total(), dec!(5));
+        assert_eq!(account.balance(Some(&usd)).total(), dec!(10));
+        assert_eq!(
+            account
+                .per_currency()
+                .iter()
+                .map(|(currency, _)| *currency)
+                .collect::<Vec<Option<&Currency>>>(),
+            vec![Some(&eur), Some(&usd)]
+        );
     }
 }
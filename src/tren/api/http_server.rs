@@ -0,0 +1,312 @@
+// Embedded HTTP server to query balances and submit transactions to a long-lived engine
+// The runner is shared behind an async mutex: transactions are applied one at a time, exactly
+// like the csv loop does, so handlers do not need to care about concurrency
+
+use std::{future::Future, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::{net::TcpListener, sync::Mutex};
+
+use super::views::{
+    AccountView, AccountsPage, ErrorView, OutcomeView, Submission, SubmissionResult,
+};
+use crate::tren::{
+    account::Account,
+    client::ClientId,
+    engine::runner::{Runner, RunnerError, RunnerOutcome},
+    handlers::transaction_handler::TransactionHandler,
+    ids,
+    storage::store::AccountsStorage,
+    transactions::Transaction,
+};
+
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("Account [{}] not found", ids::display(*.0))]
+    AccountNotFound(ClientId),
+    #[error("Invalid client [{0}]")]
+    InvalidClient(String),
+    #[error("Invalid transaction [{0}]")]
+    InvalidTransaction(String),
+    #[error("Storage encountered an error")]
+    StorageError,
+    #[error("Engine failure [{0}]")]
+    EngineFailure(String),
+}
+
+impl From<RunnerError> for ApiError {
+    fn from(error: RunnerError) -> Self {
+        match error {
+            RunnerError::InvalidRow(reason) => ApiError::InvalidTransaction(reason),
+            RunnerError::StorageError => ApiError::StorageError,
+            other => ApiError::EngineFailure(other.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            ApiError::AccountNotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::InvalidClient(_) | ApiError::InvalidTransaction(_) => StatusCode::BAD_REQUEST,
+            ApiError::StorageError | ApiError::EngineFailure(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        (
+            status,
+            Json(ErrorView {
+                error: self.to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+/// State shared by all the routes
+pub struct ApiState<H, S>
+where
+    H: TransactionHandler<S>,
+    S: AccountsStorage,
+{
+    runner: Arc<Mutex<Runner<H, S>>>,
+}
+
+// not derived, since derive would require H and S to be Clone as well
+impl<H, S> Clone for ApiState<H, S>
+where
+    H: TransactionHandler<S>,
+    S: AccountsStorage,
+{
+    fn clone(&self) -> Self {
+        ApiState {
+            runner: Arc::clone(&self.runner),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Pagination {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// Build the API routes around a runner
+pub fn router<H, S>(runner: Runner<H, S>) -> Router
+where
+    H: TransactionHandler<S> + Send + 'static,
+    S: AccountsStorage + Send + 'static,
+{
+    routes(Arc::new(Mutex::new(runner)))
+}
+
+fn routes<H, S>(runner: Arc<Mutex<Runner<H, S>>>) -> Router
+where
+    H: TransactionHandler<S> + Send + 'static,
+    S: AccountsStorage + Send + 'static,
+{
+    let state = ApiState { runner };
+
+    Router::new()
+        .route("/transactions", post(submit_transactions::<H, S>))
+        .route("/accounts", get(list_accounts::<H, S>))
+        .route("/accounts/{client}", get(get_account::<H, S>))
+        .route(
+            "/accounts/{client}/transactions",
+            get(get_account_transactions::<H, S>),
+        )
+        .with_state(state)
+}
+
+/// Serve the API on an already bound listener until the process is stopped
+///
+/// # Errors
+///
+/// Returns an error if the underlying server fails
+pub async fn serve<H, S>(listener: TcpListener, runner: Runner<H, S>) -> std::io::Result<()>
+where
+    H: TransactionHandler<S> + Send + 'static,
+    S: AccountsStorage + Send + 'static,
+{
+    axum::serve(listener, router(runner)).await
+}
+
+/// Serve the API on an already bound listener until `shutdown` completes, then sync the
+/// storage of the runner so that everything committed is durable
+///
+/// # Errors
+///
+/// Returns an error if the underlying server fails or the storage can't be synced
+pub async fn serve_until<H, S>(
+    listener: TcpListener,
+    runner: Runner<H, S>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()>
+where
+    H: TransactionHandler<S> + Send + 'static,
+    S: AccountsStorage + Send + 'static,
+{
+    let runner = Arc::new(Mutex::new(runner));
+    axum::serve(listener, routes(Arc::clone(&runner)))
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    let mut runner = runner.lock().await;
+    runner.sync().await.map_err(std::io::Error::other)
+}
+
+async fn submit_transactions<H, S>(
+    State(state): State<ApiState<H, S>>,
+    Json(submission): Json<Submission>,
+) -> Result<Response, ApiError>
+where
+    H: TransactionHandler<S> + Send + 'static,
+    S: AccountsStorage + Send + 'static,
+{
+    let mut runner = state.runner.lock().await;
+
+    match submission {
+        Submission::One(transaction) => {
+            let result = apply(&mut runner, transaction).await?;
+            Ok(Json(result).into_response())
+        }
+        Submission::Batch(transactions) => {
+            // validate the whole batch upfront, so that a bad row does not leave it half applied
+            let transactions = transactions
+                .into_iter()
+                .map(|transaction| {
+                    transaction
+                        .validate()
+                        .map_err(|err| ApiError::InvalidTransaction(err.to_string()))
+                })
+                .collect::<Result<Vec<Transaction>, ApiError>>()?;
+
+            // not atomic past validation: each transaction is its own unit of work, a storage
+            // failure fails the request but the transactions before it stay applied
+            let mut results = Vec::with_capacity(transactions.len());
+            for transaction in transactions {
+                results.push(apply(&mut runner, transaction).await?);
+            }
+            Ok(Json(results).into_response())
+        }
+    }
+}
+
+async fn apply<H, S>(
+    runner: &mut Runner<H, S>,
+    transaction: Transaction,
+) -> Result<SubmissionResult, ApiError>
+where
+    H: TransactionHandler<S>,
+    S: AccountsStorage,
+{
+    let client = transaction.client_id;
+    let tx = transaction.transaction_id;
+    let outcome = runner.run_transaction(transaction).await?;
+
+    Ok(SubmissionResult {
+        client,
+        tx,
+        outcome: OutcomeView::from(&outcome),
+        reason: match outcome {
+            RunnerOutcome::Skipped(reason) => Some(reason),
+            RunnerOutcome::Success => None,
+        },
+    })
+}
+
+async fn list_accounts<H, S>(
+    State(state): State<ApiState<H, S>>,
+    Query(pagination): Query<Pagination>,
+) -> Json<AccountsPage>
+where
+    H: TransactionHandler<S> + Send + 'static,
+    S: AccountsStorage + Send + 'static,
+{
+    let offset = pagination.offset.unwrap_or(0);
+    let limit = pagination
+        .limit
+        .unwrap_or(DEFAULT_PAGE_LIMIT)
+        .min(MAX_PAGE_LIMIT);
+
+    let runner = state.runner.lock().await;
+    let store = runner.accounts_store();
+
+    // storage iteration order is not guaranteed, sort to get stable pages
+    let mut accounts = store.all_accounts_iter().collect::<Vec<&Account>>();
+    accounts.sort_by_key(|account| account.client_id);
+
+    Json(AccountsPage {
+        accounts: accounts
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(AccountView::from)
+            .collect(),
+        offset,
+        limit,
+        total: store.count_accounts(),
+    })
+}
+
+// ids in paths are in their textual form, e.g. UUIDs
+fn parse_client(text: &str) -> Result<ClientId, ApiError> {
+    ids::parse(text).map_err(|_| ApiError::InvalidClient(text.to_string()))
+}
+
+async fn get_account<H, S>(
+    State(state): State<ApiState<H, S>>,
+    Path(client): Path<String>,
+) -> Result<Json<AccountView>, ApiError>
+where
+    H: TransactionHandler<S> + Send + 'static,
+    S: AccountsStorage + Send + 'static,
+{
+    let client = parse_client(&client)?;
+    let runner = state.runner.lock().await;
+
+    runner
+        .accounts_store()
+        .get(client)
+        .map_err(|_| ApiError::StorageError)?
+        .map(|account| Json(AccountView::from(account)))
+        .ok_or(ApiError::AccountNotFound(client))
+}
+
+// as kept by the storage: with a bounded history only the transactions still in memory, without
+// dispute, resolve and chargeback rows
+async fn get_account_transactions<H, S>(
+    State(state): State<ApiState<H, S>>,
+    Path(client): Path<String>,
+) -> Result<Json<Vec<Transaction>>, ApiError>
+where
+    H: TransactionHandler<S> + Send + 'static,
+    S: AccountsStorage + Send + 'static,
+{
+    let client = parse_client(&client)?;
+    let runner = state.runner.lock().await;
+    let store = runner.accounts_store();
+
+    if store
+        .get(client)
+        .map_err(|_| ApiError::StorageError)?
+        .is_none()
+    {
+        return Err(ApiError::AccountNotFound(client));
+    }
+
+    Ok(Json(
+        store.get_transactions(client).cloned().unwrap_or_default(),
+    ))
+}
@@ -0,0 +1,119 @@
+// JSON representations exposed by the HTTP API
+// kept separate from the domain types so that the wire format does not leak into the engine
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::tren::{
+    account::{Account, AccountStatus},
+    client::ClientId,
+    engine::runner::{RunnerOutcome, SkipReason},
+    ids,
+    transactions::{Currency, Transaction, TransactionId},
+};
+
+/// Balances of a single account, rounded like the CSV output.
+/// Top level balances are the ones moved by transactions without a currency
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct AccountView {
+    #[serde(with = "ids")]
+    pub client: ClientId,
+    pub available: Decimal,
+    pub held: Decimal,
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    pub reserved: Decimal,
+    pub total: Decimal,
+    pub status: AccountStatus,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub currencies: Vec<CurrencyBalanceView>,
+}
+
+/// Balances of an account in a currency
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct CurrencyBalanceView {
+    pub currency: Currency,
+    pub available: Decimal,
+    pub held: Decimal,
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    pub reserved: Decimal,
+    pub total: Decimal,
+}
+
+impl From<&Account> for AccountView {
+    fn from(account: &Account) -> Self {
+        let balance = account.balance(None);
+        AccountView {
+            client: account.client_id,
+            available: balance.available.round_dp(4),
+            held: balance.held.round_dp(4),
+            reserved: balance.reserved.round_dp(4),
+            total: balance.total().round_dp(4),
+            status: account.status.clone(),
+            currencies: account
+                .per_currency()
+                .into_iter()
+                .filter_map(|(currency, balance)| {
+                    Some(CurrencyBalanceView {
+                        currency: currency?.clone(),
+                        available: balance.available.round_dp(4),
+                        held: balance.held.round_dp(4),
+                        reserved: balance.reserved.round_dp(4),
+                        total: balance.total().round_dp(4),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A page of accounts, ordered by client id
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AccountsPage {
+    pub accounts: Vec<AccountView>,
+    pub offset: usize,
+    pub limit: usize,
+    /// total number of accounts in the store, for clients to know when to stop paging
+    pub total: usize,
+}
+
+/// Body of `POST /transactions`: either a single transaction or a batch
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Submission {
+    One(Transaction),
+    Batch(Vec<Transaction>),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum OutcomeView {
+    #[serde(rename = "success")]
+    Success,
+    #[serde(rename = "skipped")]
+    Skipped,
+}
+
+impl From<&RunnerOutcome> for OutcomeView {
+    fn from(outcome: &RunnerOutcome) -> Self {
+        match outcome {
+            RunnerOutcome::Success => OutcomeView::Success,
+            RunnerOutcome::Skipped(_) => OutcomeView::Skipped,
+        }
+    }
+}
+
+/// Outcome of a submitted transaction
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SubmissionResult {
+    #[serde(with = "ids")]
+    pub client: ClientId,
+    #[serde(with = "ids")]
+    pub tx: TransactionId,
+    pub outcome: OutcomeView,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<SkipReason>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorView {
+    pub error: String,
+}
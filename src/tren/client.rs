@@ -1,3 +1,90 @@
 // Client information
 
+use std::collections::BTreeMap;
+
+/// 16 bit by default, see `ids` for wider representations
+#[cfg(not(any(feature = "wide-ids", feature = "uuid-ids")))]
 pub type ClientId = u16;
+#[cfg(all(feature = "wide-ids", not(feature = "uuid-ids")))]
+pub type ClientId = u64;
+#[cfg(feature = "uuid-ids")]
+pub type ClientId = u128;
+
+/// What is known about a client before processing, e.g. from the accounts settings
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ClientProfile {
+    /// free label, e.g. `retail` or `business`, for handlers and stages to apply their rules
+    pub account_type: Option<String>,
+}
+
+/// The clients known before processing, with their profiles
+#[derive(Debug, Default, Clone)]
+pub struct ClientDirectory {
+    /// refuse the transactions of clients that are not in the directory, instead of opening
+    /// accounts for them
+    pub known_clients_only: bool,
+    profiles: BTreeMap<ClientId, ClientProfile>,
+}
+
+impl ClientDirectory {
+    #[must_use]
+    pub const fn new() -> Self {
+        ClientDirectory {
+            known_clients_only: false,
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    /// Add a client, or update what is known about it. Missing settings are left as they are
+    pub fn register(&mut self, client: ClientId, profile: ClientProfile) {
+        let known = self.profiles.entry(client).or_default();
+        if profile.account_type.is_some() {
+            known.account_type = profile.account_type;
+        }
+    }
+
+    #[must_use]
+    pub fn profile(&self, client: ClientId) -> Option<&ClientProfile> {
+        self.profiles.get(&client)
+    }
+
+    /// May transactions of `client` be processed?
+    #[must_use]
+    pub fn admits(&self, client: ClientId) -> bool {
+        !self.known_clients_only || self.profiles.contains_key(&client)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn known_clients_only_test() {
+        // with
+        let mut directory = ClientDirectory::new();
+        directory.register(
+            1,
+            ClientProfile {
+                account_type: Some(String::from("business")),
+            },
+        );
+        directory.register(1, ClientProfile::default());
+
+        // then
+        assert!(directory.admits(2));
+        assert_eq!(
+            directory
+                .profile(1)
+                .and_then(|profile| profile.account_type.as_deref()),
+            Some("business")
+        );
+
+        // when
+        directory.known_clients_only = true;
+
+        // then
+        assert!(directory.admits(1));
+        assert!(!directory.admits(2));
+    }
+}
@@ -0,0 +1,320 @@
+// Differences between two states of the accounts, e.g. before and after a dry run
+
+use std::collections::BTreeMap;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::tren::{
+    account::{Account, AccountStatus},
+    client::ClientId,
+    ids,
+    transactions::Currency,
+};
+
+/// Balances of an account in a currency at some point in time, rounded like the output
+/// field names match the csv output, so that it can be read back
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct AccountSnapshot {
+    #[serde(with = "ids")]
+    pub client: ClientId,
+    /// missing in outputs without a currency column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    pub available: Decimal,
+    pub held: Decimal,
+    /// missing in outputs without a reserved column
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    pub reserved: Decimal,
+    pub total: Decimal,
+    /// outputs older than account statuses have a `locked` column instead, read as frozen when
+    /// true and operational otherwise
+    #[serde(alias = "locked", deserialize_with = "status_or_locked")]
+    pub status: AccountStatus,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StatusOrLocked {
+    Status(AccountStatus),
+    Locked(bool),
+}
+
+fn status_or_locked<'de, D>(deserializer: D) -> Result<AccountStatus, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match StatusOrLocked::deserialize(deserializer)? {
+        StatusOrLocked::Status(status) => status,
+        StatusOrLocked::Locked(true) => AccountStatus::Frozen,
+        StatusOrLocked::Locked(false) => AccountStatus::Operational,
+    })
+}
+
+impl AccountSnapshot {
+    /// One snapshot per currency of the account, like the rows of the output
+    #[must_use]
+    pub fn of(account: &Account) -> Vec<Self> {
+        account
+            .per_currency()
+            .into_iter()
+            .map(|(currency, balance)| AccountSnapshot {
+                client: account.client_id,
+                currency: currency.cloned(),
+                available: balance.available.round_dp(4),
+                held: balance.held.round_dp(4),
+                reserved: balance.reserved.round_dp(4),
+                total: balance.total().round_dp(4),
+                status: account.status.clone(),
+            })
+            .collect()
+    }
+}
+
+/// State of a single account balance on both sides. A missing side means the balance
+/// has been added (no `before`) or removed (no `after`)
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct AccountDiff {
+    #[serde(with = "ids")]
+    pub client: ClientId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    pub before: Option<AccountSnapshot>,
+    pub after: Option<AccountSnapshot>,
+}
+
+impl AccountDiff {
+    fn empty(client: ClientId, currency: Option<Currency>) -> Self {
+        AccountDiff {
+            client,
+            currency,
+            before: None,
+            after: None,
+        }
+    }
+
+    /// Is there any difference at all between the two sides?
+    #[must_use]
+    pub fn is_change(&self) -> bool {
+        self.before != self.after
+    }
+
+    #[must_use]
+    pub fn available_delta(&self) -> Decimal {
+        self.delta(|snapshot| snapshot.available)
+    }
+
+    #[must_use]
+    pub fn held_delta(&self) -> Decimal {
+        self.delta(|snapshot| snapshot.held)
+    }
+
+    #[must_use]
+    pub fn reserved_delta(&self) -> Decimal {
+        self.delta(|snapshot| snapshot.reserved)
+    }
+
+    #[must_use]
+    pub fn total_delta(&self) -> Decimal {
+        self.delta(|snapshot| snapshot.total)
+    }
+
+    /// Is the difference beyond `tolerance`? Accounts appearing, disappearing or being
+    /// changing status always are
+    #[must_use]
+    pub fn exceeds(&self, tolerance: Decimal) -> bool {
+        self.before.is_none()
+            || self.after.is_none()
+            || self.status_changed()
+            || [
+                self.available_delta(),
+                self.held_delta(),
+                self.reserved_delta(),
+                self.total_delta(),
+            ]
+            .iter()
+            .any(|delta| delta.abs() > tolerance)
+    }
+
+    /// the account has changed status, e.g. it has been frozen
+    #[must_use]
+    pub fn status_changed(&self) -> bool {
+        match (&self.before, &self.after) {
+            (Some(before), Some(after)) => before.status != after.status,
+            _ => false,
+        }
+    }
+
+    // a missing side counts as zero
+    fn delta(&self, field: impl Fn(&AccountSnapshot) -> Decimal) -> Decimal {
+        self.after.as_ref().map(&field).unwrap_or_default()
+            - self.before.as_ref().map(&field).unwrap_or_default()
+    }
+}
+
+/// All the account balances that changed, ordered by client id and currency
+#[derive(Debug, Default)]
+pub struct BalanceDiff {
+    pub changes: Vec<AccountDiff>,
+}
+
+impl BalanceDiff {
+    /// Compare two sets of balances, e.g. outputs of two runs
+    pub fn compare(
+        before: impl IntoIterator<Item = AccountSnapshot>,
+        after: impl IntoIterator<Item = AccountSnapshot>,
+    ) -> Self {
+        let mut sides: BTreeMap<(ClientId, Option<Currency>), AccountDiff> = BTreeMap::new();
+
+        for snapshot in before {
+            let key = (snapshot.client, snapshot.currency.clone());
+            sides
+                .entry(key.clone())
+                .or_insert_with(|| AccountDiff::empty(key.0, key.1))
+                .before = Some(snapshot);
+        }
+        for snapshot in after {
+            let key = (snapshot.client, snapshot.currency.clone());
+            sides
+                .entry(key.clone())
+                .or_insert_with(|| AccountDiff::empty(key.0, key.1))
+                .after = Some(snapshot);
+        }
+
+        BalanceDiff {
+            changes: sides.into_values().filter(AccountDiff::is_change).collect(),
+        }
+    }
+
+    /// Is any of the changes beyond `tolerance`?
+    #[must_use]
+    pub fn exceeds(&self, tolerance: Decimal) -> bool {
+        self.changes.iter().any(|change| change.exceeds(tolerance))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn snapshot(available: Decimal, status: AccountStatus) -> AccountSnapshot {
+        AccountSnapshot {
+            client: 1,
+            currency: None,
+            available,
+            held: dec!(0),
+            reserved: dec!(0),
+            total: available,
+            status,
+        }
+    }
+
+    #[test]
+    fn deltas_test() {
+        let changed = AccountDiff {
+            client: 1,
+            currency: None,
+            before: Some(snapshot(dec!(10), AccountStatus::Operational)),
+            after: Some(snapshot(dec!(7.5), AccountStatus::Frozen)),
+        };
+        assert!(changed.is_change());
+        assert!(changed.status_changed());
+        assert_eq!(changed.available_delta(), dec!(-2.5));
+        assert_eq!(changed.held_delta(), dec!(0));
+
+        let removed = AccountDiff {
+            client: 1,
+            currency: None,
+            before: Some(snapshot(dec!(10), AccountStatus::Frozen)),
+            after: None,
+        };
+        assert!(!removed.status_changed());
+        assert_eq!(removed.total_delta(), dec!(-10));
+
+        let unchanged = AccountDiff {
+            client: 1,
+            currency: None,
+            before: Some(snapshot(dec!(10), AccountStatus::Operational)),
+            after: Some(snapshot(dec!(10), AccountStatus::Operational)),
+        };
+        assert!(!unchanged.is_change());
+    }
+
+    #[test]
+    fn compare_test() {
+        // with
+        let before = vec![
+            AccountSnapshot {
+                client: 2,
+                ..snapshot(dec!(10), AccountStatus::Operational)
+            },
+            AccountSnapshot {
+                client: 3,
+                ..snapshot(dec!(10), AccountStatus::Operational)
+            },
+            AccountSnapshot {
+                client: 1,
+                ..snapshot(dec!(10), AccountStatus::Operational)
+            },
+        ];
+        let after = vec![
+            AccountSnapshot {
+                client: 1,
+                ..snapshot(dec!(10.0001), AccountStatus::Operational)
+            },
+            AccountSnapshot {
+                client: 3,
+                ..snapshot(dec!(10), AccountStatus::Operational)
+            },
+            AccountSnapshot {
+                client: 4,
+                ..snapshot(dec!(1), AccountStatus::Operational)
+            },
+        ];
+
+        // when
+        let diff = BalanceDiff::compare(before, after);
+
+        // then: 1 changed, 2 removed, 4 added
+        assert_eq!(
+            diff.changes
+                .iter()
+                .map(|change| change.client)
+                .collect::<Vec<ClientId>>(),
+            vec![1, 2, 4]
+        );
+        assert!(!diff.changes[0].exceeds(dec!(0.001)));
+        assert!(diff.changes[0].exceeds(dec!(0)));
+        assert!(diff.changes[1].exceeds(dec!(1000)));
+        assert!(diff.exceeds(dec!(0.001)));
+    }
+
+    #[test]
+    fn compare_per_currency_test() {
+        // with
+        let before = vec![AccountSnapshot {
+            currency: Some(Currency::from("EUR")),
+            ..snapshot(dec!(10), AccountStatus::Operational)
+        }];
+        let after = vec![
+            AccountSnapshot {
+                currency: Some(Currency::from("EUR")),
+                ..snapshot(dec!(10), AccountStatus::Operational)
+            },
+            AccountSnapshot {
+                currency: Some(Currency::from("USD")),
+                ..snapshot(dec!(3), AccountStatus::Operational)
+            },
+        ];
+
+        // when
+        let diff = BalanceDiff::compare(before, after);
+
+        // then: only the new USD balance changed
+        assert_eq!(diff.changes.len(), 1);
+        assert_eq!(diff.changes[0].currency, Some(Currency::from("USD")));
+        assert!(diff.changes[0].before.is_none());
+        assert_eq!(diff.changes[0].total_delta(), dec!(3));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod balance_diff;
+pub mod snapshot_reader;
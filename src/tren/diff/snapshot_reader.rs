@@ -0,0 +1,66 @@
+// Read back balances printed by tren, e.g. yesterday's output, to compare them
+
+use super::balance_diff::AccountSnapshot;
+use crate::tren::inputs::csv_reader::{read_csv, CsvReaderError};
+
+/// Read an accounts csv, as printed by `CsvPrinter`
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or a row is not a valid account
+pub fn read_snapshots(path: &str) -> Result<Vec<AccountSnapshot>, CsvReaderError> {
+    read_csv(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::{account::AccountStatus, client::ClientId, diff::balance_diff::BalanceDiff};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn read_output_test() {
+        let snapshots =
+            read_snapshots("src/tests/accounts_before.csv").expect("Expected valid snapshots");
+
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(
+            snapshots[0],
+            AccountSnapshot {
+                client: 1,
+                currency: None,
+                available: dec!(1.5),
+                held: dec!(0),
+                reserved: dec!(0),
+                total: dec!(1.5),
+                status: AccountStatus::Operational,
+            }
+        );
+    }
+
+    #[test]
+    fn read_old_output_test() {
+        // with: an output with a `locked` column, and one with a `status` column
+        let before =
+            read_snapshots("src/tests/accounts_before.csv").expect("Expected valid snapshots");
+        let after =
+            read_snapshots("src/tests/accounts_after.csv").expect("Expected valid snapshots");
+
+        // when
+        let diff = BalanceDiff::compare(before, after);
+
+        // then: unlocked accounts are operational, so only the frozen one changed status
+        let status_changes: Vec<ClientId> = diff
+            .changes
+            .iter()
+            .filter(|change| change.status_changed())
+            .map(|change| change.client)
+            .collect();
+        assert_eq!(status_changes, vec![1]);
+    }
+
+    #[test]
+    fn read_missing_file_test() {
+        assert!(read_snapshots("src/tests/does_not_exist.csv").is_err());
+    }
+}
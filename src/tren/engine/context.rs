@@ -1,15 +1,34 @@
 // context meant to be passed through transactions
 // in case of multithreaded systems here's where locks may be held
 
+use crate::tren::client::ClientDirectory;
+use crate::tren::engine::events::EventBus;
 use crate::tren::storage::store::AccountsStorage;
 
+// what handlers see when no client is known in advance
+static NO_CLIENTS: ClientDirectory = ClientDirectory::new();
+
 // in terms of reuse this could also become a trait
 pub struct RunnerContext<'a, S: AccountsStorage> {
     pub accounts_store: &'a mut S,
+    pub events: &'a mut EventBus,
+    /// clients known before processing, with their profiles
+    pub clients: &'a ClientDirectory,
 }
 
 impl<'a, S: AccountsStorage> RunnerContext<'a, S> {
-    pub fn new(accounts_store: &'a mut S) -> Self {
-        RunnerContext { accounts_store }
+    pub fn new(accounts_store: &'a mut S, events: &'a mut EventBus) -> Self {
+        RunnerContext {
+            accounts_store,
+            events,
+            clients: &NO_CLIENTS,
+        }
+    }
+
+    /// The same context, with `clients` known in advance
+    #[must_use]
+    pub fn with_clients(mut self, clients: &'a ClientDirectory) -> Self {
+        self.clients = clients;
+        self
     }
 }
@@ -0,0 +1,254 @@
+// Domain events published by the engine, for other services to react to balance changes
+// Handlers emit events while handling a transaction, but they are only dispatched to subscribers
+// once the runner knows the transaction has been fully handled: a storage failure halfway through
+// must not leak events about changes that never happened
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::tren::{
+    account::AccountStatus,
+    client::ClientId,
+    engine::runner::SkipReason,
+    ids,
+    transactions::{Amount, Currency, TransactionId},
+};
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+#[serde(tag = "event")]
+pub enum EngineEvent {
+    Deposited {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    Withdrawn {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    /// funds of the disputed transaction are now held
+    DisputeOpened {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    /// held funds of the disputed transaction are available again
+    DisputeResolved {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    /// held funds of the disputed transaction are lost
+    ChargedBack {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    /// funds have been reserved by an authorization
+    Authorized {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    /// `amount` of an authorization has been settled, the rest released
+    Captured {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    /// the funds reserved by an authorization have been released
+    Voided {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    /// the effect of a deposit or withdrawal has been undone
+    Reversed {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    /// `amount` in `currency` has been converted to `to_amount` in `to_currency`
+    Exchanged {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        currency: Currency,
+        to_amount: Amount,
+        to_currency: Currency,
+        rate: Decimal,
+    },
+    /// a fee has been charged for the transaction, and credited to the house account
+    FeeCharged {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        amount: Amount,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+    },
+    CreditLimitChanged {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        currency: Option<Currency>,
+        limit: Amount,
+    },
+    AccountFrozen {
+        #[serde(with = "ids")]
+        client: ClientId,
+    },
+    /// an account has been opened by an `open` row
+    AccountOpened {
+        #[serde(with = "ids")]
+        client: ClientId,
+    },
+    /// the account has been suspended, activated, closed or has fallen dormant
+    AccountStatusChanged {
+        #[serde(with = "ids")]
+        client: ClientId,
+        status: AccountStatus,
+    },
+    TransactionSkipped {
+        #[serde(with = "ids")]
+        client: ClientId,
+        #[serde(with = "ids")]
+        tx: TransactionId,
+        reason: SkipReason,
+    },
+}
+
+/// Anything that wants to be notified of engine events
+pub trait EventSubscriber {
+    fn on_event(&mut self, event: &EngineEvent);
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Box<dyn EventSubscriber + Send>>,
+    pending: Vec<EngineEvent>,
+}
+
+impl EventBus {
+    /// Register a subscriber. Subscribers are notified in registration order
+    pub fn subscribe(&mut self, subscriber: Box<dyn EventSubscriber + Send>) {
+        self.subscribers.push(subscriber);
+    }
+
+    /// Queue an event, to be dispatched on the next `flush`
+    pub fn emit(&mut self, event: EngineEvent) {
+        // nobody is listening, don't bother keeping it around
+        if !self.subscribers.is_empty() {
+            self.pending.push(event);
+        }
+    }
+
+    /// Dispatch all the queued events to the subscribers
+    pub fn flush(&mut self) {
+        for event in self.pending.drain(..) {
+            for subscriber in &mut self.subscribers {
+                subscriber.on_event(&event);
+            }
+        }
+    }
+
+    /// Drop all the queued events without dispatching them
+    pub fn discard(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    struct SharedCollector {
+        events: Arc<Mutex<Vec<EngineEvent>>>,
+    }
+
+    impl EventSubscriber for SharedCollector {
+        fn on_event(&mut self, event: &EngineEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn events_are_dispatched_only_on_flush_test() {
+        // with
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut bus = EventBus::default();
+        bus.subscribe(Box::new(SharedCollector {
+            events: Arc::clone(&events),
+        }));
+
+        // when
+        bus.emit(EngineEvent::AccountFrozen { client: 1 });
+        bus.discard();
+        bus.emit(EngineEvent::Deposited {
+            client: 2,
+            tx: 3,
+            amount: dec!(4),
+            currency: None,
+        });
+
+        // then
+        assert!(events.lock().unwrap().is_empty());
+
+        // when
+        bus.flush();
+
+        // then
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![EngineEvent::Deposited {
+                client: 2,
+                tx: 3,
+                amount: dec!(4),
+                currency: None,
+            }]
+        );
+    }
+}
@@ -1,2 +1,4 @@
 pub mod context;
+pub mod events;
 pub mod runner;
+pub mod summary;
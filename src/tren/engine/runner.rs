@@ -1,18 +1,27 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display, Formatter};
 
+use crate::tren::account::Account;
+use crate::tren::client::ClientDirectory;
+use crate::tren::diff::balance_diff::BalanceDiff;
+use crate::tren::handlers::limits::Limit;
 use crate::tren::handlers::transaction_handler::TransactionHandler;
+use crate::tren::inputs::account_config::AccountConfig;
 use crate::tren::inputs::csv_streamer::CsvConfig;
 use crate::tren::inputs::csv_streamer::CsvStreamer;
 use crate::tren::inputs::csv_streamer::CsvStreamerError;
 use crate::tren::inputs::transactions_provider::TransactionsProvider;
-use crate::tren::storage::store::AccountsStorage;
+use crate::tren::storage::overlay_accounts_storage::OverlayAccountsStorage;
+use crate::tren::storage::store::{AccountsScope, AccountsStorage};
 use crate::tren::transactions::Transaction;
 use futures::Stream;
 // transaction engine runner`
 use futures_util::StreamExt; // needed for .next()
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use super::context::RunnerContext;
+use super::events::{EventBus, EventSubscriber};
+use super::summary::RunSummary;
 
 // TODO make this the "generic runner errors" and transalte handler errors into this type,
 // or make the error generic and return directly a generic thiserror::Error without this
@@ -28,13 +37,114 @@ pub enum RunnerError {
     StorageError,
     #[error("Stream failure [{0}]")]
     StreamFailure(String),
+    /// a transaction is older than a previous one of the same client
+    #[error("Out of order transaction [{0}]")]
+    OutOfOrder(String),
+    /// the engine has no ids left for the transactions it generates, e.g. interest deposits
+    #[error("No transaction ids left [{0}]")]
+    IdsExhausted(String),
+    /// a pipeline stage changed a transaction so that it touches other accounts than those
+    /// loaded for it, e.g. by rewriting its client
+    #[error("A stage changed the accounts of transaction [{0}]")]
+    ScopeChanged(String),
 }
 
 /// successful outcomes for a transaction handling
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum RunnerOutcome {
     Success,
-    Skipped,
+    Skipped(SkipReason),
+}
+
+/// why a transaction has been skipped
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone)]
+pub enum SkipReason {
+    #[serde(rename = "account_frozen")]
+    AccountFrozen,
+    /// the account is suspended, and the transaction would take funds out of it
+    #[serde(rename = "account_suspended")]
+    AccountSuspended,
+    /// the account is dormant, it must be activated again first
+    #[serde(rename = "account_dormant")]
+    AccountDormant,
+    #[serde(rename = "account_closed")]
+    AccountClosed,
+    /// the client is not known, and only known clients are allowed
+    #[serde(rename = "unknown_client")]
+    UnknownClient,
+    /// the account to open is already there
+    #[serde(rename = "account_exists")]
+    AccountExists,
+    /// the account can't go from its status to the requested one
+    #[serde(rename = "invalid_status_transition")]
+    InvalidStatusTransition,
+    /// the account can't be closed with funds in it
+    #[serde(rename = "balance_not_zero")]
+    BalanceNotZero,
+    #[serde(rename = "not_enough_funds")]
+    NotEnoughFunds,
+    /// the referenced transaction does not exist for this client
+    #[serde(rename = "transaction_not_found")]
+    TransactionNotFound,
+    /// the referenced transaction exists, but cannot be disputed/resolved/charged back
+    #[serde(rename = "invalid_transaction_state")]
+    InvalidTransactionState,
+    /// the transaction is older than a previous one of the same client
+    #[serde(rename = "out_of_order")]
+    OutOfOrder,
+    /// the disputed transaction is too old to be disputed
+    #[serde(rename = "dispute_window_expired")]
+    DisputeWindowExpired,
+    /// the dispute would bring the balance beyond its credit limit
+    #[serde(rename = "overdrawn")]
+    Overdrawn,
+    /// there is no exchange rate between the currencies at the time of the transaction
+    #[serde(rename = "rate_not_found")]
+    RateNotFound,
+    /// the withdrawal would breach a velocity limit of the client
+    #[serde(rename = "limit_exceeded")]
+    LimitExceeded(Limit),
+    /// a pipeline stage refused the transaction
+    #[serde(rename = "rejected")]
+    Rejected(String),
+    /// the id of the transaction is reserved to the ones the engine generates
+    #[serde(rename = "reserved_transaction_id")]
+    ReservedTransactionId,
+}
+
+// same names as the serialized ones
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::AccountFrozen => write!(f, "account_frozen"),
+            SkipReason::AccountSuspended => write!(f, "account_suspended"),
+            SkipReason::AccountDormant => write!(f, "account_dormant"),
+            SkipReason::AccountClosed => write!(f, "account_closed"),
+            SkipReason::UnknownClient => write!(f, "unknown_client"),
+            SkipReason::AccountExists => write!(f, "account_exists"),
+            SkipReason::InvalidStatusTransition => write!(f, "invalid_status_transition"),
+            SkipReason::BalanceNotZero => write!(f, "balance_not_zero"),
+            SkipReason::NotEnoughFunds => write!(f, "not_enough_funds"),
+            SkipReason::TransactionNotFound => write!(f, "transaction_not_found"),
+            SkipReason::InvalidTransactionState => write!(f, "invalid_transaction_state"),
+            SkipReason::OutOfOrder => write!(f, "out_of_order"),
+            SkipReason::DisputeWindowExpired => write!(f, "dispute_window_expired"),
+            SkipReason::Overdrawn => write!(f, "overdrawn"),
+            SkipReason::RateNotFound => write!(f, "rate_not_found"),
+            SkipReason::LimitExceeded(limit) => write!(f, "limit_exceeded ({limit})"),
+            SkipReason::Rejected(reason) => write!(f, "rejected ({reason})"),
+            SkipReason::ReservedTransactionId => write!(f, "reserved_transaction_id"),
+        }
+    }
+}
+
+/// What a dry run would have done
+#[derive(Debug)]
+pub struct DryRunReport {
+    /// balances of all the accounts as they would be, ordered by client id
+    pub accounts: Vec<Account>,
+    /// what would have changed
+    pub diff: BalanceDiff,
 }
 
 pub struct Runner<H, S>
@@ -45,6 +155,9 @@ where
     // handler must live at least as long as Runner
     handler: H,
     accounts_store: S,
+    events: EventBus,
+    summary: RunSummary,
+    clients: ClientDirectory,
 }
 
 impl<H, S> Runner<H, S>
@@ -58,11 +171,92 @@ where
         Runner {
             handler,
             accounts_store: accounts_storage,
+            events: EventBus::default(),
+            summary: RunSummary::default(),
+            clients: ClientDirectory::default(),
+        }
+    }
+
+    /// Register a subscriber to the events published while handling transactions
+    pub fn subscribe(&mut self, subscriber: Box<dyn EventSubscriber + Send>) {
+        self.events.subscribe(subscriber);
+    }
+
+    /// Read-only access to the underlying storage, e.g. for querying balances between runs
+    #[must_use]
+    pub fn accounts_store(&self) -> &S {
+        &self.accounts_store
+    }
+
+    /// Make what has been committed to the storage durable, e.g. at the end of a run, for
+    /// storages that delay it
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageError` if the storage fails to write to disk
+    pub async fn sync(&mut self) -> Result<(), RunnerError> {
+        self.accounts_store
+            .sync()
+            .await
+            .map_err(|_| RunnerError::StorageError)
+    }
+
+    /// What happened to the transactions handled so far
+    #[must_use]
+    pub fn summary(&self) -> &RunSummary {
+        &self.summary
+    }
+
+    /// Clients known in advance, with their profiles
+    #[must_use]
+    pub fn clients(&self) -> &ClientDirectory {
+        &self.clients
+    }
+
+    /// Refuse the transactions of clients that are not configured, instead of opening accounts
+    /// for them
+    pub fn known_clients_only(&mut self, known_clients_only: bool) {
+        self.clients.known_clients_only = known_clients_only;
+    }
+
+    /// Apply per-account settings, creating the accounts that don't exist yet, and register
+    /// the clients as known
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StorageError` if an account cannot be read or written
+    pub async fn configure_accounts(
+        &mut self,
+        configs: impl IntoIterator<Item = AccountConfig>,
+    ) -> Result<(), RunnerError> {
+        let configs = configs.into_iter().collect::<Vec<AccountConfig>>();
+        self.accounts_store
+            .load(&AccountsScope::Clients(
+                configs.iter().map(|config| config.client).collect(),
+            ))
+            .await
+            .map_err(|_| RunnerError::StorageError)?;
+        self.accounts_store.begin();
+        for config in &configs {
+            let Ok(account) = self.accounts_store.get_or_create(config.client) else {
+                self.accounts_store.rollback();
+                return Err(RunnerError::StorageError);
+            };
+            config.apply(account);
+        }
+        self.accounts_store
+            .commit()
+            .await
+            .map_err(|_| RunnerError::StorageError)?;
+
+        for config in configs {
+            self.clients.register(config.client, config.profile());
         }
+        Ok(())
     }
 
-    /// Extract a reference to the underlying handler for inspection. Needed for test only
-    #[cfg(test)]
+    /// Extract a reference to the underlying handler for inspection, e.g. to carry its state
+    /// over to a dry run
     pub fn handler(&self) -> &H {
         &self.handler
     }
@@ -83,6 +277,41 @@ where
         self.run_transactions(csv_stream).await
     }
 
+    /// Run the transactions of a csv against a copy-on-write overlay of the storage, reporting
+    /// the resulting balances and what changed. The storage is left untouched, and no events are
+    /// published to the subscribers of this runner
+    /// `handler` handles the transactions of the dry run in place of the runner's one
+    ///
+    /// # Errors
+    ///
+    /// Returns error for errors opening the CSV or handling its transactions
+    pub async fn dry_run_from_csv<'s, D>(
+        &'s self,
+        handler: D,
+        path: &str,
+    ) -> Result<DryRunReport, RunnerError>
+    where
+        D: TransactionHandler<OverlayAccountsStorage<'s, S>>,
+    {
+        let mut dry_runner =
+            Runner::new(handler, OverlayAccountsStorage::new(&self.accounts_store));
+        dry_runner.clients = self.clients.clone();
+        let context = dry_runner.run_from_csv(path).await?;
+
+        let mut accounts = context
+            .accounts_store
+            .all_accounts_iter()
+            .cloned()
+            .collect::<Vec<Account>>();
+        accounts.sort_by_key(|account| account.client_id);
+        let diff = context
+            .accounts_store
+            .diff()
+            .map_err(|_| RunnerError::StorageError)?;
+
+        Ok(DryRunReport { accounts, diff })
+    }
+
     /// Iterate through the list of transactions and handle them
     ///
     /// # Errors
@@ -96,7 +325,8 @@ where
         T: Stream<Item = Result<Transaction, E>> + Unpin,
         E: Debug,
     {
-        let mut context = RunnerContext::new(&mut self.accounts_store);
+        let mut context = RunnerContext::new(&mut self.accounts_store, &mut self.events)
+            .with_clients(&self.clients);
 
         while let Some(result) = stream.next().await {
             let record = result
@@ -107,12 +337,108 @@ where
                 .map_err(|err| RunnerError::InvalidRow(format!("Invalid row [{err:?}]")))?;
             //print!("{:?}", record);
 
-            self.handler.handle(record, &mut context)?;
+            Self::handle(&mut self.handler, record, &mut context, &mut self.summary).await?;
+        }
+
+        // same as for transactions, events are only published if everything went fine
+        match Self::finish(&mut self.handler, &mut context).await {
+            Ok(()) => context.events.flush(),
+            Err(err) => {
+                context.events.discard();
+                return Err(err);
+            }
         }
 
         Ok(context)
     }
 
+    /// Validate and handle a single transaction, e.g. one submitted through the HTTP API
+    ///
+    /// # Errors
+    ///
+    /// See `RunnerError` for the possible errors returned and their meaning
+    pub async fn run_transaction(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<RunnerOutcome, RunnerError> {
+        let record = transaction
+            .validate()
+            .map_err(|err| RunnerError::InvalidRow(format!("Invalid row [{err:?}]")))?;
+
+        let mut context = RunnerContext::new(&mut self.accounts_store, &mut self.events)
+            .with_clients(&self.clients);
+        Self::handle(&mut self.handler, record, &mut context, &mut self.summary).await
+    }
+
+    // events emitted while handling are only published if the handler succeeded
+    async fn handle(
+        handler: &mut H,
+        record: Transaction,
+        context: &mut RunnerContext<'_, S>,
+        summary: &mut RunSummary,
+    ) -> Result<RunnerOutcome, RunnerError> {
+        let result = Self::apply(handler, record, context).await;
+        match &result {
+            Ok(outcome) => {
+                summary.record(outcome);
+                context.events.flush();
+            }
+            Err(_) => context.events.discard(),
+        }
+        result
+    }
+
+    // the storage loads what the handler needs before, and the handler's changes are a unit of
+    // work: committed if it succeeds, rolled back if it fails
+    async fn apply(
+        handler: &mut H,
+        record: Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> Result<RunnerOutcome, RunnerError> {
+        context
+            .accounts_store
+            .load(&handler.scope(&record))
+            .await
+            .map_err(|_| RunnerError::StorageError)?;
+
+        context.accounts_store.begin();
+        let outcome = match handler.handle(record, context) {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                context.accounts_store.rollback();
+                return Err(err);
+            }
+        };
+        context
+            .accounts_store
+            .commit()
+            .await
+            .map_err(|_| RunnerError::StorageError)?;
+        Ok(outcome)
+    }
+
+    async fn finish(
+        handler: &mut H,
+        context: &mut RunnerContext<'_, S>,
+    ) -> Result<(), RunnerError> {
+        context
+            .accounts_store
+            .load(&handler.finish_scope())
+            .await
+            .map_err(|_| RunnerError::StorageError)?;
+
+        context.accounts_store.begin();
+        if let Err(err) = handler.finish(context) {
+            context.accounts_store.rollback();
+            return Err(err);
+        }
+        context
+            .accounts_store
+            .commit()
+            .await
+            .map_err(|_| RunnerError::StorageError)
+    }
+
     // temporary here because I did not decide about errors yet
     fn handle_csv_error(error: CsvStreamerError) -> RunnerError {
         match error {
@@ -132,13 +458,38 @@ where
 // can include information for the same client, how to handle chronological order etc) so that proper parallelization
 // and locking rules can be applied
 #[cfg(test)]
+#[allow(clippy::needless_borrow)]
 mod test {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
     use super::*;
-    use crate::tren::account::Account;
+    use crate::tren::account::AccountStatus;
+    use crate::tren::diff::balance_diff::AccountSnapshot;
+    use crate::tren::engine::events::EngineEvent;
+    use crate::tren::exchange::rates_reader::read_rates;
+    use crate::tren::handlers::authorization_policy::AuthorizationPolicy;
     use crate::tren::handlers::collect_handler::CollectHandler;
-    use crate::tren::handlers::execute_handler::ExecuteHandler;
+    use crate::tren::handlers::dispute_policy::{
+        DisputeExpiry, DisputePolicy, ExpiryAction, OverdrawnDisputes,
+    };
+    use crate::tren::handlers::execute_handler::{ExecuteHandler, OutOfOrderPolicy};
+    use crate::tren::handlers::interest::{InterestPolicy, DEFAULT_FIRST_TRANSACTION_ID};
+    use crate::tren::handlers::limits::{Limits, WithdrawalLimits};
+    use crate::tren::inputs::account_config::read_account_config;
+    use crate::tren::inputs::fees_config::read_fee_schedule;
+    use crate::tren::inputs::limits_config::read_client_limits;
+    use crate::tren::storage::bounded_accounts_storage::BoundedAccountsStorage;
+    use crate::tren::storage::cached_accounts_storage::CachedAccountsStorage;
+    use crate::tren::storage::faulty_accounts_storage::{Fault, FaultyAccountsStorage};
+    use crate::tren::storage::history::HistoryPolicy;
     use crate::tren::storage::in_memory_accounts_storage::InMemoryAccountsStorage;
-    use crate::tren::transactions::{Transaction, TransactionStatus, TransactionType};
+    use crate::tren::storage::kv_accounts_storage::KvAccountsStorage;
+    use crate::tren::transactions::{
+        Currency, Transaction, TransactionId, TransactionStatus, TransactionType,
+    };
+    use chrono::TimeDelta;
+    use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
 
     // TODO: this should be separate tests with decent separation, but at this
@@ -204,7 +555,7 @@ mod test {
             .get(1)
             .expect("Get should work")
             .expect("Account should exist");
-        assert_eq!(account.total(), dec!(101));
+        assert_eq!(account.balance(None).total(), dec!(101));
     }
 
     #[tokio::test]
@@ -226,7 +577,7 @@ mod test {
             .expect("Account 1 should exist");
 
         // 1 + 2 - 1.5 (disputed) + 2 + (resolved)
-        assert_eq!(account.total(), dec!(3.5));
+        assert_eq!(account.balance(None).total(), dec!(3.5));
 
         // let's also test a transaction has been set back as executed
         let transaction = result
@@ -260,7 +611,7 @@ mod test {
 
         // 1 + 2 - 1.5 (disputed) + 2 + (chargeback) + frozen (ignore subsequent transactions)
         assert!(account.frozen());
-        assert_eq!(account.total(), dec!(2));
+        assert_eq!(account.balance(None).total(), dec!(2));
 
         // let's also test a transaction is set as charged back
         let transaction = result
@@ -272,8 +623,8 @@ mod test {
     }
 
     #[tokio::test]
-    async fn refer_inexistent_tx_test() {
-        let test_csv_path = "src/tests/refer_inexistent_tx.csv";
+    async fn partial_disputes_test() {
+        let test_csv_path = "src/tests/partial_disputes.csv";
 
         let mut runner = get_executor_runner();
         let result = runner
@@ -281,20 +632,55 @@ mod test {
             .await
             .expect("Expected an Ok value from runner");
 
+        // 30 + 50 held, 30 more is too much, 50 released, the remaining 70 held, 40 charged back,
+        // nothing more while the account is frozen, then once activated 20 of what is still held
+        // released and the last 40 charged back, freezing it again
         let account = result
             .accounts_store
             .get(1)
             .expect("Get should work")
             .expect("Account 1 should exist");
+        assert!(account.frozen());
+        assert_eq!(account.balance(None).available, dec!(20));
+        assert_eq!(account.balance(None).held, dec!(0));
 
-        // 1 + 2 - 1.5 + (dispute a non existent tx) + 2 + (resolve a non disputed tx) + (chargeback a non disputed tx, account is not locked)
-        assert!(!account.frozen());
-        assert_eq!(account.total(), dec!(3.5));
+        let deposit = result
+            .accounts_store
+            .find_non_disputing_transaction(1, 1)
+            .expect("Transaction should have been found");
+        assert_eq!(deposit.status, TransactionStatus::ChargedBack);
+        assert_eq!(
+            (deposit.disputed, deposit.resolved, deposit.charged_back),
+            (dec!(0), dec!(70), dec!(80))
+        );
+
+        // rows record what they held, released or charged back
+        assert_eq!(
+            result
+                .accounts_store
+                .get_transactions(1)
+                .expect("Transactions should exist")
+                .iter()
+                .skip(1)
+                .map(|t| (t.status.clone(), t.amount))
+                .collect::<Vec<(TransactionStatus, Option<Decimal>)>>(),
+            vec![
+                (TransactionStatus::Executed, Some(dec!(30))),
+                (TransactionStatus::Executed, Some(dec!(50))),
+                (TransactionStatus::Skipped, Some(dec!(30))),
+                (TransactionStatus::Executed, Some(dec!(50))),
+                (TransactionStatus::Executed, Some(dec!(70))),
+                (TransactionStatus::Executed, Some(dec!(40))),
+                (TransactionStatus::Executed, None),
+                (TransactionStatus::Executed, Some(dec!(20))),
+                (TransactionStatus::Executed, Some(dec!(40))),
+            ]
+        );
     }
 
     #[tokio::test]
-    async fn don_t_be_greedy_test() {
-        let test_csv_path = "src/tests/don_t_be_greedy.csv";
+    async fn reversals_test() {
+        let test_csv_path = "src/tests/reversals.csv";
 
         let mut runner = get_executor_runner();
         let result = runner
@@ -302,111 +688,1859 @@ mod test {
             .await
             .expect("Expected an Ok value from runner");
 
-        let account = result
+        // 100 + 5 - 20, then 5 and 20 are reversed
+        let ac1 = result
             .accounts_store
             .get(1)
             .expect("Get should work")
             .expect("Account 1 should exist");
+        assert!(!ac1.frozen());
+        assert_eq!(ac1.balance(None).available, dec!(100));
+        assert_eq!(
+            result
+                .accounts_store
+                .find_non_disputing_transaction(1, 2)
+                .expect("Transaction should have been found")
+                .status,
+            TransactionStatus::Reversed
+        );
+        // reversed transactions can't be disputed nor reversed again
+        assert_eq!(
+            result
+                .accounts_store
+                .get_transactions(1)
+                .expect("Transactions should exist")
+                .iter()
+                .skip(3)
+                .map(|t| (t.transaction_type.clone(), t.status.clone()))
+                .collect::<Vec<(TransactionType, TransactionStatus)>>(),
+            vec![
+                (TransactionType::Reversal, TransactionStatus::Executed),
+                (TransactionType::Reversal, TransactionStatus::Executed),
+                (TransactionType::Dispute, TransactionStatus::Skipped),
+                (TransactionType::Reversal, TransactionStatus::Skipped),
+            ]
+        );
 
-        // 2 + 1 + 2 - (cannot withdraw 500, let's skip) -3
-        assert_eq!(account.total(), dec!(2));
+        // a deposit already spent can't be reversed
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert_eq!(ac2.balance(None).available, dec!(0));
+        assert_eq!(
+            result
+                .accounts_store
+                .find_non_disputing_transaction(2, 4)
+                .expect("Transaction should have been found")
+                .status,
+            TransactionStatus::Executed
+        );
     }
 
     #[tokio::test]
-    async fn don_t_dispute_skipped_transactions_test() {
-        let test_csv_path = "src/tests/don_t_dispute_skipped_transactions.csv";
-        let client_id = 1;
-        // withdrawal, 1,      4,  500
-        let disputed_skipped_transaction_id = 4;
-
+    async fn reversals_dont_use_credit_test() {
+        // with: a credit line of 100, 50 deposited and 30 of them withdrawn
+        let transactions = vec![
+            Transaction::new(TransactionType::CreditLimit, 1, 1, Some(dec!(100))),
+            Transaction::new(TransactionType::Deposit, 1, 2, Some(dec!(50))),
+            Transaction::new(TransactionType::Withdrawal, 1, 3, Some(dec!(30))),
+            Transaction::new(TransactionType::Reversal, 1, 2, None),
+            Transaction::new(TransactionType::Deposit, 1, 4, Some(dec!(40))),
+            Transaction::new(TransactionType::Reversal, 1, 2, None),
+        ];
         let mut runner = get_executor_runner();
+
+        // when
         let result = runner
-            .run_from_csv(&test_csv_path)
+            .run_transactions(futures::stream::iter(
+                transactions.into_iter().map(Ok::<_, ()>),
+            ))
             .await
             .expect("Expected an Ok value from runner");
 
-        let withdrawal_operation = result
+        // then: the deposit is taken back only once the client has the funds again
+        let ac1 = result
             .accounts_store
-            .find_non_disputing_transaction(client_id, disputed_skipped_transaction_id)
-            .expect("Transaction should have been found");
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).available, dec!(10));
+        assert_eq!(
+            result
+                .accounts_store
+                .get_transactions(1)
+                .expect("Transactions should exist")
+                .iter()
+                .map(|t| t.status.clone())
+                .collect::<Vec<TransactionStatus>>(),
+            vec![
+                TransactionStatus::Executed,
+                TransactionStatus::Reversed,
+                TransactionStatus::Executed,
+                TransactionStatus::Skipped,
+                TransactionStatus::Executed,
+                TransactionStatus::Executed,
+            ]
+        );
+    }
 
-        // 2 + 1 + 2 - (cannot withdraw 500, let's skip) -3
-        assert_eq!(withdrawal_operation.status, TransactionStatus::Skipped);
+    #[tokio::test]
+    async fn authorizations_test() {
+        let test_csv_path = "src/tests/authorizations.csv";
+
+        let handler = ExecuteHandler {
+            authorizations: AuthorizationPolicy {
+                expiry: Some(TimeDelta::days(7)),
+            },
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // 25 of 40 captured, 30 voided, 50 is too much, 20 still reserved at the tick
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).available, dec!(55));
+        assert_eq!(ac1.balance(None).reserved, dec!(20));
+        assert_eq!(ac1.balance(None).total(), dec!(75));
+        assert_eq!(
+            result
+                .accounts_store
+                .get_transactions(1)
+                .expect("Transactions should exist")
+                .iter()
+                .skip(1)
+                .map(|t| (t.transaction_type.clone(), t.status.clone(), t.amount))
+                .collect::<Vec<(TransactionType, TransactionStatus, Option<Decimal>)>>(),
+            vec![
+                (
+                    TransactionType::Authorize,
+                    TransactionStatus::Captured,
+                    Some(dec!(40))
+                ),
+                (
+                    TransactionType::Authorize,
+                    TransactionStatus::Voided,
+                    Some(dec!(30))
+                ),
+                (
+                    TransactionType::Authorize,
+                    TransactionStatus::Skipped,
+                    Some(dec!(50))
+                ),
+                (
+                    TransactionType::Capture,
+                    TransactionStatus::Executed,
+                    Some(dec!(25))
+                ),
+                (TransactionType::Capture, TransactionStatus::Skipped, None),
+                (
+                    TransactionType::Void,
+                    TransactionStatus::Executed,
+                    Some(dec!(30))
+                ),
+                (
+                    TransactionType::Authorize,
+                    TransactionStatus::Executed,
+                    Some(dec!(20))
+                ),
+            ]
+        );
+
+        // stale authorizations are voided
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert_eq!(ac2.balance(None).available, dec!(10));
+        assert_eq!(ac2.balance(None).reserved, dec!(0));
+        assert_eq!(
+            result
+                .accounts_store
+                .find_non_disputing_transaction(2, 7)
+                .expect("Transaction should have been found")
+                .status,
+            TransactionStatus::Voided
+        );
     }
 
     #[tokio::test]
-    async fn malformed_test() {
-        let test_csv_path = "src/tests/malformed.csv";
+    async fn authorizations_expire_on_frozen_accounts_test() {
+        // with: an authorization on an account frozen by a chargeback
+        let at = |timestamp: &str| timestamp.parse().expect("Valid timestamp");
+        let transactions = vec![
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(100)))
+                .with_timestamp(at("2025-01-01T00:00:00Z")),
+            Transaction::new(TransactionType::Deposit, 1, 2, Some(dec!(10)))
+                .with_timestamp(at("2025-01-01T00:00:00Z")),
+            Transaction::new(TransactionType::Authorize, 1, 3, Some(dec!(40)))
+                .with_timestamp(at("2025-01-01T00:00:00Z")),
+            Transaction::new(TransactionType::Dispute, 1, 2, None)
+                .with_timestamp(at("2025-01-02T00:00:00Z")),
+            Transaction::new(TransactionType::Chargeback, 1, 2, None)
+                .with_timestamp(at("2025-01-02T00:00:00Z")),
+            Transaction::new(TransactionType::Tick, 0, 0, None)
+                .with_timestamp(at("2025-01-10T00:00:00Z")),
+        ];
+        let handler = ExecuteHandler {
+            authorizations: AuthorizationPolicy {
+                expiry: Some(TimeDelta::days(7)),
+            },
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
 
-        let mut runner = get_executor_runner();
-        assert!(runner.run_from_csv(&test_csv_path).await.is_err());
+        // when
+        let result = runner
+            .run_transactions(futures::stream::iter(
+                transactions.into_iter().map(Ok::<_, ()>),
+            ))
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // then: the reservation is released all the same
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert!(ac1.frozen());
+        assert_eq!(ac1.balance(None).reserved, dec!(0));
+        assert_eq!(ac1.balance(None).available, dec!(100));
     }
 
     #[tokio::test]
-    async fn does_not_exist_test() {
-        let test_csv_path = "src/tests/does_not_exist.csv";
+    async fn negative_authorizations_test() {
+        // with
+        let mut runner = Runner::new(
+            ExecuteHandler::default(),
+            InMemoryAccountsStorage::default(),
+        );
+        runner
+            .run_transaction(Transaction::new(
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(dec!(10)),
+            ))
+            .await
+            .expect("Expected an Ok value from runner");
 
-        let mut runner = get_executor_runner();
-        assert!(runner.run_from_csv(&test_csv_path).await.is_err());
+        // when
+        let result = runner
+            .run_transaction(Transaction::new(
+                TransactionType::Authorize,
+                1,
+                2,
+                Some(dec!(-50)),
+            ))
+            .await;
+
+        // then: the row is refused and a capture has nothing to settle
+        assert!(matches!(result, Err(RunnerError::InvalidRow(_))));
+        let outcome = runner
+            .run_transaction(Transaction::new(TransactionType::Capture, 1, 2, None))
+            .await
+            .expect("Expected an Ok value from runner");
+        assert!(matches!(outcome, RunnerOutcome::Skipped(_)));
+        let ac1 = runner
+            .accounts_store()
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).available, dec!(10));
+        assert_eq!(ac1.balance(None).reserved, dec!(0));
     }
 
     #[tokio::test]
-    async fn multiple_clients_test() {
-        // this test is just for chaos, honestly those behavious should already
-        // have been tested before
-        // the file has ordered clients IDs, but only for my mental safety since in this implementation
-        // all client are indipendent
-        let test_csv_path = "src/tests/multiple_clients.csv";
-        // client ids
-        let ci1 = 10;
-        let ci2 = 20;
-        let ci3 = 30;
+    async fn account_lifecycle_test() {
+        let test_csv_path = "src/tests/account_lifecycle.csv";
 
-        let mut runner = get_executor_runner();
+        let handler = ExecuteHandler {
+            dormant_after: Some(TimeDelta::days(30)),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
         let result = runner
-            .run_from_csv(&test_csv_path)
+            .run_from_csv(test_csv_path)
             .await
             .expect("Expected an Ok value from runner");
 
-        // Global asserts
-        assert_eq!(result.accounts_store.count_accounts(), 3);
+        let status = |client_id| {
+            result
+                .accounts_store
+                .get(client_id)
+                .expect("Get should work")
+                .expect("Account should exist")
+                .status
+                .clone()
+        };
+        // suspended, activated again, emptied and closed
+        assert_eq!(status(1), AccountStatus::Closed);
+        // can't be closed with funds, then falls dormant on the tick
+        assert_eq!(status(2), AccountStatus::Dormant);
+        // fell dormant on its own row, activated again
+        assert_eq!(status(3), AccountStatus::Operational);
+        assert_eq!(
+            result
+                .accounts_store
+                .get(3)
+                .expect("Get should work")
+                .expect("Account 3 should exist")
+                .balance(None)
+                .available,
+            dec!(10)
+        );
 
-        // ac1: some deposits/withdrawals, a chargeback and a frozen deposit
+        assert_eq!(
+            runner
+                .summary()
+                .skipped
+                .keys()
+                .cloned()
+                .collect::<Vec<SkipReason>>(),
+            vec![
+                SkipReason::AccountSuspended,
+                SkipReason::AccountDormant,
+                SkipReason::AccountClosed,
+                SkipReason::BalanceNotZero,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn suspended_accounts_test() {
+        // with: an authorization and a deposit on an account suspended afterwards
+        let transactions = vec![
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(100))),
+            Transaction::new(TransactionType::Authorize, 1, 2, Some(dec!(40))),
+            Transaction::new(TransactionType::Suspend, 1, 0, None),
+            Transaction::new(TransactionType::Capture, 1, 2, None),
+            Transaction::new(TransactionType::Reversal, 1, 1, None),
+            Transaction::new(TransactionType::Accrue, 0, 0, None),
+        ];
+        let handler = ExecuteHandler {
+            interest: Some(InterestPolicy::new(dec!(0.01))),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+
+        // when
+        let result = runner
+            .run_transactions(futures::stream::iter(
+                transactions.into_iter().map(Ok::<_, ()>),
+            ))
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // then: no money moves out, and no interest is posted
         let ac1 = result
             .accounts_store
-            .get(ci1)
-            .expect("Expected success")
-            .expect("Expected account");
-        assert!(ac1.frozen());
-        assert_eq!(ac1.held_amount, dec!(0));
-        assert_eq!(ac1.amount, dec!(298.1234));
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).available, dec!(60));
+        assert_eq!(ac1.balance(None).reserved, dec!(40));
+        assert_eq!(
+            runner.summary().skipped.get(&SkipReason::AccountSuspended),
+            Some(&2)
+        );
+    }
 
-        // ac2: some deposits/withdrawals, concurrent disputes and a final lottery win
-        let ac2 = result
-            .accounts_store
-            .get(ci2)
-            .expect("Expected success")
-            .expect("Expected account");
-        assert!(!ac2.frozen());
-        assert_eq!(ac2.held_amount, dec!(0)); // all disputes have been resolved
-        assert_eq!(ac2.amount, dec!(10199.1235));
+    #[tokio::test]
+    async fn known_clients_only_test() {
+        let test_csv_path = "src/tests/known_clients.csv";
 
-        // ac3: a lot of stuff targeting non existent transactions or another client's transactions
-        // but also a real dispute left pending
-        let tid3_disputed = 32; // disputed transaction, left pending
-        let ac3 = result
-            .accounts_store
-            .get(ci3)
-            .expect("Expected success")
-            .expect("Expected account");
+        let mut runner = get_executor_runner();
+        runner
+            .configure_accounts(
+                read_account_config("src/tests/accounts_config.csv")
+                    .expect("Expected valid config"),
+            )
+            .await
+            .expect("Expected accounts to be configured");
+        runner.known_clients_only(true);
+        assert_eq!(
+            runner
+                .clients()
+                .profile(1)
+                .and_then(|profile| profile.account_type.as_deref()),
+            Some("business")
+        );
+
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // client 3 is not configured: no account is opened for it
+        assert_eq!(result.accounts_store.count_accounts(), 2);
+        assert!(result
+            .accounts_store
+            .get(3)
+            .expect("Get should work")
+            .is_none());
+        assert_eq!(
+            runner.summary().skipped.get(&SkipReason::UnknownClient),
+            Some(&2)
+        );
+    }
+
+    #[tokio::test]
+    async fn open_accounts_test() {
+        let test_csv_path = "src/tests/open_accounts.csv";
+
+        // dispute rows never open accounts, whatever the mode
+        let mut runner = get_executor_runner();
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+        assert_eq!(result.accounts_store.count_accounts(), 2);
+        assert!(result
+            .accounts_store
+            .get(3)
+            .expect("Get should work")
+            .is_none());
+
+        // only the opened account, when only known clients are allowed
+        let mut runner = get_executor_runner();
+        runner.known_clients_only(true);
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+        assert_eq!(result.accounts_store.count_accounts(), 1);
+        assert_eq!(
+            result
+                .accounts_store
+                .get(1)
+                .expect("Get should work")
+                .expect("Account 1 should exist")
+                .balance(None)
+                .available,
+            dec!(10)
+        );
+        assert_eq!(
+            runner.summary().skipped,
+            BTreeMap::from([
+                (SkipReason::TransactionNotFound, 2),
+                (SkipReason::UnknownClient, 1),
+                (SkipReason::AccountExists, 1),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_storage_test() {
+        // everything but the storage is the same, including fees, ticks and end of run interest
+        fn handler() -> ExecuteHandler {
+            let mut interest = InterestPolicy::new(dec!(0.0001));
+            interest.at_end_of_run = true;
+            ExecuteHandler {
+                disputes: DisputePolicy {
+                    expiry: Some(DisputeExpiry {
+                        after: TimeDelta::days(7),
+                        action: ExpiryAction::Resolve,
+                    }),
+                    ..Default::default()
+                },
+                fees: read_fee_schedule("src/tests/fees.csv", 99).expect("Expected valid fees"),
+                interest: Some(interest),
+                authorizations: AuthorizationPolicy {
+                    expiry: Some(TimeDelta::days(7)),
+                },
+                dormant_after: Some(TimeDelta::days(30)),
+                ..Default::default()
+            }
+        }
+
+        for test_csv_path in [
+            "src/tests/fees_transactions.csv",
+            "src/tests/interest.csv",
+            "src/tests/dispute_windows.csv",
+            "src/tests/partial_disputes.csv",
+            "src/tests/reversals.csv",
+            "src/tests/authorizations.csv",
+            "src/tests/account_lifecycle.csv",
+        ] {
+            let mut in_memory = Runner::new(handler(), InMemoryAccountsStorage::default());
+            let expected = in_memory
+                .run_from_csv(test_csv_path)
+                .await
+                .expect("Expected an Ok value from runner")
+                .accounts_store;
+
+            let mut cached = Runner::new(
+                handler(),
+                CachedAccountsStorage::new(InMemoryAccountsStorage::default()),
+            );
+            let backend = cached
+                .run_from_csv(test_csv_path)
+                .await
+                .expect("Expected an Ok value from runner")
+                .accounts_store
+                .backend();
+
+            // then: everything has been written back
+            assert_same_storage(backend, expected, test_csv_path);
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_transactions_are_rolled_back_test() {
+        use crate::tren::handlers::pipeline::{Pipeline, Stage};
+
+        // fails after the handler applied the transaction
+        struct FailAfter(TransactionId);
+        impl<S: AccountsStorage> Stage<S> for FailAfter {
+            fn after(
+                &mut self,
+                transaction: &Transaction,
+                _outcome: &RunnerOutcome,
+                _context: &mut RunnerContext<'_, S>,
+            ) -> Result<(), RunnerError> {
+                if transaction.transaction_id == self.0 {
+                    return Err(RunnerError::StorageError);
+                }
+                Ok(())
+            }
+        }
+
+        fn handler() -> ExecuteHandler {
+            ExecuteHandler {
+                fees: read_fee_schedule("src/tests/fees.csv", 99).expect("Expected valid fees"),
+                ..Default::default()
+            }
+        }
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(100)));
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 2, Some(dec!(10)));
+        // same state as before the withdrawal
+        fn assert_untouched<S: AccountsStorage>(store: &S) {
+            let account = store
+                .get(1)
+                .expect("Get should work")
+                .expect("Account should exist");
+            assert_eq!(account.balance(None).available, dec!(100));
+            assert_eq!(store.get_transactions(1).map(Vec::len), Some(1));
+            assert!(store.get(99).expect("Get should work").is_none());
+        }
+
+        // with: a stage failing after the fee'd withdrawal has been fully applied
+        let mut pipeline = Pipeline::new(handler());
+        pipeline.add_stage(FailAfter(2));
+        let mut runner = Runner::new(pipeline, InMemoryAccountsStorage::default());
+        runner
+            .run_transaction(deposit.clone())
+            .await
+            .expect("Expected the deposit to be handled");
+
+        // when
+        let result = runner.run_transaction(withdrawal.clone()).await;
+
+        // then: neither the withdrawal nor its fee are left
+        assert!(matches!(result, Err(RunnerError::StorageError)));
+        assert_untouched(runner.accounts_store());
+
+        // with: a backend refusing to write the withdrawal once
+        let backend = FaultyAccountsStorage::new(InMemoryAccountsStorage::default())
+            .failing_writes(Fault::OnCalls(vec![2]));
+        let mut runner = Runner::new(handler(), CachedAccountsStorage::new(backend));
+        runner
+            .run_transaction(deposit)
+            .await
+            .expect("Expected the deposit to be handled");
+
+        // when
+        let result = runner.run_transaction(withdrawal.clone()).await;
+
+        // then: the cache stays in line with the backend
+        assert!(matches!(result, Err(RunnerError::StorageError)));
+        assert_untouched(runner.accounts_store());
+        assert_untouched(runner.accounts_store().backend().inner());
+
+        // when: the backend is back
+        let outcome = runner
+            .run_transaction(withdrawal)
+            .await
+            .expect("Expected the withdrawal to be handled");
+
+        // then
+        assert_eq!(outcome, RunnerOutcome::Success);
+        let backend = runner.accounts_store().backend().inner();
+        let account = backend
+            .get(1)
+            .expect("Get should work")
+            .expect("Account should exist");
+        assert_eq!(account.balance(None).available, dec!(89.5));
+        let house = backend
+            .get(99)
+            .expect("Get should work")
+            .expect("House account should exist");
+        assert_eq!(house.balance(None).available, dec!(0.5));
+    }
+
+    // fixtures and handler of the tests about storages: everything but interest, whose
+    // transaction ids are taken by the handler and not by the storage
+    const STORAGE_FIXTURES: [&str; 7] = [
+        "src/tests/fees_transactions.csv",
+        "src/tests/dispute_windows.csv",
+        "src/tests/partial_disputes.csv",
+        "src/tests/reversals.csv",
+        "src/tests/authorizations.csv",
+        "src/tests/account_lifecycle.csv",
+        "src/tests/velocity.csv",
+    ];
+
+    fn storage_handler() -> ExecuteHandler {
+        ExecuteHandler {
+            disputes: DisputePolicy {
+                expiry: Some(DisputeExpiry {
+                    after: TimeDelta::days(7),
+                    action: ExpiryAction::Resolve,
+                }),
+                ..Default::default()
+            },
+            fees: read_fee_schedule("src/tests/fees.csv", 99).expect("Expected valid fees"),
+            authorizations: AuthorizationPolicy {
+                expiry: Some(TimeDelta::days(7)),
+            },
+            dormant_after: Some(TimeDelta::days(30)),
+            limits: Limits {
+                global: WithdrawalLimits {
+                    max_daily_withdrawals: Some(2),
+                    max_withdrawn_24h: Some(dec!(1000)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    async fn read_transactions(path: &str) -> Vec<Transaction> {
+        CsvStreamer::stream_transactions(CsvConfig {
+            path: String::from(path),
+        })
+        .await
+        .expect("Expected the csv to be opened")
+        .map(|result| result.expect("Expected a valid row"))
+        .collect()
+        .await
+    }
+
+    // runs the transactions of a csv one by one on a runner over `storage`, and the ones that
+    // went through on a runner over a storage that never fails: a failed transaction must leave
+    // no trace, so that both end up the same
+    async fn run_chaos<S: AccountsStorage>(
+        storage: S,
+        test_csv_path: &str,
+    ) -> (
+        Runner<ExecuteHandler, S>,
+        Runner<ExecuteHandler, InMemoryAccountsStorage>,
+    ) {
+        let mut chaotic = Runner::new(storage_handler(), storage);
+        let mut reference = Runner::new(storage_handler(), InMemoryAccountsStorage::default());
+
+        for transaction in read_transactions(test_csv_path).await {
+            match chaotic.run_transaction(transaction.clone()).await {
+                Ok(outcome) => assert_eq!(
+                    reference.run_transaction(transaction).await.ok(),
+                    Some(outcome),
+                    "{test_csv_path}"
+                ),
+                Err(err) => assert!(
+                    matches!(err, RunnerError::StorageError),
+                    "{test_csv_path}: {err}"
+                ),
+            }
+        }
+        assert_eq!(chaotic.summary(), reference.summary(), "{test_csv_path}");
+        (chaotic, reference)
+    }
+
+    fn assert_same_accounts(
+        actual: &impl AccountsStorage,
+        expected: &impl AccountsStorage,
+        test_csv_path: &str,
+    ) {
+        assert_eq!(
+            actual.count_accounts(),
+            expected.count_accounts(),
+            "{test_csv_path}"
+        );
+        for account in expected.all_accounts_iter() {
+            let stored = actual
+                .get(account.client_id)
+                .expect("Get should work")
+                .expect("Account should exist");
+            assert_eq!(
+                AccountSnapshot::of(stored),
+                AccountSnapshot::of(account),
+                "{test_csv_path}"
+            );
+            assert_eq!(stored.status, account.status, "{test_csv_path}");
+            assert_eq!(
+                stored.last_activity, account.last_activity,
+                "{test_csv_path}"
+            );
+        }
+    }
+
+    fn assert_same_storage(
+        actual: &impl AccountsStorage,
+        expected: &impl AccountsStorage,
+        test_csv_path: &str,
+    ) {
+        assert_same_accounts(actual, expected, test_csv_path);
+        for account in expected.all_accounts_iter() {
+            assert_eq!(
+                actual.get_transactions(account.client_id),
+                expected.get_transactions(account.client_id),
+                "{test_csv_path}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn storage_failures_leave_no_trace_test() {
+        let mut failures = 0;
+        for test_csv_path in STORAGE_FIXTURES {
+            for seed in 0..8 {
+                // with
+                let storage = FaultyAccountsStorage::new(InMemoryAccountsStorage::default())
+                    .failing_reads(Fault::Randomly {
+                        probability: 0.05,
+                        seed,
+                    })
+                    .failing_writes(Fault::Randomly {
+                        probability: 0.1,
+                        seed: seed + 100,
+                    });
+
+                // when
+                let (chaotic, reference) = run_chaos(storage, test_csv_path).await;
+
+                // then
+                failures += chaotic.accounts_store().failures();
+                assert_same_storage(
+                    chaotic.accounts_store().inner(),
+                    reference.accounts_store(),
+                    test_csv_path,
+                );
+            }
+        }
+        assert!(failures > 0);
+    }
+
+    #[tokio::test]
+    async fn backend_failures_leave_no_trace_test() {
+        let mut failures = 0;
+        for test_csv_path in STORAGE_FIXTURES {
+            for seed in 0..8 {
+                // with
+                let backend = FaultyAccountsStorage::new(InMemoryAccountsStorage::default())
+                    .failing_reads(Fault::Randomly {
+                        probability: 0.1,
+                        seed,
+                    })
+                    .failing_writes(Fault::Randomly {
+                        probability: 0.2,
+                        seed: seed + 100,
+                    });
+
+                // when
+                let (chaotic, reference) =
+                    run_chaos(CachedAccountsStorage::new(backend), test_csv_path).await;
+
+                // then: the cache stays in line with the backend
+                let backend = chaotic.accounts_store().backend();
+                failures += backend.failures();
+                assert_same_storage(backend.inner(), reference.accounts_store(), test_csv_path);
+                assert_same_storage(
+                    chaotic.accounts_store(),
+                    reference.accounts_store(),
+                    test_csv_path,
+                );
+            }
+        }
+        assert!(failures > 0);
+    }
+
+    #[tokio::test]
+    async fn storage_failure_stops_the_run_test() {
+        for test_csv_path in STORAGE_FIXTURES {
+            // with: a storage failing for good after a while
+            let storage = FaultyAccountsStorage::new(InMemoryAccountsStorage::default())
+                .failing_writes(Fault::After(12));
+            let mut runner = Runner::new(storage_handler(), storage);
+
+            // when
+            let result = runner.run_from_csv(test_csv_path).await;
+
+            // then: what was handled before the failure is kept, nothing else
+            assert!(
+                matches!(result, Err(RunnerError::StorageError)),
+                "{test_csv_path}"
+            );
+            let handled = runner.summary().processed;
+            let mut reference = Runner::new(storage_handler(), InMemoryAccountsStorage::default());
+            for transaction in read_transactions(test_csv_path)
+                .await
+                .into_iter()
+                .take(handled)
+            {
+                reference
+                    .run_transaction(transaction)
+                    .await
+                    .expect("Expected the transaction to be handled");
+            }
+            assert_eq!(runner.summary(), reference.summary(), "{test_csv_path}");
+            assert_same_storage(
+                runner.accounts_store().inner(),
+                reference.accounts_store(),
+                test_csv_path,
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn storage_failure_while_configuring_test() {
+        // with: the second account can't be written
+        let storage = FaultyAccountsStorage::new(InMemoryAccountsStorage::default())
+            .failing_writes(Fault::OnCalls(vec![2]));
+        let mut runner = Runner::new(ExecuteHandler::default(), storage);
+
+        // when
+        let result = runner
+            .configure_accounts(
+                read_account_config("src/tests/accounts_config.csv")
+                    .expect("Expected valid config"),
+            )
+            .await;
+
+        // then: no account is configured, and no client known
+        assert!(matches!(result, Err(RunnerError::StorageError)));
+        assert_eq!(runner.accounts_store().inner().count_accounts(), 0);
+        assert!(runner.clients().profile(1).is_none());
+    }
+
+    #[tokio::test]
+    async fn bounded_history_test() {
+        let spill_path =
+            std::env::temp_dir().join(format!("tren_bounded_runner_{}.bin", std::process::id()));
+        let mut spilled = 0;
+        for test_csv_path in STORAGE_FIXTURES {
+            // with: a single transaction per client in memory
+            let mut in_memory = Runner::new(storage_handler(), InMemoryAccountsStorage::default());
+            let storage = BoundedAccountsStorage::new(&HistoryPolicy {
+                max_in_memory: 1,
+                spill_path: spill_path.clone(),
+            })
+            .expect("Expected a storage");
+            let mut bounded = Runner::new(storage_handler(), storage);
+
+            // when
+            in_memory
+                .run_from_csv(test_csv_path)
+                .await
+                .expect("Expected an Ok value from runner");
+            bounded
+                .run_from_csv(test_csv_path)
+                .await
+                .expect("Expected an Ok value from runner");
+
+            // then: spilled transactions are found when referred to, as if they were in memory
+            spilled += bounded.accounts_store().spilled();
+            assert_eq!(bounded.summary(), in_memory.summary(), "{test_csv_path}");
+            assert_same_accounts(
+                bounded.accounts_store(),
+                in_memory.accounts_store(),
+                test_csv_path,
+            );
+        }
+        assert!(spilled > 0);
+        assert!(!spill_path.exists());
+    }
+
+    #[tokio::test]
+    async fn kv_storage_test() {
+        let path = std::env::temp_dir().join(format!("tren_kv_runner_{}.redb", std::process::id()));
+        for test_csv_path in STORAGE_FIXTURES {
+            // with
+            let mut in_memory = Runner::new(storage_handler(), InMemoryAccountsStorage::default());
+            let storage = KvAccountsStorage::open(&path).expect("Expected a storage");
+            let mut kv = Runner::new(storage_handler(), CachedAccountsStorage::new(storage));
+
+            // when
+            in_memory
+                .run_from_csv(test_csv_path)
+                .await
+                .expect("Expected an Ok value from runner");
+            kv.run_from_csv(test_csv_path)
+                .await
+                .expect("Expected an Ok value from runner");
+            assert_eq!(kv.summary(), in_memory.summary(), "{test_csv_path}");
+            kv.sync().await.expect("Expected the storage to be synced");
+            drop(kv);
+
+            // then: everything can be read back from the file
+            let mut reopened = CachedAccountsStorage::new(
+                KvAccountsStorage::open(&path).expect("Expected a storage"),
+            );
+            reopened
+                .load(&AccountsScope::All)
+                .await
+                .expect("Expected the accounts to be read");
+            assert_same_storage(&reopened, in_memory.accounts_store(), test_csv_path);
+            std::fs::remove_file(&path).expect("Expected the file to be removed");
+        }
+    }
+
+    #[tokio::test]
+    async fn refer_inexistent_tx_test() {
+        let test_csv_path = "src/tests/refer_inexistent_tx.csv";
+
+        let mut runner = get_executor_runner();
+        let result = runner
+            .run_from_csv(&test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let account = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+
+        // 1 + 2 - 1.5 + (dispute a non existent tx) + 2 + (resolve a non disputed tx) + (chargeback a non disputed tx, account is not locked)
+        assert!(!account.frozen());
+        assert_eq!(account.balance(None).total(), dec!(3.5));
+    }
+
+    #[tokio::test]
+    async fn don_t_be_greedy_test() {
+        let test_csv_path = "src/tests/don_t_be_greedy.csv";
+
+        let mut runner = get_executor_runner();
+        let result = runner
+            .run_from_csv(&test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let account = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+
+        // 2 + 1 + 2 - (cannot withdraw 500, let's skip) -3
+        assert_eq!(account.balance(None).total(), dec!(2));
+    }
+
+    #[tokio::test]
+    async fn don_t_dispute_skipped_transactions_test() {
+        let test_csv_path = "src/tests/don_t_dispute_skipped_transactions.csv";
+        let client_id = 1;
+        // withdrawal, 1,      4,  500
+        let disputed_skipped_transaction_id = 4;
+
+        let mut runner = get_executor_runner();
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let withdrawal_operation = result
+            .accounts_store
+            .find_non_disputing_transaction(client_id, disputed_skipped_transaction_id)
+            .expect("Transaction should have been found");
+
+        // 2 + 1 + 2 - (cannot withdraw 500, let's skip) -3
+        assert_eq!(withdrawal_operation.status, TransactionStatus::Skipped);
+    }
+
+    #[tokio::test]
+    async fn malformed_test() {
+        let test_csv_path = "src/tests/malformed.csv";
+
+        let mut runner = get_executor_runner();
+        assert!(runner.run_from_csv(&test_csv_path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn does_not_exist_test() {
+        let test_csv_path = "src/tests/does_not_exist.csv";
+
+        let mut runner = get_executor_runner();
+        assert!(runner.run_from_csv(&test_csv_path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn multiple_clients_test() {
+        // this test is just for chaos, honestly those behavious should already
+        // have been tested before
+        // the file has ordered clients IDs, but only for my mental safety since in this implementation
+        // all client are indipendent
+        let test_csv_path = "src/tests/multiple_clients.csv";
+        // client ids
+        let ci1 = 10;
+        let ci2 = 20;
+        let ci3 = 30;
+
+        let mut runner = get_executor_runner();
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // Global asserts
+        assert_eq!(result.accounts_store.count_accounts(), 3);
+
+        // ac1: some deposits/withdrawals, a chargeback and a frozen deposit
+        let ac1 = result
+            .accounts_store
+            .get(ci1)
+            .expect("Expected success")
+            .expect("Expected account");
+        assert!(ac1.frozen());
+        assert_eq!(ac1.balance(None).held, dec!(0));
+        assert_eq!(ac1.balance(None).available, dec!(298.1234));
+
+        // ac2: some deposits/withdrawals, concurrent disputes and a final lottery win
+        let ac2 = result
+            .accounts_store
+            .get(ci2)
+            .expect("Expected success")
+            .expect("Expected account");
+        assert!(!ac2.frozen());
+        assert_eq!(ac2.balance(None).held, dec!(0)); // all disputes have been resolved
+        assert_eq!(ac2.balance(None).available, dec!(10199.1235));
+
+        // ac3: a lot of stuff targeting non existent transactions or another client's transactions
+        // but also a real dispute left pending
+        let tid3_disputed = 32; // disputed transaction, left pending
+        let ac3 = result
+            .accounts_store
+            .get(ci3)
+            .expect("Expected success")
+            .expect("Expected account");
         let t3_disputed = result
             .accounts_store
             .find_non_disputing_transaction(ci3, tid3_disputed)
             .expect("Transaction should have been found");
         assert_eq!(t3_disputed.status, TransactionStatus::Disputed);
         assert!(!ac3.frozen());
-        assert_eq!(ac3.held_amount, dec!(1.0));
-        assert_eq!(ac3.amount, dec!(198.1235));
+        assert_eq!(ac3.balance(None).held, dec!(1.0));
+        assert_eq!(ac3.balance(None).available, dec!(198.1235));
+    }
+
+    #[tokio::test]
+    async fn events_are_published_test() {
+        let test_csv_path = "src/tests/simple_disputed_chargeback.csv";
+        let events = Arc::new(Mutex::new(vec![]));
+
+        let mut runner = get_executor_runner();
+        runner.subscribe(Box::new(SharedCollector {
+            events: Arc::clone(&events),
+        }));
+        runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let events = events.lock().unwrap();
+        assert!(events.contains(&EngineEvent::DisputeOpened {
+            client: 1,
+            tx: 4,
+            amount: dec!(1.5),
+            currency: None,
+        }));
+        assert!(events.contains(&EngineEvent::ChargedBack {
+            client: 1,
+            tx: 4,
+            amount: dec!(1.5),
+            currency: None,
+        }));
+        assert!(events.contains(&EngineEvent::AccountFrozen { client: 1 }));
+        // deposits after the chargeback are refused
+        assert_eq!(
+            events.last(),
+            Some(&EngineEvent::TransactionSkipped {
+                client: 1,
+                tx: 9,
+                reason: SkipReason::AccountFrozen
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_does_not_change_storage_test() {
+        let mut runner = get_executor_runner();
+        runner
+            .run_from_csv("src/tests/base_transactions.csv")
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let report = runner
+            .dry_run_from_csv(
+                ExecuteHandler::default(),
+                "src/tests/simple_disputed_chargeback.csv",
+            )
+            .await
+            .expect("Expected an Ok value from dry run");
+
+        // client 1 gets charged back and frozen, client 2 is untouched
+        assert_eq!(report.accounts.len(), 2);
+        assert_eq!(report.diff.changes.len(), 1);
+        let change = &report.diff.changes[0];
+        assert_eq!(change.client, 1);
+        assert!(change.status_changed());
+        assert_eq!(change.total_delta(), dec!(2));
+
+        let account = runner
+            .accounts_store()
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert!(!account.frozen());
+        assert_eq!(account.balance(None).total(), dec!(1.5));
+        assert!(runner
+            .accounts_store()
+            .find_non_disputing_transaction(1, 7)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn out_of_order_skip_test() {
+        // deposit,    1,      2,  5.0,    2025-01-01T09:00:00Z
+        // happens before the previous one of the same client
+        let test_csv_path = "src/tests/timestamps.csv";
+
+        let mut runner = get_executor_runner();
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let account = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(account.balance(None).total(), dec!(9));
+        assert_eq!(account.balance(None).held, dec!(10));
+        assert_eq!(
+            account.last_activity,
+            Some("2025-01-02T00:00:00Z".parse().expect("Valid timestamp"))
+        );
+
+        let skipped = result
+            .accounts_store
+            .find_non_disputing_transaction(1, 2)
+            .expect("Transaction should have been found");
+        assert_eq!(skipped.status, TransactionStatus::Skipped);
+        assert_eq!(
+            skipped.timestamp,
+            Some("2025-01-01T09:00:00Z".parse().expect("Valid timestamp"))
+        );
+    }
+
+    #[tokio::test]
+    async fn out_of_order_accept_test() {
+        let test_csv_path = "src/tests/timestamps.csv";
+
+        let handler = ExecuteHandler {
+            out_of_order: OutOfOrderPolicy::Accept,
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let account = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(account.balance(None).total(), dec!(14));
+    }
+
+    #[tokio::test]
+    async fn out_of_order_fail_test() {
+        let test_csv_path = "src/tests/timestamps.csv";
+
+        let handler = ExecuteHandler {
+            out_of_order: OutOfOrderPolicy::Fail,
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+        assert!(matches!(
+            runner.run_from_csv(test_csv_path).await,
+            Err(RunnerError::OutOfOrder(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn dispute_window_and_expiry_resolve_test() {
+        // disputes are allowed within 30 days, and resolved after being open for 7 days
+        let test_csv_path = "src/tests/dispute_windows.csv";
+
+        let mut runner = get_disputes_runner(ExpiryAction::Resolve);
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // tx 1 is disputed too late, tx 2 dispute expires with the deposit at 2025-02-25
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).held, dec!(0));
+        assert_eq!(ac1.balance(None).total(), dec!(16));
+        let disputes = result
+            .accounts_store
+            .get_transactions(1)
+            .expect("Transactions should exist")
+            .iter()
+            .filter(|t| t.transaction_type == TransactionType::Dispute)
+            .map(|t| t.status.clone())
+            .collect::<Vec<TransactionStatus>>();
+        assert_eq!(
+            disputes,
+            vec![TransactionStatus::Skipped, TransactionStatus::Executed]
+        );
+
+        // tx 3 dispute expires with the tick
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert_eq!(ac2.balance(None).held, dec!(0));
+        assert_eq!(ac2.balance(None).total(), dec!(8));
+        let closing = result
+            .accounts_store
+            .get_transactions(2)
+            .expect("Transactions should exist")
+            .last()
+            .expect("A closing transaction should exist");
+        assert_eq!(closing.transaction_type, TransactionType::Resolve);
+        assert_eq!(
+            closing.timestamp,
+            Some("2025-02-20T00:00:00Z".parse().expect("Valid timestamp"))
+        );
+
+        // ticks don't belong to anyone
+        assert_eq!(result.accounts_store.count_accounts(), 2);
+    }
+
+    #[tokio::test]
+    async fn dispute_expiry_chargeback_test() {
+        let test_csv_path = "src/tests/dispute_windows.csv";
+
+        let mut runner = get_disputes_runner(ExpiryAction::Chargeback);
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // the last deposit comes after the expired dispute has been charged back
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert!(ac1.frozen());
+        assert_eq!(ac1.balance(None).total(), dec!(10));
+        let skipped = result
+            .accounts_store
+            .find_non_disputing_transaction(1, 5)
+            .expect("Transaction should have been found");
+        assert_eq!(skipped.status, TransactionStatus::Skipped);
+
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert!(ac2.frozen());
+        assert_eq!(ac2.balance(None).total(), dec!(1));
+    }
+
+    #[tokio::test]
+    async fn currencies_test() {
+        let test_csv_path = "src/tests/currencies.csv";
+
+        let mut runner = get_executor_runner();
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let eur = Currency::from("EUR");
+        let usd = Currency::from("USD");
+        let gbp = Currency::from("GBP");
+
+        // euros can't pay for dollars, and the dispute holds dollars
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(Some(&eur)).available, dec!(6));
+        assert_eq!(ac1.balance(Some(&usd)).available, dec!(0));
+        assert_eq!(ac1.balance(Some(&usd)).held, dec!(5));
+        assert_eq!(ac1.balance(None).total(), dec!(0));
+
+        // the currency of the dispute row is not the one of the disputed deposit: pounds are charged back
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert!(ac2.frozen());
+        assert_eq!(ac2.balance(Some(&gbp)).total(), dec!(0));
+        assert_eq!(ac2.balance(Some(&eur)).total(), dec!(0));
+        assert_eq!(ac2.balance(None).total(), dec!(1));
+    }
+
+    #[tokio::test]
+    async fn exchanges_test() {
+        let test_csv_path = "src/tests/exchanges.csv";
+
+        let handler = ExecuteHandler {
+            rates: read_rates("src/tests/rates.csv").expect("Expected valid rates"),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // tx 2 and 4 at different rates, tx 3 has not enough euros, tx 5 has no rate
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(Some(&Currency::from("EUR"))).total(), dec!(40));
+        assert_eq!(
+            ac1.balance(Some(&Currency::from("USD"))).total(),
+            dec!(65.25)
+        );
+
+        let transactions = result
+            .accounts_store
+            .get_transactions(1)
+            .expect("Transactions should exist");
+        assert_eq!(
+            transactions
+                .iter()
+                .filter(|t| t.transaction_type == TransactionType::Exchange)
+                .map(|t| (t.status.clone(), t.rate))
+                .collect::<Vec<(TransactionStatus, Option<Decimal>)>>(),
+            vec![
+                (TransactionStatus::Executed, Some(dec!(1.0850))),
+                (TransactionStatus::Skipped, None),
+                (TransactionStatus::Executed, Some(dec!(1.1))),
+                (TransactionStatus::Skipped, None),
+            ]
+        );
+        // exchanges can't be disputed
+        assert_eq!(
+            transactions.last().map(|t| t.status.clone()),
+            Some(TransactionStatus::Skipped)
+        );
+    }
+
+    #[tokio::test]
+    async fn exchanges_dont_use_credit_test() {
+        // with: 10 euros and a credit line of 100 euros
+        let timestamp = "2025-01-15T00:00:00Z".parse().ok();
+        let row = |transaction_type, transaction_id, amount| {
+            let mut transaction =
+                Transaction::new(transaction_type, 1, transaction_id, Some(amount));
+            transaction.currency = Some(Currency::from("EUR"));
+            transaction.timestamp = timestamp;
+            if transaction.transaction_type == TransactionType::Exchange {
+                transaction.to_currency = Some(Currency::from("USD"));
+            }
+            transaction
+        };
+        let transactions = vec![
+            row(TransactionType::CreditLimit, 1, dec!(100)),
+            row(TransactionType::Deposit, 2, dec!(10)),
+            row(TransactionType::Exchange, 3, dec!(20)),
+            row(TransactionType::Exchange, 4, dec!(10)),
+        ];
+        let handler = ExecuteHandler {
+            rates: read_rates("src/tests/rates.csv").expect("Expected valid rates"),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+
+        // when
+        let result = runner
+            .run_transactions(futures::stream::iter(
+                transactions.into_iter().map(Ok::<_, ()>),
+            ))
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // then: only the 10 euros the client has are exchanged
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(Some(&Currency::from("EUR"))).available, dec!(0));
+        assert_eq!(
+            ac1.balance(Some(&Currency::from("USD"))).available,
+            dec!(10.85)
+        );
+        assert_eq!(
+            result
+                .accounts_store
+                .get_transactions(1)
+                .expect("Transactions should exist")
+                .iter()
+                .map(|t| t.status.clone())
+                .collect::<Vec<TransactionStatus>>(),
+            vec![
+                TransactionStatus::Executed,
+                TransactionStatus::Executed,
+                TransactionStatus::Skipped,
+                TransactionStatus::Executed,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn credit_limits_test() {
+        let test_csv_path = "src/tests/overdrafts.csv";
+
+        let mut runner = get_executor_runner();
+        runner
+            .configure_accounts(
+                read_account_config("src/tests/accounts_config.csv")
+                    .expect("Expected valid config"),
+            )
+            .await
+            .expect("Expected accounts to be configured");
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // the second withdrawal would go beyond the credit limit
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).available, dec!(-30));
+
+        // without a credit line the dispute is held anyway by default
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert_eq!(ac2.balance(None).available, dec!(-15));
+        assert_eq!(ac2.balance(None).held, dec!(20));
+
+        // credit limit set by an admin row
+        let ac3 = result
+            .accounts_store
+            .get(3)
+            .expect("Get should work")
+            .expect("Account 3 should exist");
+        assert_eq!(ac3.balance(None).available, dec!(-5));
+    }
+
+    #[tokio::test]
+    async fn overdrawn_disputes_skip_test() {
+        let test_csv_path = "src/tests/overdrafts.csv";
+
+        let handler = ExecuteHandler {
+            disputes: DisputePolicy {
+                overdrawn: OverdrawnDisputes::Skip,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert_eq!(ac2.balance(None).available, dec!(5));
+        assert_eq!(ac2.balance(None).held, dec!(0));
+        let dispute = result
+            .accounts_store
+            .get_transactions(2)
+            .expect("Transactions should exist")
+            .last()
+            .expect("The dispute should exist");
+        assert_eq!(dispute.status, TransactionStatus::Skipped);
+    }
+
+    #[tokio::test]
+    async fn velocity_limits_test() {
+        let test_csv_path = "src/tests/velocity.csv";
+
+        let handler = ExecuteHandler {
+            limits: Limits {
+                global: WithdrawalLimits {
+                    max_withdrawal: Some(dec!(500)),
+                    max_daily_withdrawals: Some(2),
+                    max_withdrawn_24h: Some(dec!(1000)),
+                },
+                per_client: read_client_limits("src/tests/limits.csv")
+                    .expect("Expected valid limits"),
+            },
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // client 1 can withdraw more at once, but not more often
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).available, dec!(4050));
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert_eq!(ac2.balance(None).available, dec!(4920));
+
+        let summary = runner.summary();
+        assert_eq!(summary.processed, 8);
+        assert_eq!(summary.succeeded, 5);
+        for limit in [
+            Limit::MaxWithdrawal,
+            Limit::MaxDailyWithdrawals,
+            Limit::MaxWithdrawn24h,
+        ] {
+            assert_eq!(
+                summary.skipped.get(&SkipReason::LimitExceeded(limit)),
+                Some(&1)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn velocity_limits_captures_test() {
+        // with: a capture too large, then one after a withdrawal on the same day
+        let timestamp = "2025-01-15T10:00:00Z".parse().expect("Valid timestamp");
+        let row = |transaction_type, transaction_id, amount| {
+            Transaction::new(transaction_type, 1, transaction_id, amount).with_timestamp(timestamp)
+        };
+        let transactions = vec![
+            row(TransactionType::Deposit, 1, Some(dec!(1000))),
+            row(TransactionType::Authorize, 2, Some(dec!(600))),
+            row(TransactionType::Capture, 2, None),
+            row(TransactionType::Authorize, 3, Some(dec!(100))),
+            row(TransactionType::Capture, 3, None),
+            row(TransactionType::Withdrawal, 4, Some(dec!(100))),
+            row(TransactionType::Authorize, 5, Some(dec!(50))),
+            row(TransactionType::Capture, 5, None),
+        ];
+        let handler = ExecuteHandler {
+            limits: Limits {
+                global: WithdrawalLimits {
+                    max_withdrawal: Some(dec!(500)),
+                    max_daily_withdrawals: Some(2),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+
+        // when
+        let result = runner
+            .run_transactions(futures::stream::iter(
+                transactions.into_iter().map(Ok::<_, ()>),
+            ))
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // then: captures are limited like withdrawals, and counted with them
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).total(), dec!(800));
+
+        let summary = runner.summary();
+        for limit in [Limit::MaxWithdrawal, Limit::MaxDailyWithdrawals] {
+            assert_eq!(
+                summary.skipped.get(&SkipReason::LimitExceeded(limit)),
+                Some(&1)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fees_test() {
+        let test_csv_path = "src/tests/fees_transactions.csv";
+
+        let handler = ExecuteHandler {
+            fees: read_fee_schedule("src/tests/fees.csv", 99).expect("Expected valid fees"),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // 0.5 flat on tx 2, 0.1% on tx 3, nothing on the skipped tx 4
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).total(), dec!(488));
+        // the dispute holds the withdrawal, not its fee
+        assert_eq!(ac1.balance(None).held, dec!(10));
+        assert_eq!(
+            result
+                .accounts_store
+                .get_transactions(1)
+                .expect("Transactions should exist")
+                .iter()
+                .map(|t| (t.transaction_type.clone(), t.transaction_id))
+                .collect::<Vec<(TransactionType, TransactionId)>>(),
+            vec![
+                (TransactionType::Deposit, 1),
+                (TransactionType::Withdrawal, 2),
+                (TransactionType::Fee, 2),
+                (TransactionType::Withdrawal, 3),
+                (TransactionType::Fee, 3),
+                (TransactionType::Withdrawal, 4),
+                (TransactionType::Dispute, 2),
+            ]
+        );
+
+        // chargeback fees are charged even on a frozen, empty account
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert_eq!(ac2.balance(None).total(), dec!(-15));
+
+        let house = result
+            .accounts_store
+            .get(99)
+            .expect("Get should work")
+            .expect("House account should exist");
+        assert_eq!(house.balance(None).total(), dec!(17));
+        assert_eq!(
+            result
+                .accounts_store
+                .get_transactions(99)
+                .expect("Transactions should exist")
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn interest_test() {
+        let test_csv_path = "src/tests/interest.csv";
+
+        let mut interest = InterestPolicy::new(dec!(0.0001));
+        interest.at_end_of_run = true;
+        let handler = ExecuteHandler {
+            interest: Some(interest),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+        let result = runner
+            .run_from_csv(test_csv_path)
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // 0.1 on the accrue row, nothing worth posting at the end
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).available, dec!(0.1));
+
+        // 0.00125 rounds to 0.0012, then 0.00125012 to 0.0013
+        let ac2 = result
+            .accounts_store
+            .get(2)
+            .expect("Get should work")
+            .expect("Account 2 should exist");
+        assert_eq!(ac2.balance(None).available, dec!(12.5025));
+        assert_eq!(
+            result
+                .accounts_store
+                .get_transactions(2)
+                .expect("Transactions should exist")
+                .iter()
+                .map(|t| (t.transaction_type.clone(), t.transaction_id))
+                .collect::<Vec<(TransactionType, TransactionId)>>(),
+            vec![
+                (TransactionType::Deposit, 2),
+                (TransactionType::Deposit, DEFAULT_FIRST_TRANSACTION_ID + 1),
+                (TransactionType::Deposit, DEFAULT_FIRST_TRANSACTION_ID + 2),
+            ]
+        );
+
+        // frozen accounts don't earn anything
+        let ac3 = result
+            .accounts_store
+            .get(3)
+            .expect("Get should work")
+            .expect("Account 3 should exist");
+        assert_eq!(ac3.balance(None).total(), dec!(0));
+    }
+
+    #[tokio::test]
+    async fn reserved_transaction_ids_test() {
+        // with: interest deposits numbered from 100
+        let transactions = vec![
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(10))),
+            Transaction::new(TransactionType::Deposit, 1, 100, Some(dec!(10))),
+        ];
+        let handler = ExecuteHandler {
+            interest: Some(InterestPolicy::new(dec!(0.01)).starting_at(100)),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(handler, InMemoryAccountsStorage::default());
+
+        // when
+        let result = runner
+            .run_transactions(futures::stream::iter(
+                transactions.into_iter().map(Ok::<_, ()>),
+            ))
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // then: the row using a reserved id is skipped
+        let ac1 = result
+            .accounts_store
+            .get(1)
+            .expect("Get should work")
+            .expect("Account 1 should exist");
+        assert_eq!(ac1.balance(None).available, dec!(10));
+    }
+
+    #[tokio::test]
+    async fn dry_run_interest_ids_test() {
+        use crate::tren::handlers::pipeline::{Pipeline, Stage};
+
+        // sees the interest deposits of client 2 once accrued
+        struct Deposits(Arc<Mutex<Vec<TransactionId>>>);
+        impl<S: AccountsStorage> Stage<S> for Deposits {
+            fn after(
+                &mut self,
+                _transaction: &Transaction,
+                _outcome: &RunnerOutcome,
+                context: &mut RunnerContext<'_, S>,
+            ) -> Result<(), RunnerError> {
+                *self.0.lock().unwrap() = context
+                    .accounts_store
+                    .get_transactions(2)
+                    .expect("Transactions should exist")
+                    .iter()
+                    .map(|t| t.transaction_id)
+                    .collect();
+                Ok(())
+            }
+        }
+
+        // with: a run posting interest from 100
+        let handler = ExecuteHandler {
+            interest: Some(InterestPolicy::new(dec!(0.01)).starting_at(100)),
+            ..Default::default()
+        };
+        let mut runner = Runner::new(Pipeline::new(handler), InMemoryAccountsStorage::default());
+        runner
+            .run_from_csv("src/tests/interest.csv")
+            .await
+            .expect("Expected an Ok value from runner");
+
+        // when: accruing again in a dry run, carrying the interest policy over
+        let seen = Arc::new(Mutex::new(vec![]));
+        let mut dry_handler = Pipeline::new(ExecuteHandler {
+            interest: Some(InterestPolicy::new(dec!(0.01)).starting_at(100)),
+            ..Default::default()
+        });
+        dry_handler.inner_mut().interest = runner.handler().inner().interest;
+        dry_handler.add_stage(Deposits(Arc::clone(&seen)));
+        runner
+            .dry_run_from_csv(dry_handler, "src/tests/accrue.csv")
+            .await
+            .expect("Expected an Ok value from dry run");
+
+        // then: ids go on after the ones posted by the run, client 1 taking 102
+        assert_eq!(*seen.lock().unwrap(), vec![2, 101, 103]);
+    }
+
+    fn get_disputes_runner(
+        action: ExpiryAction,
+    ) -> Runner<ExecuteHandler, InMemoryAccountsStorage> {
+        let handler = ExecuteHandler {
+            disputes: DisputePolicy {
+                window: Some(TimeDelta::days(30)),
+                expiry: Some(DisputeExpiry {
+                    after: TimeDelta::days(7),
+                    action,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        Runner::new(handler, InMemoryAccountsStorage::default())
+    }
+
+    struct SharedCollector {
+        events: Arc<Mutex<Vec<EngineEvent>>>,
+    }
+
+    impl EventSubscriber for SharedCollector {
+        fn on_event(&mut self, event: &EngineEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
     }
 
     fn get_runner() -> Runner<CollectHandler, InMemoryAccountsStorage> {
@@ -420,7 +2554,7 @@ mod test {
     }
 
     fn get_executor_runner() -> Runner<ExecuteHandler, InMemoryAccountsStorage> {
-        let handler = ExecuteHandler {};
+        let handler = ExecuteHandler::default();
         let storage = InMemoryAccountsStorage::default();
 
         Runner::new(handler, storage)
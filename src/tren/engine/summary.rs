@@ -0,0 +1,73 @@
+// Counters of what happened during a run, to check a batch at a glance
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use super::runner::{RunnerOutcome, SkipReason};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunSummary {
+    /// transactions handled, whatever their outcome
+    pub processed: usize,
+    pub succeeded: usize,
+    /// skipped transactions, by reason
+    pub skipped: BTreeMap<SkipReason, usize>,
+}
+
+impl RunSummary {
+    /// Count a handled transaction
+    pub fn record(&mut self, outcome: &RunnerOutcome) {
+        self.processed += 1;
+        match outcome {
+            RunnerOutcome::Success => self.succeeded += 1,
+            RunnerOutcome::Skipped(reason) => *self.skipped.entry(reason.clone()).or_default() += 1,
+        }
+    }
+
+    /// Number of transactions skipped, for any reason
+    #[must_use]
+    pub fn skipped_total(&self) -> usize {
+        self.skipped.values().sum()
+    }
+}
+
+impl Display for RunSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "processed: {}", self.processed)?;
+        writeln!(f, "succeeded: {}", self.succeeded)?;
+        write!(f, "skipped: {}", self.skipped_total())?;
+        for (reason, count) in &self.skipped {
+            write!(f, "\n  {reason}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::handlers::limits::Limit;
+
+    #[test]
+    fn record_test() {
+        // with
+        let mut summary = RunSummary::default();
+
+        // when
+        summary.record(&RunnerOutcome::Success);
+        summary.record(&RunnerOutcome::Skipped(SkipReason::NotEnoughFunds));
+        summary.record(&RunnerOutcome::Skipped(SkipReason::LimitExceeded(
+            Limit::MaxWithdrawal,
+        )));
+        summary.record(&RunnerOutcome::Skipped(SkipReason::NotEnoughFunds));
+
+        // then
+        assert_eq!(summary.processed, 4);
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.skipped_total(), 3);
+        assert_eq!(
+            summary.to_string(),
+            "processed: 4\nsucceeded: 1\nskipped: 3\n  not_enough_funds: 2\n  limit_exceeded (max_withdrawal): 1"
+        );
+    }
+}
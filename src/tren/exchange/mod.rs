@@ -0,0 +1,2 @@
+pub mod rate_table;
+pub mod rates_reader;
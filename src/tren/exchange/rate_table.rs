@@ -0,0 +1,105 @@
+// Exchange rates between currencies, each effective from some point in time on
+// rates are explicit: knowing EUR -> USD does not mean knowing USD -> EUR
+
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::tren::transactions::{Currency, Timestamp};
+
+/// How many units of `to` one unit of `from` is worth, starting at `effective`
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Rate {
+    pub from: Currency,
+    pub to: Currency,
+    pub rate: Decimal,
+    pub effective: Timestamp,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RateTable {
+    // ordered by effective time
+    rates: HashMap<(Currency, Currency), Vec<(Timestamp, Decimal)>>,
+}
+
+impl RateTable {
+    #[must_use]
+    pub fn new(rates: impl IntoIterator<Item = Rate>) -> Self {
+        let mut table = RateTable::default();
+        for rate in rates {
+            table.add(rate);
+        }
+        table
+    }
+
+    /// Add a rate. A rate with the same currencies and effective time replaces the previous one
+    pub fn add(&mut self, rate: Rate) {
+        let history = self.rates.entry((rate.from, rate.to)).or_default();
+        match history.binary_search_by_key(&rate.effective, |(effective, _)| *effective) {
+            Ok(index) => history[index].1 = rate.rate,
+            Err(index) => history.insert(index, (rate.effective, rate.rate)),
+        }
+    }
+
+    /// Rate to convert `from` into `to` effective `at` that time, or the latest known one
+    /// for transactions without a time
+    #[must_use]
+    pub fn rate(&self, from: &Currency, to: &Currency, at: Option<Timestamp>) -> Option<Decimal> {
+        let history = self.rates.get(&(from.clone(), to.clone()))?;
+        match at {
+            Some(at) => history
+                .iter()
+                .rev()
+                .find(|(effective, _)| *effective <= at)
+                .map(|(_, rate)| *rate),
+            None => history.last().map(|(_, rate)| *rate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn at(timestamp: &str) -> Timestamp {
+        timestamp.parse().expect("Valid timestamp")
+    }
+
+    fn rate(rate: Decimal, effective: &str) -> Rate {
+        Rate {
+            from: Currency::from("EUR"),
+            to: Currency::from("USD"),
+            rate,
+            effective: at(effective),
+        }
+    }
+
+    #[test]
+    fn effective_rate_test() {
+        // with
+        let table = RateTable::new(vec![
+            rate(dec!(1.2), "2025-02-01T00:00:00Z"),
+            rate(dec!(1.1), "2025-01-01T00:00:00Z"),
+        ]);
+        let eur = Currency::from("EUR");
+        let usd = Currency::from("USD");
+
+        // then
+        assert_eq!(
+            table.rate(&eur, &usd, Some(at("2024-12-31T00:00:00Z"))),
+            None
+        );
+        assert_eq!(
+            table.rate(&eur, &usd, Some(at("2025-01-15T00:00:00Z"))),
+            Some(dec!(1.1))
+        );
+        assert_eq!(
+            table.rate(&eur, &usd, Some(at("2025-02-01T00:00:00Z"))),
+            Some(dec!(1.2))
+        );
+        assert_eq!(table.rate(&eur, &usd, None), Some(dec!(1.2)));
+        assert_eq!(table.rate(&usd, &eur, None), None);
+    }
+}
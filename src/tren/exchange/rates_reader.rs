@@ -0,0 +1,66 @@
+// Read exchange rates from a local csv file, e.g.
+// from, to,  rate,   effective
+// EUR,  USD, 1.0850, 2025-01-01T00:00:00Z
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use super::rate_table::{Rate, RateTable};
+use crate::tren::inputs::csv_reader::{read_csv, CsvReaderError};
+
+#[derive(Debug, Error)]
+pub enum RatesReaderError {
+    #[error(transparent)]
+    Csv(#[from] CsvReaderError),
+    #[error("Invalid rate [{0}]")]
+    InvalidRate(String),
+}
+
+/// Read a rate table from a csv with `from, to, rate, effective` columns
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or a row is not a valid rate. Rates must be
+/// above zero, otherwise exchanges would destroy money, or create some going back
+pub fn read_rates(path: &str) -> Result<RateTable, RatesReaderError> {
+    let rates = read_csv::<Rate>(path)?;
+
+    if let Some(rate) = rates.iter().find(|rate| rate.rate <= Decimal::ZERO) {
+        return Err(RatesReaderError::InvalidRate(format!(
+            "{} -> {}: {}",
+            rate.from, rate.to, rate.rate
+        )));
+    }
+
+    Ok(RateTable::new(rates))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::transactions::Currency;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn read_rates_test() {
+        let table = read_rates("src/tests/rates.csv").expect("Expected valid rates");
+
+        assert_eq!(
+            table.rate(&Currency::from("EUR"), &Currency::from("USD"), None),
+            Some(dec!(1.1))
+        );
+    }
+
+    #[test]
+    fn read_invalid_rates_test() {
+        assert!(matches!(
+            read_rates("src/tests/invalid_rates.csv"),
+            Err(RatesReaderError::InvalidRate(_))
+        ));
+    }
+
+    #[test]
+    fn read_missing_file_test() {
+        assert!(read_rates("src/tests/does_not_exist.csv").is_err());
+    }
+}
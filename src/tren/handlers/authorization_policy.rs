@@ -0,0 +1,41 @@
+// Time rules for card authorizations. Like disputes, they only apply to authorizations with
+// timestamps
+
+use chrono::TimeDelta;
+
+use crate::tren::transactions::Timestamp;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AuthorizationPolicy {
+    /// authorizations neither captured nor voided for longer than this are voided
+    pub expiry: Option<TimeDelta>,
+}
+
+impl AuthorizationPolicy {
+    /// Has an authorization made at `authorized` expired at `now`?
+    #[must_use]
+    pub fn expired(&self, authorized: Timestamp, now: Timestamp) -> bool {
+        self.expiry.is_some_and(|expiry| now - authorized > expiry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(timestamp: &str) -> Timestamp {
+        timestamp.parse().expect("Valid timestamp")
+    }
+
+    #[test]
+    fn expiry_test() {
+        let policy = AuthorizationPolicy {
+            expiry: Some(TimeDelta::days(7)),
+        };
+
+        assert!(!policy.expired(at("2025-01-01T00:00:00Z"), at("2025-01-08T00:00:00Z")));
+        assert!(policy.expired(at("2025-01-01T00:00:00Z"), at("2025-01-08T00:00:01Z")));
+        assert!(!AuthorizationPolicy::default()
+            .expired(at("2025-01-01T00:00:00Z"), at("2026-01-01T00:00:00Z")));
+    }
+}
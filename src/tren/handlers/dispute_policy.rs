@@ -0,0 +1,117 @@
+// Time rules for disputes. They only apply to transactions with timestamps: without a
+// notion of time a dispute can't be too late
+
+use chrono::TimeDelta;
+
+use crate::tren::transactions::{Timestamp, Transaction};
+
+/// What happens to a dispute left open for too long
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpiryAction {
+    Resolve,
+    Chargeback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisputeExpiry {
+    /// how long a dispute can stay open
+    pub after: TimeDelta,
+    pub action: ExpiryAction,
+}
+
+/// What happens to a dispute holding more than what is left of the credit line
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OverdrawnDisputes {
+    /// hold the funds anyway, the balance goes below the credit limit
+    #[default]
+    Hold,
+    /// skip the dispute
+    Skip,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DisputePolicy {
+    /// disputes on transactions older than this are refused
+    pub window: Option<TimeDelta>,
+    /// disputes open for longer than this are closed automatically
+    pub expiry: Option<DisputeExpiry>,
+    pub overdrawn: OverdrawnDisputes,
+}
+
+impl DisputePolicy {
+    /// Is `dispute` too late to dispute `original`?
+    #[must_use]
+    pub fn out_of_window(&self, original: &Transaction, dispute: &Transaction) -> bool {
+        match (self.window, original.timestamp, dispute.timestamp) {
+            (Some(window), Some(happened), Some(disputed)) => disputed - happened > window,
+            _ => false,
+        }
+    }
+
+    /// Has a dispute opened at `opened` expired at `now`?
+    #[must_use]
+    pub fn expired(&self, opened: Timestamp, now: Timestamp) -> Option<ExpiryAction> {
+        self.expiry
+            .filter(|expiry| now - opened > expiry.after)
+            .map(|expiry| expiry.action)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::transactions::TransactionType;
+    use rust_decimal_macros::dec;
+
+    fn at(timestamp: &str) -> Timestamp {
+        timestamp.parse().expect("Valid timestamp")
+    }
+
+    #[test]
+    fn window_test() {
+        let policy = DisputePolicy {
+            window: Some(TimeDelta::days(30)),
+            expiry: None,
+            ..Default::default()
+        };
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(1)))
+            .with_timestamp(at("2025-01-01T00:00:00Z"));
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+
+        assert!(!policy.out_of_window(&deposit, &dispute));
+        assert!(!policy.out_of_window(
+            &deposit,
+            &dispute.clone().with_timestamp(at("2025-01-31T00:00:00Z"))
+        ));
+        assert!(policy.out_of_window(
+            &deposit,
+            &dispute.with_timestamp(at("2025-01-31T00:00:01Z"))
+        ));
+    }
+
+    #[test]
+    fn expiry_test() {
+        let policy = DisputePolicy {
+            window: None,
+            expiry: Some(DisputeExpiry {
+                after: TimeDelta::days(7),
+                action: ExpiryAction::Chargeback,
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            policy.expired(at("2025-01-01T00:00:00Z"), at("2025-01-08T00:00:00Z")),
+            None
+        );
+        assert_eq!(
+            policy.expired(at("2025-01-01T00:00:00Z"), at("2025-01-09T00:00:00Z")),
+            Some(ExpiryAction::Chargeback)
+        );
+        assert_eq!(
+            DisputePolicy::default()
+                .expired(at("2025-01-01T00:00:00Z"), at("2026-01-01T00:00:00Z")),
+            None
+        );
+    }
+}
@@ -1,12 +1,53 @@
-use crate::tren::account::{Account, AccountOperationError};
+use chrono::TimeDelta;
+
+use crate::tren::account::{Account, AccountOperationError, AccountStatus, AccountStatusError};
 use crate::tren::engine::context::RunnerContext;
+use crate::tren::engine::events::EngineEvent;
 // This is the "real" default executor for production environment
-use crate::tren::engine::runner::{RunnerError, RunnerOutcome};
+use crate::tren::client::ClientId;
+use crate::tren::engine::runner::{RunnerError, RunnerOutcome, SkipReason};
+use crate::tren::exchange::rate_table::RateTable;
+use crate::tren::handlers::authorization_policy::AuthorizationPolicy;
+use crate::tren::handlers::dispute_policy::{DisputePolicy, ExpiryAction, OverdrawnDisputes};
+use crate::tren::handlers::fees::FeeSchedule;
+use crate::tren::handlers::interest::InterestPolicy;
+use crate::tren::handlers::limits::{Limit, Limits};
 use crate::tren::handlers::transaction_handler::TransactionHandler;
-use crate::tren::storage::store::AccountsStorage;
-use crate::tren::transactions::{Transaction, TransactionStatus, TransactionType};
+use crate::tren::ids;
+use crate::tren::storage::store::{AccountsScope, AccountsStorage};
+use crate::tren::transactions::{
+    Currency, Timestamp, Transaction, TransactionId, TransactionStatus, TransactionType,
+};
 
-pub struct ExecuteHandler {}
+/// What to do with a row older than the latest one seen for the same client
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutOfOrderPolicy {
+    /// process it anyway
+    Accept,
+    /// skip it, like any other transaction that does not make sense
+    #[default]
+    Skip,
+    /// stop the whole run
+    Fail,
+}
+
+#[derive(Default)]
+pub struct ExecuteHandler {
+    pub out_of_order: OutOfOrderPolicy,
+    pub disputes: DisputePolicy,
+    /// rates for exchanges, none by default
+    pub rates: RateTable,
+    /// velocity limits on withdrawals, none by default
+    pub limits: Limits,
+    /// fees charged on executed transactions, none by default
+    pub fees: FeeSchedule,
+    /// interest on positive balances, none by default
+    pub interest: Option<InterestPolicy>,
+    /// expiry of authorizations neither captured nor voided, never by default
+    pub authorizations: AuthorizationPolicy,
+    /// operational accounts without any activity for longer than this fall dormant, never by default
+    pub dormant_after: Option<TimeDelta>,
+}
 
 impl<S: AccountsStorage> TransactionHandler<S> for ExecuteHandler {
     fn handle(
@@ -14,6 +55,42 @@ impl<S: AccountsStorage> TransactionHandler<S> for ExecuteHandler {
         mut transaction: Transaction,
         context: &mut RunnerContext<'_, S>,
     ) -> Result<RunnerOutcome, RunnerError> {
+        // control rows don't belong to any account
+        if let (TransactionType::Tick, Some(now)) =
+            (&transaction.transaction_type, transaction.timestamp)
+        {
+            return self.handle_tick(now, context);
+        }
+        if transaction.transaction_type == TransactionType::Accrue {
+            return self.accrue(transaction.timestamp, context);
+        }
+
+        // rows bringing an id of their own can't take one of interest deposits
+        if !transaction.is_disputing()
+            && self
+                .interest
+                .as_ref()
+                .is_some_and(|interest| interest.reserves(transaction.transaction_id))
+        {
+            let reason = SkipReason::ReservedTransactionId;
+            context.events.emit(EngineEvent::TransactionSkipped {
+                client: transaction.client_id,
+                tx: transaction.transaction_id,
+                reason: reason.clone(),
+            });
+            return Ok(RunnerOutcome::Skipped(reason));
+        }
+
+        // accounts are only opened on purpose
+        if let Some(reason) = Self::check_opening(&transaction, context)? {
+            context.events.emit(EngineEvent::TransactionSkipped {
+                client: transaction.client_id,
+                tx: transaction.transaction_id,
+                reason: reason.clone(),
+            });
+            return Ok(RunnerOutcome::Skipped(reason));
+        }
+
         // Functional style clone to avoid holding &mut borrow on store while we need to call
         // other store methods (push_transaction, find_non_disputing_transaction_mut)
         // Account is small, so clone overhead is negligible.
@@ -28,28 +105,51 @@ impl<S: AccountsStorage> TransactionHandler<S> for ExecuteHandler {
         // println!("------");
         // println!("{:?}", account);
 
-        // if the account is locked, let's ignore the operation
-        if account.frozen() {
-            return Ok(RunnerOutcome::Skipped);
+        // reservations expire whatever the status of the account
+        if let Some(now) = transaction.timestamp {
+            self.expire_authorizations(&mut account, now, context);
+            self.fall_dormant(&mut account, now, context);
         }
 
-        let result = match transaction.transaction_type {
-            TransactionType::Deposit => Self::handle_deposit(&mut account, &transaction),
-            TransactionType::Withdrawal => Self::handle_withdrawal(&mut account, &transaction),
-            TransactionType::Dispute => Self::handle_dispute(&mut account, &transaction, context),
-            TransactionType::Resolve => Self::handle_resolve(&mut account, &transaction, context),
-            TransactionType::Chargeback => {
-                Self::handle_chargeback(&mut account, &transaction, context)
-            }
+        // if the account is locked, closed or can't do this, let's ignore the operation
+        if let Some(reason) = Self::check_status(&account, &transaction.transaction_type) {
+            context.events.emit(EngineEvent::TransactionSkipped {
+                client: transaction.client_id,
+                tx: transaction.transaction_id,
+                reason: reason.clone(),
+            });
+            // it may have just fallen dormant
+            context
+                .accounts_store
+                .put(account)
+                .map_err(|_| RunnerError::StorageError)?;
+            return Ok(RunnerOutcome::Skipped(reason));
+        }
+
+        let result = match self.check_ordering(&mut account, &transaction)? {
+            Some(skipped) => skipped,
+            None => self.expire_and_execute(&mut account, &mut transaction, context),
         };
 
         // maybe update a skipped transaction
         Self::update_transaction(&mut transaction, &result);
+        if let RunnerOutcome::Skipped(reason) = &result {
+            context.events.emit(EngineEvent::TransactionSkipped {
+                client: transaction.client_id,
+                tx: transaction.transaction_id,
+                reason: reason.clone(),
+            });
+        }
 
         // Add transaction to account's log
+        let executed = (result == RunnerOutcome::Success).then(|| transaction.clone());
         context
             .accounts_store
             .push_transaction(account.client_id, transaction);
+        // fees come right after what they are charged for
+        if let Some(transaction) = executed {
+            self.charge_fee(&mut account, &transaction, context)?;
+        }
         // println!("{:?}", account);
         // Update Account in storage
         context
@@ -59,40 +159,631 @@ impl<S: AccountsStorage> TransactionHandler<S> for ExecuteHandler {
 
         Ok(result)
     }
+
+    fn scope(&self, transaction: &Transaction) -> AccountsScope {
+        match &transaction.transaction_type {
+            // control rows go through all the accounts
+            TransactionType::Tick | TransactionType::Accrue => AccountsScope::All,
+            // fees are credited to the house account
+            transaction_type if self.fees.charges(transaction_type) => {
+                AccountsScope::Clients(vec![transaction.client_id, self.fees.house_account])
+            }
+            _ => AccountsScope::client(transaction.client_id),
+        }
+    }
+
+    fn finish_scope(&self) -> AccountsScope {
+        if self.interest.is_some_and(|interest| interest.at_end_of_run) {
+            AccountsScope::All
+        } else {
+            AccountsScope::none()
+        }
+    }
+
+    fn finish(&mut self, context: &mut RunnerContext<'_, S>) -> Result<(), RunnerError> {
+        if self.interest.is_some_and(|interest| interest.at_end_of_run) {
+            self.accrue(None, context)?;
+        }
+        Ok(())
+    }
 }
 
 impl ExecuteHandler {
+    fn execute<S: AccountsStorage>(
+        &self,
+        account: &mut Account,
+        transaction: &mut Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        match transaction.transaction_type {
+            TransactionType::Deposit => Self::handle_deposit(account, transaction, context),
+            TransactionType::Withdrawal => {
+                if let Some(limit) = self.breached_limit(transaction, context) {
+                    return RunnerOutcome::Skipped(SkipReason::LimitExceeded(limit));
+                }
+                Self::handle_withdrawal(account, transaction, context)
+            }
+            TransactionType::Dispute => self.handle_dispute(account, transaction, context),
+            TransactionType::Resolve => Self::handle_resolve(account, transaction, context),
+            TransactionType::Chargeback => Self::handle_chargeback(account, transaction, context),
+            TransactionType::Reversal => Self::handle_reversal(account, transaction, context),
+            TransactionType::Authorize => Self::handle_authorize(account, transaction, context),
+            TransactionType::Capture => {
+                // money leaves the account when a payment is captured, not when it is authorized
+                if let Some(limit) = Self::captured(transaction, context)
+                    .and_then(|capture| self.breached_limit(&capture, context))
+                {
+                    return RunnerOutcome::Skipped(SkipReason::LimitExceeded(limit));
+                }
+                Self::handle_capture(account, transaction, context)
+            }
+            TransactionType::Void => Self::handle_void(account, transaction, context),
+            TransactionType::Open => {
+                context.events.emit(EngineEvent::AccountOpened {
+                    client: account.client_id,
+                });
+                RunnerOutcome::Success
+            }
+            TransactionType::Suspend => {
+                Self::handle_status_change(account, AccountStatus::Suspended, context)
+            }
+            TransactionType::Activate => {
+                Self::handle_status_change(account, AccountStatus::Operational, context)
+            }
+            TransactionType::Close => {
+                Self::handle_status_change(account, AccountStatus::Closed, context)
+            }
+            TransactionType::Exchange => self.handle_exchange(account, transaction, context),
+            TransactionType::CreditLimit => {
+                Self::handle_credit_limit(account, transaction, context)
+            }
+            // ticks and accruals are handled before getting to an account, nothing to do
+            TransactionType::Tick | TransactionType::Accrue => RunnerOutcome::Success,
+            // fees are never submitted, see `Transaction::validate`
+            TransactionType::Fee => RunnerOutcome::Skipped(SkipReason::InvalidTransactionState),
+        }
+    }
+
+    /// The velocity limit `withdrawal` would breach, given the previous transactions of the client
+    fn breached_limit<S: AccountsStorage>(
+        &self,
+        withdrawal: &Transaction,
+        context: &RunnerContext<'_, S>,
+    ) -> Option<Limit> {
+        self.limits.breach(
+            withdrawal,
+            context
+                .accounts_store
+                .get_transactions(withdrawal.client_id)
+                .map_or(&[], Vec::as_slice),
+        )
+    }
+
+    /// The capture row as it would be recorded: the amount and the currency taken from the
+    /// authorization. None if there is no authorization to capture
+    fn captured<S: AccountsStorage>(
+        transaction: &Transaction,
+        context: &RunnerContext<'_, S>,
+    ) -> Option<Transaction> {
+        let authorization = context
+            .accounts_store
+            .find_non_disputing_transaction(transaction.client_id, transaction.transaction_id)
+            .filter(|authorization| authorization.transaction_type == TransactionType::Authorize)?;
+        let mut capture = transaction.clone();
+        capture.amount = transaction.amount.or(authorization.amount);
+        capture.currency.clone_from(&authorization.currency);
+        Some(capture)
+    }
+
+    /// Close the disputes expired by the time the transaction happened, then execute it
+    fn expire_and_execute<S: AccountsStorage>(
+        &self,
+        account: &mut Account,
+        transaction: &mut Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        if let Some(now) = transaction.timestamp {
+            self.expire_disputes(account, now, context);
+        }
+
+        // an expired dispute may have been charged back
+        if let Some(reason) = Self::check_status(account, &transaction.transaction_type) {
+            RunnerOutcome::Skipped(reason)
+        } else {
+            self.execute(account, transaction, context)
+        }
+    }
+
+    /// Time went by for everyone: close the expired disputes and authorizations of all the
+    /// accounts, and the inactive ones fall dormant
+    fn handle_tick<S: AccountsStorage>(
+        &self,
+        now: Timestamp,
+        context: &mut RunnerContext<'_, S>,
+    ) -> Result<RunnerOutcome, RunnerError> {
+        if self.disputes.expiry.is_none()
+            && self.authorizations.expiry.is_none()
+            && self.dormant_after.is_none()
+        {
+            return Ok(RunnerOutcome::Success);
+        }
+
+        let client_ids = context
+            .accounts_store
+            .all_accounts_iter()
+            .map(|account| account.client_id)
+            .collect::<Vec<ClientId>>();
+
+        for client_id in client_ids {
+            let mut account = context
+                .accounts_store
+                .get_or_create(client_id)
+                .map_err(|_| RunnerError::StorageError)?
+                .clone();
+            // reservations expire whatever the status of the account
+            self.expire_authorizations(&mut account, now, context);
+            if !account.frozen() {
+                self.expire_disputes(&mut account, now, context);
+            }
+            self.fall_dormant(&mut account, now, context);
+            context
+                .accounts_store
+                .put(account)
+                .map_err(|_| RunnerError::StorageError)?;
+        }
+
+        Ok(RunnerOutcome::Success)
+    }
+
+    /// Post interest on the positive available balances of all the operational accounts,
+    /// as deposits with ids from the interest range
+    fn accrue<S: AccountsStorage>(
+        &mut self,
+        timestamp: Option<Timestamp>,
+        context: &mut RunnerContext<'_, S>,
+    ) -> Result<RunnerOutcome, RunnerError> {
+        let Some(interest) = self.interest.as_mut() else {
+            return Ok(RunnerOutcome::Success);
+        };
+
+        // sorted, so that the same input always gets the same ids
+        let mut client_ids = context
+            .accounts_store
+            .all_accounts_iter()
+            .filter(|account| account.status == AccountStatus::Operational)
+            .map(|account| account.client_id)
+            .collect::<Vec<ClientId>>();
+        client_ids.sort_unstable();
+
+        for client_id in client_ids {
+            let mut account = context
+                .accounts_store
+                .get_or_create(client_id)
+                .map_err(|_| RunnerError::StorageError)?
+                .clone();
+            let postings = account
+                .per_currency()
+                .into_iter()
+                .filter_map(|(currency, balance)| {
+                    interest
+                        .interest(balance.available)
+                        .map(|amount| (currency.cloned(), amount))
+                })
+                .collect::<Vec<(Option<Currency>, _)>>();
+
+            for (currency, amount) in postings {
+                let mut posting = Transaction::new(
+                    TransactionType::Deposit,
+                    client_id,
+                    interest.take_transaction_id()?,
+                    Some(amount),
+                );
+                posting.currency = currency;
+                posting.timestamp = timestamp;
+                Self::handle_deposit(&mut account, &posting, context);
+                posting.executed();
+                context.accounts_store.push_transaction(client_id, posting);
+            }
+
+            context
+                .accounts_store
+                .put(account)
+                .map_err(|_| RunnerError::StorageError)?;
+        }
+
+        Ok(RunnerOutcome::Success)
+    }
+
+    /// Close the disputes of an account that have been open for too long at `now`,
+    /// as if a resolve/chargeback row had been received for each of them
+    fn expire_disputes<S: AccountsStorage>(
+        &self,
+        account: &mut Account,
+        now: Timestamp,
+        context: &mut RunnerContext<'_, S>,
+    ) {
+        if self.disputes.expiry.is_none() {
+            return;
+        }
+        let Some(transactions) = context.accounts_store.get_transactions(account.client_id) else {
+            return;
+        };
+
+        // a transaction may have been disputed, resolved and disputed again: the latest dispute counts
+        let mut expired = transactions
+            .iter()
+            .filter(|transaction| {
+                !transaction.is_disputing() && transaction.status == TransactionStatus::Disputed
+            })
+            .filter_map(|transaction| {
+                self.disputes
+                    .expired(transaction.disputed_at?, now)
+                    .map(|action| (transaction.transaction_id, action))
+            })
+            .collect::<Vec<(TransactionId, ExpiryAction)>>();
+        expired.sort_by_key(|(transaction_id, _)| *transaction_id);
+
+        for (transaction_id, action) in expired {
+            let mut closing = Transaction::new(
+                match action {
+                    ExpiryAction::Resolve => TransactionType::Resolve,
+                    ExpiryAction::Chargeback => TransactionType::Chargeback,
+                },
+                account.client_id,
+                transaction_id,
+                None,
+            )
+            .with_timestamp(now);
+
+            let outcome = match action {
+                ExpiryAction::Resolve => Self::handle_resolve(account, &mut closing, context),
+                ExpiryAction::Chargeback => Self::handle_chargeback(account, &mut closing, context),
+            };
+            Self::update_transaction(&mut closing, &outcome);
+            context
+                .accounts_store
+                .push_transaction(account.client_id, closing);
+
+            // no more operations on a frozen account
+            if account.frozen() {
+                break;
+            }
+        }
+    }
+
+    /// Void the authorizations of an account that have been neither captured nor voided for
+    /// too long at `now`, as if a void row had been received for each of them
+    fn expire_authorizations<S: AccountsStorage>(
+        &self,
+        account: &mut Account,
+        now: Timestamp,
+        context: &mut RunnerContext<'_, S>,
+    ) {
+        if self.authorizations.expiry.is_none() {
+            return;
+        }
+        let Some(transactions) = context.accounts_store.get_transactions(account.client_id) else {
+            return;
+        };
+
+        let mut expired = transactions
+            .iter()
+            .filter(|transaction| {
+                transaction.transaction_type == TransactionType::Authorize
+                    && transaction.status == TransactionStatus::Executed
+                    && transaction
+                        .timestamp
+                        .is_some_and(|authorized| self.authorizations.expired(authorized, now))
+            })
+            .map(|transaction| transaction.transaction_id)
+            .collect::<Vec<TransactionId>>();
+        expired.sort_unstable();
+
+        for transaction_id in expired {
+            let mut void = Transaction::new(
+                TransactionType::Void,
+                account.client_id,
+                transaction_id,
+                None,
+            )
+            .with_timestamp(now);
+            let outcome = Self::handle_void(account, &mut void, context);
+            Self::update_transaction(&mut void, &outcome);
+            context
+                .accounts_store
+                .push_transaction(account.client_id, void);
+        }
+    }
+
+    /// Why a row must not get to an account, if it mustn't. Rows referring to a previous
+    /// transaction never open an account, since there can't be anything to refer to, and when
+    /// only known clients are allowed accounts are only opened for them or by `open` rows.
+    /// `open` rows don't get to accounts that are already there
+    fn check_opening<S: AccountsStorage>(
+        transaction: &Transaction,
+        context: &RunnerContext<'_, S>,
+    ) -> Result<Option<SkipReason>, RunnerError> {
+        let exists = context
+            .accounts_store
+            .get(transaction.client_id)
+            .map_err(|_| RunnerError::StorageError)?
+            .is_some();
+
+        Ok(match (exists, &transaction.transaction_type) {
+            (true, TransactionType::Open) => Some(SkipReason::AccountExists),
+            (true, _) | (false, TransactionType::Open) => None,
+            (false, _) if transaction.is_disputing() => Some(SkipReason::TransactionNotFound),
+            (false, _) => (!context.clients.admits(transaction.client_id))
+                .then_some(SkipReason::UnknownClient),
+        })
+    }
+
+    /// An operational account without any activity for too long at `now` falls dormant
+    fn fall_dormant<S: AccountsStorage>(
+        &self,
+        account: &mut Account,
+        now: Timestamp,
+        context: &mut RunnerContext<'_, S>,
+    ) {
+        let (Some(dormant_after), Some(last_activity)) =
+            (self.dormant_after, account.last_activity)
+        else {
+            return;
+        };
+        if account.status == AccountStatus::Operational
+            && now - last_activity > dormant_after
+            && account.transition(AccountStatus::Dormant).is_ok()
+        {
+            context.events.emit(EngineEvent::AccountStatusChanged {
+                client: account.client_id,
+                status: AccountStatus::Dormant,
+            });
+        }
+    }
+
+    /// Why the status of the account does not allow a transaction, if it doesn't.
+    /// Admin rows may activate again or close frozen and dormant accounts, suspended ones
+    /// only refuse what would take funds out of them
+    fn check_status(account: &Account, transaction_type: &TransactionType) -> Option<SkipReason> {
+        let admin = matches!(
+            transaction_type,
+            TransactionType::Activate | TransactionType::Close
+        );
+        match account.status {
+            AccountStatus::Operational => None,
+            AccountStatus::Frozen => (!admin).then_some(SkipReason::AccountFrozen),
+            AccountStatus::Dormant => (!admin).then_some(SkipReason::AccountDormant),
+            AccountStatus::Closed => Some(SkipReason::AccountClosed),
+            AccountStatus::Suspended => matches!(
+                transaction_type,
+                TransactionType::Withdrawal
+                    | TransactionType::Authorize
+                    | TransactionType::Capture
+                    | TransactionType::Exchange
+            )
+            .then_some(SkipReason::AccountSuspended),
+        }
+    }
+
+    /// Timestamps must not go back in time for a client. Rows without a timestamp
+    /// are always in order, since there's nothing to compare
+    /// Returns the outcome for a row that shall not be executed
+    fn check_ordering(
+        &self,
+        account: &mut Account,
+        transaction: &Transaction,
+    ) -> Result<Option<RunnerOutcome>, RunnerError> {
+        let Some(timestamp) = transaction.timestamp else {
+            return Ok(None);
+        };
+
+        match account.last_activity {
+            Some(last_activity) if timestamp < last_activity => match self.out_of_order {
+                OutOfOrderPolicy::Accept => Ok(None),
+                OutOfOrderPolicy::Skip => Ok(Some(RunnerOutcome::Skipped(SkipReason::OutOfOrder))),
+                OutOfOrderPolicy::Fail => Err(RunnerError::OutOfOrder(format!(
+                    "Transaction [{}] of client [{}] at [{timestamp}] is older than [{last_activity}]",
+                    ids::display(transaction.transaction_id),
+                    ids::display(transaction.client_id)
+                ))),
+            },
+            _ => {
+                account.last_activity = Some(timestamp);
+                Ok(None)
+            }
+        }
+    }
+
     fn update_transaction(transaction: &mut Transaction, outcome: &RunnerOutcome) {
         match outcome {
-            RunnerOutcome::Skipped => transaction.skipped(),
+            RunnerOutcome::Skipped(_) => transaction.skipped(),
             RunnerOutcome::Success => transaction.executed(),
         }
     }
 
-    fn handle_deposit(account: &mut Account, transaction: &Transaction) -> RunnerOutcome {
+    fn handle_deposit<S: AccountsStorage>(
+        account: &mut Account,
+        transaction: &Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
         // This is a bit of an oversimplification: transaction are validated and this invariant is upheld
         // However we may want a Result here
-        account.deposit(transaction.amount.expect("Invalid transaction found"));
+        let amount = transaction.amount.expect("Invalid transaction found");
+        account
+            .balance_mut(transaction.currency.as_ref())
+            .deposit(amount);
+        context.events.emit(EngineEvent::Deposited {
+            client: account.client_id,
+            tx: transaction.transaction_id,
+            amount,
+            currency: transaction.currency.clone(),
+        });
         RunnerOutcome::Success
     }
 
-    fn handle_withdrawal(account: &mut Account, transaction: &Transaction) -> RunnerOutcome {
+    fn handle_withdrawal<S: AccountsStorage>(
+        account: &mut Account,
+        transaction: &Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
         // This is a bit of an oversimplification: transaction are validated and this invariant is upheld
         // However we may want a Result here
         let amount_to_withdraw = transaction.amount.expect("Invalid transaction found");
 
-        match account.withdraw(amount_to_withdraw) {
-            Err(AccountOperationError::NotEnoughFunds) => RunnerOutcome::Skipped,
-            Ok(()) => RunnerOutcome::Success,
+        match account
+            .balance_mut(transaction.currency.as_ref())
+            .withdraw(amount_to_withdraw)
+        {
+            Err(AccountOperationError::NotEnoughFunds) => {
+                RunnerOutcome::Skipped(SkipReason::NotEnoughFunds)
+            }
+            Err(AccountOperationError::InvalidAmount) => {
+                RunnerOutcome::Skipped(SkipReason::InvalidTransactionState)
+            }
+            Ok(()) => {
+                context.events.emit(EngineEvent::Withdrawn {
+                    client: account.client_id,
+                    tx: transaction.transaction_id,
+                    amount: amount_to_withdraw,
+                    currency: transaction.currency.clone(),
+                });
+                RunnerOutcome::Success
+            }
         }
     }
 
-    /// a previous transaction is being disputed. Funds will be held
-    /// only executed transactions may be disputed
-    fn handle_dispute<S: AccountsStorage>(
+    /// Charge the fee of an executed transaction, if any, crediting it to the house account.
+    /// Fees are charged even if they bring the balance below zero
+    fn charge_fee<S: AccountsStorage>(
+        &self,
+        account: &mut Account,
+        transaction: &Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> Result<(), RunnerError> {
+        // the house does not pay itself
+        let house_account = self.fees.house_account;
+        if account.client_id == house_account {
+            return Ok(());
+        }
+
+        // executed chargebacks record the amount and currency charged back, the fee is on those
+        let Some(amount) = transaction.amount else {
+            return Ok(());
+        };
+        let currency = transaction.currency.clone();
+        let Some(fee) = self.fees.fee(&transaction.transaction_type, amount) else {
+            return Ok(());
+        };
+
+        account.balance_mut(currency.as_ref()).charge(fee);
+        let mut posting = Transaction::new(
+            TransactionType::Fee,
+            account.client_id,
+            transaction.transaction_id,
+            Some(fee),
+        );
+        posting.currency.clone_from(&currency);
+        posting.timestamp = transaction.timestamp;
+        posting.executed();
+        // the house log keeps the client who paid
+        context
+            .accounts_store
+            .push_transaction(house_account, posting.clone());
+        context
+            .accounts_store
+            .push_transaction(account.client_id, posting);
+
+        context
+            .accounts_store
+            .get_or_create(house_account)
+            .map_err(|_| RunnerError::StorageError)?
+            .balance_mut(currency.as_ref())
+            .deposit(fee);
+
+        context.events.emit(EngineEvent::FeeCharged {
+            client: account.client_id,
+            tx: transaction.transaction_id,
+            amount: fee,
+            currency,
+        });
+        Ok(())
+    }
+
+    /// the credit limit of a balance is changed. Already overdrawn balances stay as they are
+    fn handle_credit_limit<S: AccountsStorage>(
         account: &mut Account,
         transaction: &Transaction,
         context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        // This is a bit of an oversimplification: transaction are validated and this invariant is upheld
+        // However we may want a Result here
+        let limit = transaction.amount.expect("Invalid transaction found");
+        account
+            .balance_mut(transaction.currency.as_ref())
+            .credit_limit = limit;
+        context.events.emit(EngineEvent::CreditLimitChanged {
+            client: account.client_id,
+            currency: transaction.currency.clone(),
+            limit,
+        });
+        RunnerOutcome::Success
+    }
+
+    /// an amount is converted from a currency to another, at the rate effective when the
+    /// transaction happened. The applied rate is recorded in the transaction
+    fn handle_exchange<S: AccountsStorage>(
+        &self,
+        account: &mut Account,
+        transaction: &mut Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        // This is a bit of an oversimplification: transaction are validated and this invariant is upheld
+        // However we may want a Result here
+        let amount = transaction.amount.expect("Invalid transaction found");
+        let from = transaction
+            .currency
+            .clone()
+            .expect("Invalid transaction found");
+        let to = transaction
+            .to_currency
+            .clone()
+            .expect("Invalid transaction found");
+
+        let Some(rate) = self.rates.rate(&from, &to, transaction.timestamp) else {
+            return RunnerOutcome::Skipped(SkipReason::RateNotFound);
+        };
+
+        // only funds the client has are converted, never its credit line
+        let balance = account.balance_mut(Some(&from));
+        if balance.available < amount {
+            return RunnerOutcome::Skipped(SkipReason::NotEnoughFunds);
+        }
+        balance.available -= amount;
+        let to_amount = amount * rate;
+        account.balance_mut(Some(&to)).deposit(to_amount);
+        transaction.rate = Some(rate);
+
+        context.events.emit(EngineEvent::Exchanged {
+            client: account.client_id,
+            tx: transaction.transaction_id,
+            amount,
+            currency: from,
+            to_amount,
+            to_currency: to,
+            rate,
+        });
+        RunnerOutcome::Success
+    }
+
+    /// a previous transaction is being disputed. Funds will be held: the amount of the row if
+    /// any, otherwise all that can still be disputed. Only executed transactions may be
+    /// disputed, and partially disputed ones up to their amount.
+    /// The row records the amount and the currency held
+    fn handle_dispute<S: AccountsStorage>(
+        &self,
+        account: &mut Account,
+        transaction: &mut Transaction,
+        context: &mut RunnerContext<'_, S>,
     ) -> RunnerOutcome {
         // Get the transaction
         if let Some(original_transaction) = context
@@ -101,37 +792,60 @@ impl ExecuteHandler {
         {
             match original_transaction {
                 Transaction {
-                    transaction_type: TransactionType::Deposit,
-                    status: TransactionStatus::Executed,
-                    ..
-                }
-                | Transaction {
-                    transaction_type: TransactionType::Withdrawal,
-                    status: TransactionStatus::Executed,
+                    transaction_type: TransactionType::Deposit | TransactionType::Withdrawal,
+                    status: TransactionStatus::Executed | TransactionStatus::Disputed,
                     ..
                 } => {
-                    // transaction has already been validated at this point, so expect is ugly but safe
-                    account.hold(original_transaction.amount.expect("This Dispute->Deposit/Withdrawal transaction should have an amount and should have been already validated"));
-                    original_transaction.dispute();
+                    if self
+                        .disputes
+                        .out_of_window(original_transaction, transaction)
+                    {
+                        return RunnerOutcome::Skipped(SkipReason::DisputeWindowExpired);
+                    }
+                    let disputable = original_transaction.disputable();
+                    let amount = transaction.amount.unwrap_or(disputable);
+                    if amount.is_zero() || amount > disputable {
+                        return RunnerOutcome::Skipped(SkipReason::InvalidTransactionState);
+                    }
+                    // funds are held in the currency they moved in, whatever the row says
+                    let currency = original_transaction.currency.clone();
+                    let balance = account.balance_mut(currency.as_ref());
+                    if self.disputes.overdrawn == OverdrawnDisputes::Skip
+                        && balance.hold_exceeds_credit(amount)
+                    {
+                        return RunnerOutcome::Skipped(SkipReason::Overdrawn);
+                    }
+                    balance.hold(amount);
+                    original_transaction.dispute(amount);
+                    original_transaction.disputed_at = transaction.timestamp;
+                    transaction.amount = Some(amount);
+                    transaction.currency.clone_from(&currency);
+                    context.events.emit(EngineEvent::DisputeOpened {
+                        client: account.client_id,
+                        tx: transaction.transaction_id,
+                        amount,
+                        currency,
+                    });
                     RunnerOutcome::Success
                 }
                 _ => {
-                    // the original transaction is already in Disputed state
+                    // the original transaction is already fully disputed or charged back
                     // the original transaction is not a money movement: what to do?
                     // skipping for now
-                    RunnerOutcome::Skipped
+                    RunnerOutcome::Skipped(SkipReason::InvalidTransactionState)
                 }
             }
         } else {
             // the transaction does not exist. This seems an error on the source. Skipping
-            RunnerOutcome::Skipped
+            RunnerOutcome::Skipped(SkipReason::TransactionNotFound)
         }
     }
 
-    /// a previous transaction has been resolved. Funds will be freed
+    /// a previous transaction has been resolved. Held funds will be freed: the amount of the
+    /// row if any, otherwise all that is held. The row records the amount and the currency freed
     fn handle_resolve<S: AccountsStorage>(
         account: &mut Account,
-        transaction: &Transaction,
+        transaction: &mut Transaction,
         context: &mut RunnerContext<'_, S>,
     ) -> RunnerOutcome {
         // Get the transaction
@@ -141,38 +855,46 @@ impl ExecuteHandler {
         {
             match original_transaction {
                 Transaction {
-                    transaction_type: TransactionType::Deposit,
-                    status: TransactionStatus::Disputed,
-                    ..
-                }
-                | Transaction {
-                    transaction_type: TransactionType::Withdrawal,
+                    transaction_type: TransactionType::Deposit | TransactionType::Withdrawal,
                     status: TransactionStatus::Disputed,
                     ..
                 } => {
-                    // transaction has already been validated at this point, so unwrap is ugly but safe
-                    account.release(original_transaction.amount.expect("This Resolve->Deposit/Withdrawal transaction should have an amount and should have been already validated"));
-                    original_transaction.resolve();
+                    let amount = transaction.amount.unwrap_or(original_transaction.disputed);
+                    if amount > original_transaction.disputed {
+                        return RunnerOutcome::Skipped(SkipReason::InvalidTransactionState);
+                    }
+                    let currency = original_transaction.currency.clone();
+                    account.balance_mut(currency.as_ref()).release(amount);
+                    original_transaction.resolve(amount);
+                    transaction.amount = Some(amount);
+                    transaction.currency.clone_from(&currency);
+                    context.events.emit(EngineEvent::DisputeResolved {
+                        client: account.client_id,
+                        tx: transaction.transaction_id,
+                        amount,
+                        currency,
+                    });
                     RunnerOutcome::Success
                 }
                 _ => {
                     // the original transaction is not disputed: skip
                     // the original transaction is not a money movement: what to do?
                     // skipping for now
-                    RunnerOutcome::Skipped
+                    RunnerOutcome::Skipped(SkipReason::InvalidTransactionState)
                 }
             }
         } else {
             // the transaction does not exist. This seems an error on the source. Skipping
-            RunnerOutcome::Skipped
+            RunnerOutcome::Skipped(SkipReason::TransactionNotFound)
         }
     }
 
-    /// a previous transaction has been charged back. Held funds will be definitely lost
-    /// and the account will be frozen
+    /// a previous transaction has been charged back. Held funds will be definitely lost: the
+    /// amount of the row if any, otherwise all that is held. Either way the account will be
+    /// frozen. The row records the amount and the currency lost
     fn handle_chargeback<S: AccountsStorage>(
         account: &mut Account,
-        transaction: &Transaction,
+        transaction: &mut Transaction,
         context: &mut RunnerContext<'_, S>,
     ) -> RunnerOutcome {
         // Get the transaction
@@ -182,31 +904,247 @@ impl ExecuteHandler {
         {
             match original_transaction {
                 Transaction {
-                    transaction_type: TransactionType::Deposit,
-                    status: TransactionStatus::Disputed,
-                    ..
-                }
-                | Transaction {
-                    transaction_type: TransactionType::Withdrawal,
+                    transaction_type: TransactionType::Deposit | TransactionType::Withdrawal,
                     status: TransactionStatus::Disputed,
                     ..
                 } => {
-                    // transaction has already been validated at this point, so unwrap is ugly but safe
-                    account.chargeback(original_transaction.amount.expect("This Chargeback->Deposit/Withdrawal transaction should have an amount and should have been already validated"));
-                    original_transaction.chargeback();
+                    let amount = transaction.amount.unwrap_or(original_transaction.disputed);
+                    if amount > original_transaction.disputed {
+                        return RunnerOutcome::Skipped(SkipReason::InvalidTransactionState);
+                    }
+                    let currency = original_transaction.currency.clone();
+                    account.balance_mut(currency.as_ref()).chargeback(amount);
+                    original_transaction.chargeback(amount);
+                    transaction.amount = Some(amount);
+                    transaction.currency.clone_from(&currency);
                     account.freeze();
+                    context.events.emit(EngineEvent::ChargedBack {
+                        client: account.client_id,
+                        tx: transaction.transaction_id,
+                        amount,
+                        currency,
+                    });
+                    context.events.emit(EngineEvent::AccountFrozen {
+                        client: account.client_id,
+                    });
                     RunnerOutcome::Success
                 }
                 _ => {
                     // the original transaction is not disputed: skip
                     // the original transaction is not a money movement: what to do?
                     // skipping for now
-                    RunnerOutcome::Skipped
+                    RunnerOutcome::Skipped(SkipReason::InvalidTransactionState)
                 }
             }
         } else {
             // the transaction does not exist. This seems an error on the source. Skipping
-            RunnerOutcome::Skipped
+            RunnerOutcome::Skipped(SkipReason::TransactionNotFound)
+        }
+    }
+
+    /// a previous deposit or withdrawal was a mistake: its effect is undone, without freezing
+    /// the account. Only executed transactions with nothing held or charged back may be
+    /// reversed, and reversed ones can't be disputed anymore.
+    /// The row records the amount and the currency moved back
+    fn handle_reversal<S: AccountsStorage>(
+        account: &mut Account,
+        transaction: &mut Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        let Some(original_transaction) = context
+            .accounts_store
+            .find_non_disputing_transaction_mut(transaction.client_id, transaction.transaction_id)
+        else {
+            // the transaction does not exist. This seems an error on the source. Skipping
+            return RunnerOutcome::Skipped(SkipReason::TransactionNotFound);
+        };
+
+        let Transaction {
+            transaction_type: TransactionType::Deposit | TransactionType::Withdrawal,
+            status: TransactionStatus::Executed,
+            amount: Some(amount),
+            ..
+        } = original_transaction
+        else {
+            return RunnerOutcome::Skipped(SkipReason::InvalidTransactionState);
+        };
+        let amount = *amount;
+        if !original_transaction.charged_back.is_zero() {
+            return RunnerOutcome::Skipped(SkipReason::InvalidTransactionState);
+        }
+
+        // taking a deposit back moves money out, which suspended accounts can't do
+        if original_transaction.transaction_type == TransactionType::Deposit
+            && account.status == AccountStatus::Suspended
+        {
+            return RunnerOutcome::Skipped(SkipReason::AccountSuspended);
+        }
+
+        let currency = original_transaction.currency.clone();
+        let balance = account.balance_mut(currency.as_ref());
+        if original_transaction.transaction_type == TransactionType::Deposit {
+            // a deposit already spent can't be taken back, not even on the credit line
+            if balance.available < amount {
+                return RunnerOutcome::Skipped(SkipReason::NotEnoughFunds);
+            }
+            balance.available -= amount;
+        } else {
+            balance.deposit(amount);
+        }
+        original_transaction.reversed();
+        transaction.amount = Some(amount);
+        transaction.currency.clone_from(&currency);
+
+        context.events.emit(EngineEvent::Reversed {
+            client: account.client_id,
+            tx: transaction.transaction_id,
+            amount,
+            currency,
+        });
+        RunnerOutcome::Success
+    }
+
+    /// funds are reserved for a card payment, like a withdrawal that is not settled yet
+    fn handle_authorize<S: AccountsStorage>(
+        account: &mut Account,
+        transaction: &Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        // This is a bit of an oversimplification: transaction are validated and this invariant is upheld
+        // However we may want a Result here
+        let amount = transaction.amount.expect("Invalid transaction found");
+
+        match account
+            .balance_mut(transaction.currency.as_ref())
+            .reserve(amount)
+        {
+            Err(AccountOperationError::NotEnoughFunds) => {
+                RunnerOutcome::Skipped(SkipReason::NotEnoughFunds)
+            }
+            Err(AccountOperationError::InvalidAmount) => {
+                RunnerOutcome::Skipped(SkipReason::InvalidTransactionState)
+            }
+            Ok(()) => {
+                context.events.emit(EngineEvent::Authorized {
+                    client: account.client_id,
+                    tx: transaction.transaction_id,
+                    amount,
+                    currency: transaction.currency.clone(),
+                });
+                RunnerOutcome::Success
+            }
+        }
+    }
+
+    /// a previous authorization is settled: the amount of the row if any, otherwise all of it.
+    /// Whatever is not captured is released, an authorization is captured once.
+    /// The row records the amount and the currency captured
+    fn handle_capture<S: AccountsStorage>(
+        account: &mut Account,
+        transaction: &mut Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        let Some(authorization) = context
+            .accounts_store
+            .find_non_disputing_transaction_mut(transaction.client_id, transaction.transaction_id)
+        else {
+            // the transaction does not exist. This seems an error on the source. Skipping
+            return RunnerOutcome::Skipped(SkipReason::TransactionNotFound);
+        };
+
+        let Transaction {
+            transaction_type: TransactionType::Authorize,
+            status: TransactionStatus::Executed,
+            amount: Some(authorized),
+            ..
+        } = authorization
+        else {
+            return RunnerOutcome::Skipped(SkipReason::InvalidTransactionState);
+        };
+        let authorized = *authorized;
+        let amount = transaction.amount.unwrap_or(authorized);
+        if amount > authorized {
+            return RunnerOutcome::Skipped(SkipReason::InvalidTransactionState);
+        }
+
+        let currency = authorization.currency.clone();
+        let balance = account.balance_mut(currency.as_ref());
+        balance.capture(amount);
+        balance.unreserve(authorized - amount);
+        authorization.captured();
+        transaction.amount = Some(amount);
+        transaction.currency.clone_from(&currency);
+
+        context.events.emit(EngineEvent::Captured {
+            client: account.client_id,
+            tx: transaction.transaction_id,
+            amount,
+            currency,
+        });
+        RunnerOutcome::Success
+    }
+
+    /// a previous authorization is cancelled, its funds are released.
+    /// The row records the amount and the currency released
+    fn handle_void<S: AccountsStorage>(
+        account: &mut Account,
+        transaction: &mut Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        let Some(authorization) = context
+            .accounts_store
+            .find_non_disputing_transaction_mut(transaction.client_id, transaction.transaction_id)
+        else {
+            // the transaction does not exist. This seems an error on the source. Skipping
+            return RunnerOutcome::Skipped(SkipReason::TransactionNotFound);
+        };
+
+        let Transaction {
+            transaction_type: TransactionType::Authorize,
+            status: TransactionStatus::Executed,
+            amount: Some(amount),
+            ..
+        } = authorization
+        else {
+            return RunnerOutcome::Skipped(SkipReason::InvalidTransactionState);
+        };
+        let amount = *amount;
+
+        let currency = authorization.currency.clone();
+        account.balance_mut(currency.as_ref()).unreserve(amount);
+        authorization.voided();
+        transaction.amount = Some(amount);
+        transaction.currency.clone_from(&currency);
+
+        context.events.emit(EngineEvent::Voided {
+            client: account.client_id,
+            tx: transaction.transaction_id,
+            amount,
+            currency,
+        });
+        RunnerOutcome::Success
+    }
+
+    /// an admin row moves the account to another status
+    fn handle_status_change<S: AccountsStorage>(
+        account: &mut Account,
+        status: AccountStatus,
+        context: &mut RunnerContext<'_, S>,
+    ) -> RunnerOutcome {
+        match account.transition(status.clone()) {
+            Err(AccountStatusError::InvalidTransition(..)) => {
+                RunnerOutcome::Skipped(SkipReason::InvalidStatusTransition)
+            }
+            Err(AccountStatusError::BalanceNotZero) => {
+                RunnerOutcome::Skipped(SkipReason::BalanceNotZero)
+            }
+            Ok(()) => {
+                context.events.emit(EngineEvent::AccountStatusChanged {
+                    client: account.client_id,
+                    status,
+                });
+                RunnerOutcome::Success
+            }
         }
     }
 }
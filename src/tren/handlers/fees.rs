@@ -0,0 +1,134 @@
+// Fees charged on some transactions, e.g. withdrawals and chargebacks, and credited to a
+// house account. Each transaction type has its own tiers: the tier applying to an amount is
+// the one starting at the highest amount not above it, and its fee is a flat part plus a
+// percentage of the amount. A single tier starting at zero is a plain flat or percentage fee
+
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+
+use crate::tren::{
+    client::ClientId,
+    transactions::{Amount, TransactionType},
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FeeTier {
+    /// smallest amount the tier applies to
+    pub from: Amount,
+    pub flat: Amount,
+    /// fraction of the amount, e.g. `0.01` for 1%
+    pub rate: Decimal,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FeeSchedule {
+    /// the account fees are credited to
+    pub house_account: ClientId,
+    // ordered by starting amount
+    tiers: HashMap<TransactionType, Vec<FeeTier>>,
+}
+
+impl FeeSchedule {
+    #[must_use]
+    pub fn new(
+        house_account: ClientId,
+        tiers: impl IntoIterator<Item = (TransactionType, FeeTier)>,
+    ) -> Self {
+        let mut schedule = FeeSchedule {
+            house_account,
+            tiers: HashMap::new(),
+        };
+        for (transaction_type, tier) in tiers {
+            schedule.add(transaction_type, tier);
+        }
+        schedule
+    }
+
+    /// Add a tier for a transaction type
+    pub fn add(&mut self, transaction_type: TransactionType, tier: FeeTier) {
+        let tiers = self.tiers.entry(transaction_type).or_default();
+        tiers.push(tier);
+        tiers.sort_by_key(|tier| tier.from);
+    }
+
+    /// Are transactions of `transaction_type` charged at all?
+    #[must_use]
+    pub fn charges(&self, transaction_type: &TransactionType) -> bool {
+        self.tiers.contains_key(transaction_type)
+    }
+
+    /// Fee for a transaction of `transaction_type` moving `amount`, rounded to 4 decimals.
+    /// `None` if there is nothing to charge
+    #[must_use]
+    pub fn fee(&self, transaction_type: &TransactionType, amount: Amount) -> Option<Amount> {
+        let tier = self
+            .tiers
+            .get(transaction_type)?
+            .iter()
+            .rev()
+            .find(|tier| tier.from <= amount)?;
+
+        let fee = (tier.flat + amount * tier.rate).round_dp(4);
+        (fee > Decimal::ZERO).then_some(fee)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn tiers_test() {
+        // with
+        let schedule = FeeSchedule::new(
+            0,
+            vec![
+                (
+                    TransactionType::Withdrawal,
+                    FeeTier {
+                        from: dec!(1000),
+                        flat: dec!(0),
+                        rate: dec!(0.001),
+                    },
+                ),
+                (
+                    TransactionType::Withdrawal,
+                    FeeTier {
+                        from: dec!(0),
+                        flat: dec!(0.5),
+                        rate: dec!(0),
+                    },
+                ),
+                (
+                    TransactionType::Chargeback,
+                    FeeTier {
+                        from: dec!(0),
+                        flat: dec!(15),
+                        rate: dec!(0.01),
+                    },
+                ),
+            ],
+        );
+
+        // then
+        assert_eq!(
+            schedule.fee(&TransactionType::Withdrawal, dec!(10)),
+            Some(dec!(0.5))
+        );
+        assert_eq!(
+            schedule.fee(&TransactionType::Withdrawal, dec!(2000)),
+            Some(dec!(2))
+        );
+        assert_eq!(
+            schedule.fee(&TransactionType::Withdrawal, dec!(1000.00005)),
+            Some(dec!(1))
+        );
+        assert_eq!(
+            schedule.fee(&TransactionType::Chargeback, dec!(100)),
+            Some(dec!(16))
+        );
+        assert_eq!(schedule.fee(&TransactionType::Deposit, dec!(100)), None);
+    }
+}
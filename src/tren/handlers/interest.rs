@@ -0,0 +1,126 @@
+// Interest on the positive available balances of operational accounts, posted as deposits.
+// Interest deposits take their ids from a range of their own, from the first one configured up
+// to the largest id, so that they don't clash with the ones of the input: input rows with an id
+// in that range are skipped
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::tren::{
+    engine::runner::RunnerError,
+    ids,
+    transactions::{Amount, TransactionId},
+};
+
+/// First id of interest deposits, unless configured otherwise
+pub const DEFAULT_FIRST_TRANSACTION_ID: TransactionId = 4_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterestPolicy {
+    /// interest rate for each accrual point, e.g. a daily rate if accruing once a day
+    pub rate: Decimal,
+    /// accrue once more when the run is over, besides `accrue` rows
+    pub at_end_of_run: bool,
+    /// id of the first interest deposit, ids from this one on are reserved to interest
+    pub first_transaction_id: TransactionId,
+    /// id of the next interest deposit, incremented at each posting. `None` once the largest id
+    /// has been posted
+    pub next_transaction_id: Option<TransactionId>,
+}
+
+impl InterestPolicy {
+    #[must_use]
+    pub fn new(rate: Decimal) -> Self {
+        InterestPolicy {
+            rate,
+            at_end_of_run: false,
+            first_transaction_id: DEFAULT_FIRST_TRANSACTION_ID,
+            next_transaction_id: Some(DEFAULT_FIRST_TRANSACTION_ID),
+        }
+    }
+
+    /// The same policy, with interest deposits taking their ids from `first` on
+    #[must_use]
+    pub fn starting_at(mut self, first: TransactionId) -> Self {
+        self.first_transaction_id = first;
+        self.next_transaction_id = Some(first);
+        self
+    }
+
+    /// Is `transaction_id` in the range of interest deposits?
+    #[must_use]
+    pub fn reserves(&self, transaction_id: TransactionId) -> bool {
+        transaction_id >= self.first_transaction_id
+    }
+
+    /// Interest on `available`, with banker's rounding to 4 decimals. `None` if there is
+    /// nothing to post
+    #[must_use]
+    pub fn interest(&self, available: Amount) -> Option<Amount> {
+        if available <= Decimal::ZERO {
+            return None;
+        }
+        let interest = (available * self.rate)
+            .round_dp_with_strategy(4, RoundingStrategy::MidpointNearestEven);
+        (interest > Decimal::ZERO).then_some(interest)
+    }
+
+    /// Id for the next interest deposit
+    ///
+    /// # Errors
+    ///
+    /// Returns `RunnerError::IdsExhausted` when the range of interest deposits is used up
+    pub fn take_transaction_id(&mut self) -> Result<TransactionId, RunnerError> {
+        let transaction_id = self.next_transaction_id.ok_or_else(|| {
+            RunnerError::IdsExhausted(format!(
+                "interest deposits from {}",
+                ids::display(self.first_transaction_id)
+            ))
+        })?;
+        self.next_transaction_id = transaction_id.checked_add(1);
+        Ok(transaction_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn bankers_rounding_test() {
+        let policy = InterestPolicy::new(dec!(0.0001));
+
+        // 0.00125 and 0.00135 are halfway: to the even digit
+        assert_eq!(policy.interest(dec!(12.5)), Some(dec!(0.0012)));
+        assert_eq!(policy.interest(dec!(13.5)), Some(dec!(0.0014)));
+        assert_eq!(policy.interest(dec!(0.4)), None);
+        assert_eq!(policy.interest(dec!(-100)), None);
+    }
+
+    #[test]
+    fn transaction_ids_test() {
+        let mut policy = InterestPolicy::new(dec!(0.01));
+
+        assert_eq!(
+            policy.take_transaction_id().ok(),
+            Some(DEFAULT_FIRST_TRANSACTION_ID)
+        );
+        assert_eq!(
+            policy.take_transaction_id().ok(),
+            Some(DEFAULT_FIRST_TRANSACTION_ID + 1)
+        );
+        assert!(policy.reserves(DEFAULT_FIRST_TRANSACTION_ID));
+        assert!(!policy.reserves(DEFAULT_FIRST_TRANSACTION_ID - 1));
+    }
+
+    #[test]
+    fn transaction_ids_exhausted_test() {
+        let mut policy = InterestPolicy::new(dec!(0.01)).starting_at(TransactionId::MAX);
+
+        assert_eq!(policy.take_transaction_id().ok(), Some(TransactionId::MAX));
+        assert!(matches!(
+            policy.take_transaction_id(),
+            Err(RunnerError::IdsExhausted(_))
+        ));
+    }
+}
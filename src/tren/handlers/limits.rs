@@ -0,0 +1,210 @@
+// Velocity limits on withdrawals, to cap how much money can leave an account and how fast.
+// Limits about time only apply to transactions with timestamps: without a notion of time
+// there is no day nor 24 hours to count in
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use chrono::TimeDelta;
+use serde::{Deserialize, Serialize};
+
+use crate::tren::{
+    client::ClientId,
+    transactions::{Amount, Transaction, TransactionStatus, TransactionType},
+};
+
+/// Which limit a withdrawal breached
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub enum Limit {
+    #[serde(rename = "max_withdrawal")]
+    MaxWithdrawal,
+    #[serde(rename = "max_daily_withdrawals")]
+    MaxDailyWithdrawals,
+    #[serde(rename = "max_withdrawn_24h")]
+    MaxWithdrawn24h,
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Limit::MaxWithdrawal => write!(f, "max_withdrawal"),
+            Limit::MaxDailyWithdrawals => write!(f, "max_daily_withdrawals"),
+            Limit::MaxWithdrawn24h => write!(f, "max_withdrawn_24h"),
+        }
+    }
+}
+
+/// Limits on withdrawals. Amounts are in the currency of the withdrawal being checked
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+pub struct WithdrawalLimits {
+    /// largest single withdrawal
+    #[serde(default)]
+    pub max_withdrawal: Option<Amount>,
+    /// number of withdrawals per (UTC) calendar day
+    #[serde(default)]
+    pub max_daily_withdrawals: Option<usize>,
+    /// amount withdrawn over the last 24 hours
+    #[serde(default)]
+    pub max_withdrawn_24h: Option<Amount>,
+}
+
+impl WithdrawalLimits {
+    /// These limits, where not overridden by `other`
+    #[must_use]
+    pub fn overridden_by(self, other: &WithdrawalLimits) -> Self {
+        WithdrawalLimits {
+            max_withdrawal: other.max_withdrawal.or(self.max_withdrawal),
+            max_daily_withdrawals: other.max_daily_withdrawals.or(self.max_daily_withdrawals),
+            max_withdrawn_24h: other.max_withdrawn_24h.or(self.max_withdrawn_24h),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Limits {
+    /// limits of every client
+    pub global: WithdrawalLimits,
+    /// per client overrides of the global limits
+    pub per_client: HashMap<ClientId, WithdrawalLimits>,
+}
+
+impl Limits {
+    /// Limits applying to `client_id`
+    #[must_use]
+    pub fn for_client(&self, client_id: ClientId) -> WithdrawalLimits {
+        match self.per_client.get(&client_id) {
+            Some(overrides) => self.global.overridden_by(overrides),
+            None => self.global,
+        }
+    }
+
+    /// The limit `withdrawal` would breach given the previous transactions of the client, if any
+    #[must_use]
+    pub fn breach(&self, withdrawal: &Transaction, history: &[Transaction]) -> Option<Limit> {
+        let limits = self.for_client(withdrawal.client_id);
+        let amount = withdrawal.amount?;
+
+        if limits.max_withdrawal.is_some_and(|max| amount > max) {
+            return Some(Limit::MaxWithdrawal);
+        }
+
+        let now = withdrawal.timestamp?;
+        let previous = history.iter().filter(|transaction| {
+            matches!(
+                transaction.transaction_type,
+                TransactionType::Withdrawal | TransactionType::Capture
+            ) && transaction.status == TransactionStatus::Executed
+                && transaction.currency == withdrawal.currency
+        });
+
+        if let Some(max) = limits.max_daily_withdrawals {
+            let today = previous
+                .clone()
+                .filter(|transaction| {
+                    transaction
+                        .timestamp
+                        .is_some_and(|timestamp| timestamp.date_naive() == now.date_naive())
+                })
+                .count();
+            if today + 1 > max {
+                return Some(Limit::MaxDailyWithdrawals);
+            }
+        }
+
+        if let Some(max) = limits.max_withdrawn_24h {
+            let withdrawn: Amount = previous
+                .filter(|transaction| {
+                    transaction
+                        .timestamp
+                        .is_some_and(|timestamp| now - timestamp < TimeDelta::hours(24))
+                })
+                .filter_map(|transaction| transaction.amount)
+                .sum();
+            if withdrawn + amount > max {
+                return Some(Limit::MaxWithdrawn24h);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::transactions::{Timestamp, TransactionId};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn withdrawal(tx: TransactionId, amount: Decimal, timestamp: &str) -> Transaction {
+        let timestamp: Timestamp = timestamp.parse().expect("Valid timestamp");
+        let mut withdrawal = Transaction::new(TransactionType::Withdrawal, 1, tx, Some(amount))
+            .with_timestamp(timestamp);
+        withdrawal.executed();
+        withdrawal
+    }
+
+    #[test]
+    fn per_client_overrides_test() {
+        let limits = Limits {
+            global: WithdrawalLimits {
+                max_withdrawal: Some(dec!(100)),
+                max_daily_withdrawals: Some(3),
+                ..Default::default()
+            },
+            per_client: HashMap::from([(
+                2,
+                WithdrawalLimits {
+                    max_withdrawal: Some(dec!(1000)),
+                    ..Default::default()
+                },
+            )]),
+        };
+
+        assert_eq!(limits.for_client(1), limits.global);
+        assert_eq!(
+            limits.for_client(2),
+            WithdrawalLimits {
+                max_withdrawal: Some(dec!(1000)),
+                max_daily_withdrawals: Some(3),
+                max_withdrawn_24h: None,
+            }
+        );
+    }
+
+    #[test]
+    fn breach_test() {
+        let limits = Limits {
+            global: WithdrawalLimits {
+                max_withdrawal: Some(dec!(100)),
+                max_daily_withdrawals: Some(2),
+                max_withdrawn_24h: Some(dec!(150)),
+            },
+            ..Default::default()
+        };
+        let history = vec![
+            withdrawal(1, dec!(60), "2025-01-01T10:00:00Z"),
+            withdrawal(2, dec!(60), "2025-01-01T20:00:00Z"),
+        ];
+
+        assert_eq!(
+            limits.breach(&withdrawal(3, dec!(101), "2025-01-05T00:00:00Z"), &history),
+            Some(Limit::MaxWithdrawal)
+        );
+        assert_eq!(
+            limits.breach(&withdrawal(3, dec!(1), "2025-01-01T23:00:00Z"), &history),
+            Some(Limit::MaxDailyWithdrawals)
+        );
+        assert_eq!(
+            limits.breach(&withdrawal(3, dec!(40), "2025-01-02T09:00:00Z"), &history),
+            Some(Limit::MaxWithdrawn24h)
+        );
+        assert_eq!(
+            limits.breach(&withdrawal(3, dec!(40), "2025-01-02T11:00:00Z"), &history),
+            None
+        );
+        // no time, no time limits
+        let untimed = Transaction::new(TransactionType::Withdrawal, 1, 3, Some(dec!(100)));
+        assert_eq!(limits.breach(&untimed, &history), None);
+    }
+}
@@ -1,5 +1,11 @@
+pub mod authorization_policy;
 pub mod collect_handler;
+pub mod dispute_policy;
 pub mod execute_handler;
+pub mod fees;
+pub mod interest;
+pub mod limits;
+pub mod pipeline;
 // I'm using it only for debug, doesn't make sense to compile it in release
 #[cfg(debug_assertions)]
 pub mod print_handler;
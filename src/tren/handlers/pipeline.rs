@@ -0,0 +1,417 @@
+// Handler made of a chain of stages around an inner handler, e.g.
+// validation -> fraud rules -> ExecuteHandler -> audit
+// Stages see the transaction before the inner handler (and may change, reject or stop it), then
+// see the outcome afterwards, in reverse order, like layers of an onion
+// Accounts are loaded for a transaction before any stage sees it: a stage may change it, but not
+// into one touching other accounts (e.g. of another client), which aborts the run
+
+use crate::tren::{
+    engine::{
+        context::RunnerContext,
+        events::EngineEvent,
+        runner::{RunnerError, RunnerOutcome, SkipReason},
+    },
+    ids,
+    storage::store::{AccountsScope, AccountsStorage},
+    transactions::Transaction,
+};
+
+use super::transaction_handler::TransactionHandler;
+
+/// What a stage decided to do with a transaction
+#[derive(Debug, PartialEq)]
+pub enum StageDecision {
+    /// go on with the (possibly modified) transaction
+    Continue(Transaction),
+    /// refuse the transaction, which is skipped with the given reason
+    Reject(String),
+    /// stop here with the given outcome: following stages and the inner handler are not run.
+    /// Like rejected ones, transactions stopped with a skipped outcome are recorded as skipped
+    ShortCircuit(RunnerOutcome),
+}
+
+pub trait Stage<S: AccountsStorage> {
+    /// Called before the inner handler. A transaction let through must touch the same accounts,
+    /// i.e. have the same `scope`, as the one received
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RunnerError` to abort the whole run
+    fn before(
+        &mut self,
+        transaction: Transaction,
+        _context: &mut RunnerContext<'_, S>,
+    ) -> Result<StageDecision, RunnerError> {
+        Ok(StageDecision::Continue(transaction))
+    }
+
+    /// Called after the inner handler (or a following stage) produced an outcome.
+    /// Only stages whose `before` let the transaction through are called
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RunnerError` to abort the whole run
+    fn after(
+        &mut self,
+        _transaction: &Transaction,
+        _outcome: &RunnerOutcome,
+        _context: &mut RunnerContext<'_, S>,
+    ) -> Result<(), RunnerError> {
+        Ok(())
+    }
+}
+
+pub struct Pipeline<S: AccountsStorage, H: TransactionHandler<S>> {
+    stages: Vec<Box<dyn Stage<S> + Send>>,
+    handler: H,
+}
+
+impl<S: AccountsStorage, H: TransactionHandler<S>> Pipeline<S, H> {
+    /// A pipeline without stages, behaving exactly like `handler`
+    pub fn new(handler: H) -> Self {
+        Pipeline {
+            stages: vec![],
+            handler,
+        }
+    }
+
+    /// The inner handler, e.g. to look at its state after a run
+    pub fn inner(&self) -> &H {
+        &self.handler
+    }
+
+    /// The inner handler, e.g. to carry some state over from another run
+    pub fn inner_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Append a stage, which will see transactions after the already added ones
+    pub fn add_stage(&mut self, stage: impl Stage<S> + Send + 'static) {
+        self.stages.push(Box::new(stage));
+    }
+
+    // like the handler does for its own skips: the skip is published, and the transaction goes
+    // to the log of its account. Accounts are not opened for it
+    fn record_skipped(
+        mut transaction: Transaction,
+        reason: &SkipReason,
+        context: &mut RunnerContext<'_, S>,
+    ) -> Result<(), RunnerError> {
+        context.events.emit(EngineEvent::TransactionSkipped {
+            client: transaction.client_id,
+            tx: transaction.transaction_id,
+            reason: reason.clone(),
+        });
+        let opened = context
+            .accounts_store
+            .get(transaction.client_id)
+            .map_err(|_| RunnerError::StorageError)?
+            .is_some();
+        if opened {
+            transaction.skipped();
+            context
+                .accounts_store
+                .push_transaction(transaction.client_id, transaction);
+        }
+        Ok(())
+    }
+}
+
+impl<S: AccountsStorage, H: TransactionHandler<S>> TransactionHandler<S> for Pipeline<S, H> {
+    fn handle(
+        &mut self,
+        mut transaction: Transaction,
+        context: &mut RunnerContext<'_, S>,
+    ) -> Result<RunnerOutcome, RunnerError> {
+        // the transaction as seen by each stage that let it through, to show it back in `after`
+        let mut passed: Vec<Transaction> = Vec::with_capacity(self.stages.len());
+        let mut stopped = None;
+        // what the runner loaded before the stages
+        let scope = self.handler.scope(&transaction);
+
+        for stage in &mut self.stages {
+            match stage.before(transaction.clone(), context)? {
+                StageDecision::Continue(next) => {
+                    if self.handler.scope(&next) != scope {
+                        return Err(RunnerError::ScopeChanged(
+                            ids::display(transaction.transaction_id).to_string(),
+                        ));
+                    }
+                    passed.push(transaction);
+                    transaction = next;
+                }
+                StageDecision::Reject(reason) => {
+                    stopped = Some(RunnerOutcome::Skipped(SkipReason::Rejected(reason)));
+                    break;
+                }
+                StageDecision::ShortCircuit(outcome) => {
+                    stopped = Some(outcome);
+                    break;
+                }
+            }
+        }
+
+        let outcome = match stopped {
+            Some(outcome) => {
+                if let RunnerOutcome::Skipped(reason) = &outcome {
+                    Self::record_skipped(transaction, reason, context)?;
+                }
+                outcome
+            }
+            None => self.handler.handle(transaction, context)?,
+        };
+
+        for (stage, seen) in self.stages.iter_mut().zip(passed.iter()).rev() {
+            stage.after(seen, &outcome, context)?;
+        }
+
+        Ok(outcome)
+    }
+
+    fn scope(&self, transaction: &Transaction) -> AccountsScope {
+        self.handler.scope(transaction)
+    }
+
+    fn finish_scope(&self) -> AccountsScope {
+        self.handler.finish_scope()
+    }
+
+    fn finish(&mut self, context: &mut RunnerContext<'_, S>) -> Result<(), RunnerError> {
+        self.handler.finish(context)
+    }
+}
+
+/// Prints each transaction with its outcome on stderr, for debugging/auditing purposes
+pub struct LogStage {}
+
+impl<S: AccountsStorage> Stage<S> for LogStage {
+    fn after(
+        &mut self,
+        transaction: &Transaction,
+        outcome: &RunnerOutcome,
+        _context: &mut RunnerContext<'_, S>,
+    ) -> Result<(), RunnerError> {
+        eprintln!("{transaction:?} -> {outcome:?}");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::tren::engine::events::{EventBus, EventSubscriber};
+    use crate::tren::handlers::execute_handler::ExecuteHandler;
+    use crate::tren::storage::in_memory_accounts_storage::InMemoryAccountsStorage;
+    use crate::tren::transactions::TransactionStatus;
+    use crate::tren::transactions::TransactionType;
+    use rust_decimal_macros::dec;
+
+    /// doubles every deposit
+    struct DoubleDeposits {}
+
+    impl<S: AccountsStorage> Stage<S> for DoubleDeposits {
+        fn before(
+            &mut self,
+            mut transaction: Transaction,
+            _context: &mut RunnerContext<'_, S>,
+        ) -> Result<StageDecision, RunnerError> {
+            if transaction.transaction_type == TransactionType::Deposit {
+                transaction.amount = transaction.amount.map(|amount| amount * dec!(2));
+            }
+            Ok(StageDecision::Continue(transaction))
+        }
+    }
+
+    /// refuses withdrawals above a threshold
+    struct RejectBigWithdrawals {}
+
+    impl<S: AccountsStorage> Stage<S> for RejectBigWithdrawals {
+        fn before(
+            &mut self,
+            transaction: Transaction,
+            _context: &mut RunnerContext<'_, S>,
+        ) -> Result<StageDecision, RunnerError> {
+            match transaction {
+                Transaction {
+                    transaction_type: TransactionType::Withdrawal,
+                    amount: Some(amount),
+                    ..
+                } if amount > dec!(100) => Ok(StageDecision::Reject(String::from("too big"))),
+                _ => Ok(StageDecision::Continue(transaction)),
+            }
+        }
+    }
+
+    /// records what it sees after the handler
+    struct Audit {
+        seen: Arc<Mutex<Vec<(Transaction, RunnerOutcome)>>>,
+    }
+
+    impl<S: AccountsStorage> Stage<S> for Audit {
+        fn after(
+            &mut self,
+            transaction: &Transaction,
+            outcome: &RunnerOutcome,
+            _context: &mut RunnerContext<'_, S>,
+        ) -> Result<(), RunnerError> {
+            self.seen
+                .lock()
+                .unwrap()
+                .push((transaction.clone(), outcome.clone()));
+            Ok(())
+        }
+    }
+
+    struct SharedCollector {
+        events: Arc<Mutex<Vec<EngineEvent>>>,
+    }
+
+    impl EventSubscriber for SharedCollector {
+        fn on_event(&mut self, event: &EngineEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn stages_modify_and_reject_test() {
+        // with
+        let seen = Arc::new(Mutex::new(vec![]));
+        let published = Arc::new(Mutex::new(vec![]));
+        let mut pipeline = Pipeline::new(ExecuteHandler::default());
+        pipeline.add_stage(Audit {
+            seen: Arc::clone(&seen),
+        });
+        pipeline.add_stage(RejectBigWithdrawals {});
+        pipeline.add_stage(DoubleDeposits {});
+
+        let mut store = InMemoryAccountsStorage::default();
+        let mut events = EventBus::default();
+        events.subscribe(Box::new(SharedCollector {
+            events: Arc::clone(&published),
+        }));
+        let mut context = RunnerContext::new(&mut store, &mut events);
+
+        // when
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(100)));
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 2, Some(dec!(150)));
+        let deposit_outcome = pipeline
+            .handle(deposit.clone(), &mut context)
+            .expect("Expected success");
+        let withdrawal_outcome = pipeline
+            .handle(withdrawal.clone(), &mut context)
+            .expect("Expected success");
+
+        // then
+        assert_eq!(deposit_outcome, RunnerOutcome::Success);
+        assert_eq!(
+            withdrawal_outcome,
+            RunnerOutcome::Skipped(SkipReason::Rejected(String::from("too big")))
+        );
+
+        let account = context
+            .accounts_store
+            .get(1)
+            .expect("Expected success")
+            .expect("Expected account");
+        assert_eq!(account.balance(None).available, dec!(200));
+
+        // the rejected withdrawal is in the log and published, like the handler's own skips
+        let log = context
+            .accounts_store
+            .get_transactions(1)
+            .expect("Transactions should exist");
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[1].transaction_id, 2);
+        assert_eq!(log[1].status, TransactionStatus::Skipped);
+        context.events.flush();
+        assert!(published
+            .lock()
+            .unwrap()
+            .contains(&EngineEvent::TransactionSkipped {
+                client: 1,
+                tx: 2,
+                reason: SkipReason::Rejected(String::from("too big")),
+            }));
+
+        // the outermost stage sees the transactions as they came in, with their final outcome
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (deposit, RunnerOutcome::Success),
+                (
+                    withdrawal,
+                    RunnerOutcome::Skipped(SkipReason::Rejected(String::from("too big")))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn short_circuit_skips_inner_handler_test() {
+        // with
+        struct AlwaysSucceed {}
+        impl<S: AccountsStorage> Stage<S> for AlwaysSucceed {
+            fn before(
+                &mut self,
+                _transaction: Transaction,
+                _context: &mut RunnerContext<'_, S>,
+            ) -> Result<StageDecision, RunnerError> {
+                Ok(StageDecision::ShortCircuit(RunnerOutcome::Success))
+            }
+        }
+
+        let mut pipeline = Pipeline::new(ExecuteHandler::default());
+        pipeline.add_stage(AlwaysSucceed {});
+
+        let mut store = InMemoryAccountsStorage::default();
+        let mut events = EventBus::default();
+        let mut context = RunnerContext::new(&mut store, &mut events);
+
+        // when
+        let outcome = pipeline
+            .handle(
+                Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(100))),
+                &mut context,
+            )
+            .expect("Expected success");
+
+        // then
+        assert_eq!(outcome, RunnerOutcome::Success);
+        assert_eq!(context.accounts_store.count_accounts(), 0);
+    }
+
+    #[test]
+    fn stages_changing_client_test() {
+        // with
+        struct MoveToClient2 {}
+        impl<S: AccountsStorage> Stage<S> for MoveToClient2 {
+            fn before(
+                &mut self,
+                mut transaction: Transaction,
+                _context: &mut RunnerContext<'_, S>,
+            ) -> Result<StageDecision, RunnerError> {
+                transaction.client_id = 2;
+                Ok(StageDecision::Continue(transaction))
+            }
+        }
+
+        let mut pipeline = Pipeline::new(ExecuteHandler::default());
+        pipeline.add_stage(MoveToClient2 {});
+
+        let mut store = InMemoryAccountsStorage::default();
+        let mut events = EventBus::default();
+        let mut context = RunnerContext::new(&mut store, &mut events);
+
+        // when
+        let result = pipeline.handle(
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(100))),
+            &mut context,
+        );
+
+        // then
+        assert!(matches!(result, Err(RunnerError::ScopeChanged(_))));
+        assert_eq!(context.accounts_store.count_accounts(), 0);
+    }
+}
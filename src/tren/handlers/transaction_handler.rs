@@ -5,7 +5,7 @@ use crate::tren::{
         context::RunnerContext,
         runner::{RunnerError, RunnerOutcome},
     },
-    storage::store::AccountsStorage,
+    storage::store::{AccountsScope, AccountsStorage},
     transactions::Transaction,
 };
 
@@ -20,4 +20,23 @@ pub trait TransactionHandler<S: AccountsStorage> {
         transaction: Transaction,
         context: &mut RunnerContext<'_, S>,
     ) -> Result<RunnerOutcome, RunnerError>;
+
+    /// Accounts `transaction` may read or write, loaded by the storage before handling it
+    fn scope(&self, transaction: &Transaction) -> AccountsScope {
+        AccountsScope::client(transaction.client_id)
+    }
+
+    /// Accounts `finish` may read or write
+    fn finish_scope(&self) -> AccountsScope {
+        AccountsScope::none()
+    }
+
+    /// Called once all the transactions of a run have been handled, e.g. for end of day jobs
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RunnerError` if the storage fails
+    fn finish(&mut self, _context: &mut RunnerContext<'_, S>) -> Result<(), RunnerError> {
+        Ok(())
+    }
 }
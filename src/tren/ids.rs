@@ -0,0 +1,182 @@
+// Representation of client and transaction ids
+// Ids are small integers by default, keeping accounts and transaction logs compact in memory.
+// Larger deployments can widen them at build time:
+// - `wide-ids`: 64 bit integers
+// - `uuid-ids`: UUIDs, held as 128 bit integers and read and written as text, e.g.
+//   `67e55044-10b1-426f-9247-bb680e5fe0c8` (plain integers are accepted too)
+// Fields holding ids are (de)serialized `with` this module and printed through `display`, so that
+// inputs and outputs keep their textual form whatever the representation
+
+// with both features on, ids are UUIDs so that the error below is the only one reported
+#[cfg(all(feature = "wide-ids", feature = "uuid-ids"))]
+compile_error!("features `wide-ids` and `uuid-ids` are mutually exclusive");
+
+use std::fmt::Display;
+
+use serde::{Deserializer, Serializer};
+use thiserror::Error;
+
+#[cfg(not(feature = "uuid-ids"))]
+use serde::{Deserialize, Serialize};
+#[cfg(not(feature = "uuid-ids"))]
+use std::str::FromStr;
+#[cfg(feature = "uuid-ids")]
+use uuid::Uuid;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum IdError {
+    #[error("Invalid id [{0}]")]
+    Invalid(String),
+}
+
+/// Parse an id from its textual form, e.g. a CLI argument or an url path segment
+///
+/// # Errors
+///
+/// Fails if `text` is not an id
+#[cfg(not(feature = "uuid-ids"))]
+pub fn parse<T: FromStr>(text: &str) -> Result<T, IdError> {
+    text.trim()
+        .parse()
+        .map_err(|_| IdError::Invalid(text.to_string()))
+}
+
+/// Parse an id from its textual form, e.g. a CLI argument or an url path segment
+///
+/// # Errors
+///
+/// Fails if `text` is neither a UUID nor an integer
+#[cfg(feature = "uuid-ids")]
+pub fn parse<T: From<u128>>(text: &str) -> Result<T, IdError> {
+    let text = text.trim();
+    Uuid::parse_str(text)
+        .map(|uuid| uuid.as_u128())
+        .or_else(|_| text.parse())
+        .map(T::from)
+        .map_err(|_| IdError::Invalid(text.to_string()))
+}
+
+/// Textual form of an id, as written in outputs
+#[cfg(not(feature = "uuid-ids"))]
+pub fn display<T: Display>(id: T) -> impl Display {
+    id
+}
+
+/// Textual form of an id, as written in outputs
+#[cfg(feature = "uuid-ids")]
+pub fn display<T: Into<u128>>(id: T) -> impl Display {
+    Uuid::from_u128(id.into())
+}
+
+/// For `#[serde(with = "ids")]` fields
+///
+/// # Errors
+///
+/// Fails if the serializer does
+#[cfg(not(feature = "uuid-ids"))]
+pub fn serialize<T: Serialize, S: Serializer>(id: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    id.serialize(serializer)
+}
+
+/// For `#[serde(with = "ids")]` fields
+///
+/// # Errors
+///
+/// Fails if the serializer does
+#[cfg(feature = "uuid-ids")]
+pub fn serialize<T: Copy + Into<u128>, S: Serializer>(
+    id: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&display(*id))
+}
+
+/// For `#[serde(with = "ids")]` fields
+///
+/// # Errors
+///
+/// Fails if the input is not an id
+#[cfg(not(feature = "uuid-ids"))]
+pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    T::deserialize(deserializer)
+}
+
+/// For `#[serde(with = "ids")]` fields
+///
+/// # Errors
+///
+/// Fails if the input is neither a UUID nor an integer
+#[cfg(feature = "uuid-ids")]
+pub fn deserialize<'de, T: From<u128>, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    deserializer
+        .deserialize_any(uuid_text::IdVisitor)
+        .map(T::from)
+}
+
+#[cfg(feature = "uuid-ids")]
+mod uuid_text {
+    use std::fmt;
+
+    use serde::de::{Error, Visitor};
+
+    use super::{parse, IdError};
+
+    // csv fields are typed by their content, json ids may be either numbers or strings
+    pub struct IdVisitor;
+
+    impl Visitor<'_> for IdVisitor {
+        type Value = u128;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a UUID or a non negative integer")
+        }
+
+        fn visit_u64<E: Error>(self, value: u64) -> Result<u128, E> {
+            Ok(u128::from(value))
+        }
+
+        fn visit_u128<E: Error>(self, value: u128) -> Result<u128, E> {
+            Ok(value)
+        }
+
+        fn visit_i64<E: Error>(self, value: i64) -> Result<u128, E> {
+            u128::try_from(value).map_err(|_| E::custom(IdError::Invalid(value.to_string())))
+        }
+
+        fn visit_str<E: Error>(self, value: &str) -> Result<u128, E> {
+            parse(value).map_err(E::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::{client::ClientId, transactions::TransactionId};
+
+    #[test]
+    fn parse_and_display_test() {
+        assert_eq!(parse::<ClientId>(" 42"), Ok(42));
+        assert_eq!(parse::<TransactionId>("7"), Ok(7));
+        assert!(parse::<ClientId>("-1").is_err());
+        assert!(parse::<ClientId>("one").is_err());
+    }
+
+    #[cfg(feature = "uuid-ids")]
+    #[test]
+    fn uuid_ids_test() {
+        let text = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let id = parse::<ClientId>(text).expect("Expected an id");
+        let small: ClientId = 1;
+
+        assert_eq!(display(id).to_string(), text);
+        assert_eq!(
+            display(small).to_string(),
+            "00000000-0000-0000-0000-000000000001"
+        );
+    }
+}
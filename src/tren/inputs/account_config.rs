@@ -0,0 +1,139 @@
+// Per-account settings, read from a local csv and applied before processing transactions, e.g.
+// client, currency, credit_limit, account_type
+// 1,      EUR,      500.0,        business
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::tren::{
+    account::Account,
+    client::{ClientId, ClientProfile},
+    ids,
+    transactions::{is_valid_currency, Currency},
+};
+
+use super::csv_reader::{read_csv, CsvReaderError};
+
+#[derive(Debug, Error)]
+pub enum AccountConfigError {
+    #[error(transparent)]
+    Csv(#[from] CsvReaderError),
+    #[error("Invalid settings for client [{0}]: {1}")]
+    InvalidConfig(String, String),
+}
+
+/// Settings of an account balance. Missing settings are left as they are
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct AccountConfig {
+    #[serde(with = "ids")]
+    pub client: ClientId,
+    /// the balance the settings are about, the one without currency if missing
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    #[serde(default)]
+    pub credit_limit: Option<Decimal>,
+    /// see `ClientProfile::account_type`
+    #[serde(default)]
+    pub account_type: Option<String>,
+}
+
+impl AccountConfig {
+    /// Apply the settings to `account`
+    pub fn apply(&self, account: &mut Account) {
+        if let Some(credit_limit) = self.credit_limit {
+            account.balance_mut(self.currency.as_ref()).credit_limit = credit_limit;
+        }
+    }
+
+    /// What the settings tell about the client
+    #[must_use]
+    pub fn profile(&self) -> ClientProfile {
+        ClientProfile {
+            account_type: self.account_type.clone(),
+        }
+    }
+}
+
+/// Read the accounts settings from a csv with a `client` column and optional `currency`,
+/// `credit_limit` and `account_type` columns
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or a row is not valid: currencies must be three
+/// uppercase letters codes and credit limits can't be negative
+pub fn read_account_config(path: &str) -> Result<Vec<AccountConfig>, AccountConfigError> {
+    let configs = read_csv::<AccountConfig>(path)?;
+
+    for config in &configs {
+        let invalid = |reason: String| {
+            AccountConfigError::InvalidConfig(ids::display(config.client).to_string(), reason)
+        };
+        if let Some(currency) = config
+            .currency
+            .as_ref()
+            .filter(|currency| !is_valid_currency(currency))
+        {
+            return Err(invalid(format!("invalid currency {currency}")));
+        }
+        if let Some(credit_limit) = config
+            .credit_limit
+            .filter(|credit_limit| *credit_limit < Decimal::ZERO)
+        {
+            return Err(invalid(format!("negative credit limit {credit_limit}")));
+        }
+    }
+    Ok(configs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn read_account_config_test() {
+        let configs =
+            read_account_config("src/tests/accounts_config.csv").expect("Expected valid config");
+
+        assert_eq!(configs.len(), 2);
+        assert_eq!(
+            configs[0],
+            AccountConfig {
+                client: 1,
+                currency: None,
+                credit_limit: Some(dec!(50)),
+                account_type: Some(String::from("business")),
+            }
+        );
+
+        let mut account = Account::new(2);
+        configs[1].apply(&mut account);
+        assert_eq!(
+            account.balance(Some(&Currency::from("EUR"))).credit_limit,
+            dec!(100)
+        );
+        assert_eq!(account.balance(None).credit_limit, dec!(0));
+    }
+
+    #[test]
+    fn read_invalid_config_test() {
+        for path in [
+            "src/tests/accounts_config_negative_credit.csv",
+            "src/tests/accounts_config_bad_currency.csv",
+        ] {
+            assert!(
+                matches!(
+                    read_account_config(path),
+                    Err(AccountConfigError::InvalidConfig(..))
+                ),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn read_missing_file_test() {
+        assert!(read_account_config("src/tests/does_not_exist.csv").is_err());
+    }
+}
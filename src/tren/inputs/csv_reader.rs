@@ -0,0 +1,30 @@
+// Reading of the small csv files loaded before processing: settings, limits, fees, rates and
+// balances to compare. Headers are required, and cells are trimmed so that columns can be aligned
+
+use csv::{ReaderBuilder, Trim};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CsvReaderError {
+    #[error("Read error [{0}]")]
+    ReadError(String),
+    #[error("Deserialization error [{0}]")]
+    DeserializeError(String),
+}
+
+/// Read all the rows of the csv at `path`
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or a row can't be deserialized
+pub fn read_csv<T: DeserializeOwned>(path: &str) -> Result<Vec<T>, CsvReaderError> {
+    ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .from_path(path)
+        .map_err(|_| CsvReaderError::ReadError(format!("Could not open file: {path}")))?
+        .into_deserialize::<T>()
+        .map(|row| row.map_err(|err| CsvReaderError::DeserializeError(err.to_string())))
+        .collect()
+}
@@ -0,0 +1,117 @@
+// Fee schedule, read from a local csv with one row per tier, e.g.
+// type,       from, flat, rate
+// withdrawal, 0,    0.5,  0
+// withdrawal, 1000, 0,    0.001
+
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::tren::{
+    client::ClientId,
+    handlers::fees::{FeeSchedule, FeeTier},
+    transactions::TransactionType,
+};
+
+use super::csv_reader::{read_csv, CsvReaderError};
+
+#[derive(Debug, Error)]
+pub enum FeesConfigError {
+    #[error(transparent)]
+    Csv(#[from] CsvReaderError),
+    #[error("Invalid {0:?} fee tier from {1}: {2}")]
+    InvalidTier(TransactionType, Decimal, String),
+}
+
+#[derive(Deserialize)]
+struct FeeTierRow {
+    #[serde(rename = "type")]
+    transaction_type: TransactionType,
+    from: Decimal,
+    flat: Decimal,
+    rate: Decimal,
+}
+
+/// Read a fee schedule from a csv with `type, from, flat, rate` columns, crediting fees
+/// to `house_account`
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or a row is not a valid tier: amounts and rates
+/// can't be negative, and the tiers of a transaction type are listed by increasing `from`,
+/// each starting above the previous one
+pub fn read_fee_schedule(
+    path: &str,
+    house_account: ClientId,
+) -> Result<FeeSchedule, FeesConfigError> {
+    let mut last_from = HashMap::new();
+    let mut tiers = vec![];
+    for row in read_csv::<FeeTierRow>(path)? {
+        let invalid = |reason: &str| {
+            FeesConfigError::InvalidTier(row.transaction_type.clone(), row.from, reason.to_string())
+        };
+        if row.from < Decimal::ZERO || row.flat < Decimal::ZERO || row.rate < Decimal::ZERO {
+            return Err(invalid("negative amount or rate"));
+        }
+        if let Some(previous) = last_from.insert(row.transaction_type.clone(), row.from) {
+            if row.from <= previous {
+                return Err(invalid("tiers must start at increasing amounts"));
+            }
+        }
+        tiers.push((
+            row.transaction_type,
+            FeeTier {
+                from: row.from,
+                flat: row.flat,
+                rate: row.rate,
+            },
+        ));
+    }
+
+    Ok(FeeSchedule::new(house_account, tiers))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn read_fee_schedule_test() {
+        let schedule = read_fee_schedule("src/tests/fees.csv", 999).expect("Expected valid fees");
+
+        assert_eq!(schedule.house_account, 999);
+        assert_eq!(
+            schedule.fee(&TransactionType::Withdrawal, dec!(2000)),
+            Some(dec!(2))
+        );
+        assert_eq!(
+            schedule.fee(&TransactionType::Chargeback, dec!(5)),
+            Some(dec!(15))
+        );
+    }
+
+    #[test]
+    fn read_invalid_fees_test() {
+        for path in [
+            "src/tests/fees_negative.csv",
+            "src/tests/fees_overlapping.csv",
+            "src/tests/fees_unsorted.csv",
+        ] {
+            assert!(
+                matches!(
+                    read_fee_schedule(path, 999),
+                    Err(FeesConfigError::InvalidTier(..))
+                ),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn read_missing_file_test() {
+        assert!(read_fee_schedule("src/tests/does_not_exist.csv", 0).is_err());
+    }
+}
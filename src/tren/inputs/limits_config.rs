@@ -0,0 +1,73 @@
+// Per client withdrawal limits, read from a local csv, e.g.
+// client, max_withdrawal, max_daily_withdrawals, max_withdrawn_24h
+// 1,      1000.0,         ,                      2500.0
+
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::tren::{client::ClientId, handlers::limits::WithdrawalLimits, ids};
+
+use super::csv_reader::{read_csv, CsvReaderError};
+
+#[derive(Deserialize)]
+struct ClientLimitsRow {
+    #[serde(with = "ids")]
+    client: ClientId,
+    #[serde(default)]
+    max_withdrawal: Option<Decimal>,
+    #[serde(default)]
+    max_daily_withdrawals: Option<usize>,
+    #[serde(default)]
+    max_withdrawn_24h: Option<Decimal>,
+}
+
+/// Read the per client limits from a csv with a `client` column and optional `max_withdrawal`,
+/// `max_daily_withdrawals` and `max_withdrawn_24h` columns. Empty cells keep the global limit
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or a row is not valid
+pub fn read_client_limits(
+    path: &str,
+) -> Result<HashMap<ClientId, WithdrawalLimits>, CsvReaderError> {
+    Ok(read_csv::<ClientLimitsRow>(path)?
+        .into_iter()
+        .map(|row| {
+            (
+                row.client,
+                WithdrawalLimits {
+                    max_withdrawal: row.max_withdrawal,
+                    max_daily_withdrawals: row.max_daily_withdrawals,
+                    max_withdrawn_24h: row.max_withdrawn_24h,
+                },
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn read_client_limits_test() {
+        let limits = read_client_limits("src/tests/limits.csv").expect("Expected valid limits");
+
+        assert_eq!(
+            limits.get(&1),
+            Some(&WithdrawalLimits {
+                max_withdrawal: Some(dec!(1000)),
+                max_daily_withdrawals: None,
+                max_withdrawn_24h: None,
+            })
+        );
+    }
+
+    #[test]
+    fn read_missing_file_test() {
+        assert!(read_client_limits("src/tests/does_not_exist.csv").is_err());
+    }
+}
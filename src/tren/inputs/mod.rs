@@ -1,2 +1,6 @@
+pub mod account_config;
+pub mod csv_reader;
 pub mod csv_streamer;
+pub mod fees_config;
+pub mod limits_config;
 pub mod transactions_provider;
@@ -1,7 +1,11 @@
 pub mod account;
+pub mod api;
 pub mod client;
+pub mod diff;
 pub mod engine;
+pub mod exchange;
 pub mod handlers;
+pub mod ids;
 pub mod inputs;
 pub mod output;
 pub mod storage;
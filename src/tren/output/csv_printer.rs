@@ -1,29 +1,68 @@
-use crate::tren::account::Account;
+use crate::tren::account::{Account, Balance};
+use crate::tren::ids;
+use crate::tren::transactions::Currency;
 
 #[derive(Default)]
 pub struct CsvPrinter {}
 
 impl<'a> CsvPrinter {
+    /// Print one row per account and currency. The currency column is only there when
+    /// some balance has a currency, and the reserved one when some balance has funds reserved
+    /// by authorizations, so plain outputs stay as they always were
     pub fn print(&self, accounts_iter: impl Iterator<Item = &'a Account>) {
-        println!("{}", CsvPrinter::csv_header());
+        let accounts = accounts_iter.collect::<Vec<&Account>>();
+        let with_currency = accounts.iter().any(|account| account.has_currencies());
+        let with_reserved = accounts.iter().any(|account| account.has_reserved());
 
-        for account in accounts_iter {
-            println!("{}", CsvPrinter::account_csv(account));
+        println!("{}", CsvPrinter::csv_header(with_currency, with_reserved));
+
+        for account in accounts {
+            for (currency, balance) in account.per_currency() {
+                println!(
+                    "{}",
+                    CsvPrinter::account_csv(
+                        account,
+                        with_currency,
+                        with_reserved,
+                        currency,
+                        balance
+                    )
+                );
+            }
         }
     }
 
-    fn csv_header() -> String {
-        String::from("client, available, held, total, locked")
+    fn csv_header(with_currency: bool, with_reserved: bool) -> String {
+        let currency = if with_currency { "currency, " } else { "" };
+        let reserved = if with_reserved { "reserved, " } else { "" };
+        format!("client, {currency}available, held, {reserved}total, status")
     }
 
-    fn account_csv(account: &Account) -> String {
+    fn account_csv(
+        account: &Account,
+        with_currency: bool,
+        with_reserved: bool,
+        currency: Option<&Currency>,
+        balance: &Balance,
+    ) -> String {
+        let currency = if with_currency {
+            format!("{}, ", currency.map(String::as_str).unwrap_or_default())
+        } else {
+            String::new()
+        };
+        let reserved = if with_reserved {
+            format!("{:.4}, ", balance.reserved.round_dp(4))
+        } else {
+            String::new()
+        };
+
         format!(
-            "{client_id}, {available:.4}, {held:.4}, {total:.4}, {locked}",
-            client_id = account.client_id,
-            available = account.amount.round_dp(4),
-            held = account.held_amount.round_dp(4),
-            total = account.total().round_dp(4),
-            locked = account.frozen()
+            "{client_id}, {currency}{available:.4}, {held:.4}, {reserved}{total:.4}, {status}",
+            client_id = ids::display(account.client_id),
+            available = balance.available.round_dp(4),
+            held = balance.held.round_dp(4),
+            total = balance.total().round_dp(4),
+            status = account.status
         )
     }
 }
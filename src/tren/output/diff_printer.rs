@@ -0,0 +1,113 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::tren::client::ClientId;
+use crate::tren::diff::balance_diff::{AccountDiff, AccountSnapshot, BalanceDiff};
+use crate::tren::ids;
+use crate::tren::transactions::Currency;
+
+#[derive(Default)]
+pub struct DiffPrinter {}
+
+/// Machine readable representation of a change
+#[derive(Serialize)]
+struct ChangeView<'a> {
+    #[serde(with = "ids")]
+    client: ClientId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: &'a Option<Currency>,
+    change: &'static str,
+    available_delta: Decimal,
+    held_delta: Decimal,
+    #[serde(skip_serializing_if = "Decimal::is_zero")]
+    reserved_delta: Decimal,
+    total_delta: Decimal,
+    status_changed: bool,
+    exceeds_tolerance: bool,
+    before: &'a Option<AccountSnapshot>,
+    after: &'a Option<AccountSnapshot>,
+}
+
+impl DiffPrinter {
+    /// Print the diff as a JSON array, flagging the changes beyond `tolerance`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the diff cannot be serialized
+    pub fn print_json(&self, diff: &BalanceDiff, tolerance: Decimal) -> serde_json::Result<()> {
+        let views = diff
+            .changes
+            .iter()
+            .map(|change| ChangeView {
+                client: change.client,
+                currency: &change.currency,
+                change: DiffPrinter::change_kind(change),
+                available_delta: change.available_delta(),
+                held_delta: change.held_delta(),
+                reserved_delta: change.reserved_delta(),
+                total_delta: change.total_delta(),
+                status_changed: change.status_changed(),
+                exceeds_tolerance: change.exceeds(tolerance),
+                before: &change.before,
+                after: &change.after,
+            })
+            .collect::<Vec<ChangeView>>();
+
+        println!("{}", serde_json::to_string_pretty(&views)?);
+        Ok(())
+    }
+
+    /// Print the diff as csv. Like for the accounts, the currency column is only there
+    /// when some balance has a currency
+    pub fn print(&self, diff: &BalanceDiff) {
+        let with_currency = diff.changes.iter().any(|change| change.currency.is_some());
+
+        println!("{}", DiffPrinter::csv_header(with_currency));
+
+        for change in &diff.changes {
+            println!("{}", DiffPrinter::change_csv(change, with_currency));
+        }
+    }
+
+    fn csv_header(with_currency: bool) -> String {
+        let currency = if with_currency { "currency, " } else { "" };
+        format!(
+            "client, {currency}change, available_delta, held_delta, total_delta, status_before, status_after",
+        )
+    }
+
+    /// name of the kind of change
+    #[must_use]
+    pub fn change_kind(change: &AccountDiff) -> &'static str {
+        match (&change.before, &change.after) {
+            (None, _) => "added",
+            (_, None) => "removed",
+            _ => "changed",
+        }
+    }
+
+    fn change_csv(change: &AccountDiff, with_currency: bool) -> String {
+        // a missing side has no status at all
+        let status = |side: Option<&AccountSnapshot>| {
+            side.map(|snapshot| snapshot.status.to_string())
+                .unwrap_or_default()
+        };
+
+        let currency = if with_currency {
+            format!("{}, ", change.currency.as_deref().unwrap_or_default())
+        } else {
+            String::new()
+        };
+
+        format!(
+            "{client_id}, {currency}{kind}, {available:.4}, {held:.4}, {total:.4}, {status_before}, {status_after}",
+            client_id = ids::display(change.client),
+            kind = DiffPrinter::change_kind(change),
+            available = change.available_delta(),
+            held = change.held_delta(),
+            total = change.total_delta(),
+            status_before = status(change.before.as_ref()),
+            status_after = status(change.after.as_ref()),
+        )
+    }
+}
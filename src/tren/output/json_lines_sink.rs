@@ -0,0 +1,193 @@
+// Event subscriber writing each event as a JSON object on its own line
+// usable on anything implementing `Write`, with shortcuts for files and TCP sockets
+// subscribers are called by the engine on the runtime threads: sockets are not written from
+// there, events are handed over to a task of the runtime owning the connection
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+
+use crate::tren::engine::events::{EngineEvent, EventSubscriber};
+
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesSink { writer }
+    }
+
+    /// Give back the underlying writer, e.g. to inspect what has been written
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl JsonLinesSink<BufWriter<File>> {
+    /// Write events to a file, truncating it if it already exists
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created
+    pub fn to_file(path: &str) -> std::io::Result<Self> {
+        Ok(JsonLinesSink::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// What is written is sent, on each flush, to the task writing to the socket
+pub struct SocketWriter {
+    buffer: Vec<u8>,
+    sender: UnboundedSender<Vec<u8>>,
+}
+
+impl Write for SocketWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.sender
+            .send(std::mem::take(&mut self.buffer))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "socket closed"))
+    }
+}
+
+impl JsonLinesSink<SocketWriter> {
+    /// Stream events to a TCP socket. They are written by a task of the runtime, which ends
+    /// once the sink is dropped and everything has been written: await it so that no event
+    /// is lost when the process ends
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection cannot be established
+    pub async fn to_socket(address: impl ToSocketAddrs) -> io::Result<(Self, JoinHandle<()>)> {
+        let mut stream = TcpStream::connect(address).await?;
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Vec<u8>>();
+        let writing = tokio::spawn(async move {
+            while let Some(events) = receiver.recv().await {
+                if let Err(err) = stream.write_all(&events).await {
+                    // the sink reports the next events it can't send
+                    eprintln!("Could not write event [{err}]");
+                    return;
+                }
+            }
+        });
+        let writer = SocketWriter {
+            buffer: vec![],
+            sender,
+        };
+        Ok((JsonLinesSink::new(writer), writing))
+    }
+}
+
+impl<W: Write> EventSubscriber for JsonLinesSink<W> {
+    fn on_event(&mut self, event: &EngineEvent) {
+        // a broken sink must not stop the engine: events are best effort, report and go on
+        let written = serde_json::to_writer(&mut self.writer, event)
+            .map_err(std::io::Error::from)
+            .and_then(|()| self.writer.write_all(b"\n"))
+            .and_then(|()| self.writer.flush());
+
+        if let Err(err) = written {
+            eprintln!("Could not write event [{err}]");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::engine::runner::SkipReason;
+    use crate::tren::ids;
+    use rust_decimal_macros::dec;
+    use serde_json::{json, Value};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    // an id as the sink writes it, a number or a string depending on the representation
+    fn id(id: u8) -> Value {
+        ids::serialize(&u16::from(id), serde_json::value::Serializer).expect("Expected an id")
+    }
+
+    fn events() -> [EngineEvent; 2] {
+        [
+            EngineEvent::Deposited {
+                client: 1,
+                tx: 2,
+                amount: dec!(3.5),
+                currency: None,
+            },
+            EngineEvent::TransactionSkipped {
+                client: 1,
+                tx: 3,
+                reason: SkipReason::NotEnoughFunds,
+            },
+        ]
+    }
+
+    fn assert_lines(written: &str) {
+        let lines = written
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Expected a json line"))
+            .collect::<Vec<Value>>();
+        assert_eq!(
+            lines,
+            vec![
+                json!({"event": "Deposited", "client": id(1), "tx": id(2), "amount": "3.5"}),
+                json!({"event": "TransactionSkipped", "client": id(1), "tx": id(3), "reason": "not_enough_funds"}),
+            ]
+        );
+        assert!(written.ends_with('\n'));
+    }
+
+    #[test]
+    fn one_event_per_line_test() {
+        // with
+        let mut sink = JsonLinesSink::new(vec![]);
+
+        // when
+        for event in &events() {
+            sink.on_event(event);
+        }
+
+        // then
+        assert_lines(&String::from_utf8(sink.into_inner()).expect("Expected utf8 output"));
+    }
+
+    #[tokio::test]
+    async fn socket_test() {
+        // with
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Expected to bind a local port");
+        let address = listener.local_addr().expect("Expected a local address");
+        let (mut sink, writing) = JsonLinesSink::to_socket(address)
+            .await
+            .expect("Expected a connection");
+        let (mut socket, _) = listener.accept().await.expect("Expected a connection");
+
+        // when
+        for event in &events() {
+            sink.on_event(event);
+        }
+        drop(sink);
+        writing.await.expect("Expected the events to be written");
+
+        // then
+        let mut written = String::new();
+        socket
+            .read_to_string(&mut written)
+            .await
+            .expect("Expected the events to be read");
+        assert_lines(&written);
+    }
+}
@@ -1 +1,4 @@
 pub mod csv_printer;
+pub mod diff_printer;
+pub mod json_lines_sink;
+pub mod overdrawn_printer;
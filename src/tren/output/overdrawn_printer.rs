@@ -0,0 +1,81 @@
+// Report of the balances below zero, e.g. for collections to follow up, kept apart from the
+// accounts output so that it can be consumed on its own
+
+use std::io::Write;
+
+use crate::tren::account::Account;
+use crate::tren::ids;
+
+#[derive(Default)]
+pub struct OverdrawnPrinter {}
+
+impl<'a> OverdrawnPrinter {
+    /// Write one row per overdrawn balance
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `writer` fails
+    pub fn write(
+        &self,
+        accounts_iter: impl Iterator<Item = &'a Account>,
+        writer: &mut impl Write,
+    ) -> std::io::Result<()> {
+        writeln!(writer, "client, currency, available, credit_limit, status")?;
+
+        for account in accounts_iter {
+            for (currency, balance) in account.per_currency() {
+                if balance.overdrawn() {
+                    writeln!(
+                        writer,
+                        "{client_id}, {currency}, {available:.4}, {credit_limit:.4}, {status}",
+                        client_id = ids::display(account.client_id),
+                        currency = currency.map(String::as_str).unwrap_or_default(),
+                        available = balance.available.round_dp(4),
+                        credit_limit = balance.credit_limit.round_dp(4),
+                        status = account.status
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::transactions::Currency;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn only_overdrawn_balances_test() {
+        // with
+        let overdrawn_id = 1;
+        let mut overdrawn = Account::new(overdrawn_id);
+        overdrawn.balance_mut(None).credit_limit = dec!(10);
+        overdrawn
+            .balance_mut(None)
+            .withdraw(dec!(4))
+            .expect("Expected enough credit");
+        overdrawn
+            .balance_mut(Some(&Currency::from("EUR")))
+            .deposit(dec!(4));
+        let mut fine = Account::new(2);
+        fine.balance_mut(None).deposit(dec!(1));
+
+        // when
+        let mut output = vec![];
+        OverdrawnPrinter::default()
+            .write([overdrawn, fine].iter(), &mut output)
+            .expect("Expected a report");
+
+        // then
+        assert_eq!(
+            String::from_utf8(output).expect("Expected utf8"),
+            format!(
+                "client, currency, available, credit_limit, status\n{}, , -4.0000, 10.0000, operational\n",
+                ids::display(overdrawn_id)
+            )
+        );
+    }
+}
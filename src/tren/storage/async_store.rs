@@ -0,0 +1,69 @@
+// trait for storages living behind I/O, e.g. a network service or an async database
+// everything is owned: nothing borrowed from the storage is held across an await, so the
+// runtime is never blocked waiting for it
+// handlers don't use it directly, `CachedAccountsStorage` adapts it to `AccountsStorage`
+
+use async_trait::async_trait;
+
+use crate::tren::{account::Account, client::ClientId, transactions::Transaction};
+
+use super::store::StoreError;
+
+/// Changes of a unit of work, written at once
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    /// accounts to insert or update
+    pub accounts: Vec<Account>,
+    /// transactions to append to the log of a client, in order
+    pub appended: Vec<(ClientId, Vec<Transaction>)>,
+    /// non disputing transactions (see `Transaction::is_disputing`) replacing the ones with the
+    /// same id in the log of a client, e.g. after they have been disputed
+    pub updated: Vec<(ClientId, Transaction)>,
+}
+
+impl WriteBatch {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty() && self.appended.is_empty() && self.updated.is_empty()
+    }
+}
+
+#[async_trait]
+pub trait AsyncAccountsStorage: Send + Sync {
+    /// The account of `client_id`, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the storage fails to read the account.
+    async fn load_account(&self, client_id: ClientId) -> Result<Option<Account>, StoreError>;
+
+    /// All the accounts, in no particular order
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the storage fails to read the accounts.
+    async fn load_accounts(&self) -> Result<Vec<Account>, StoreError>;
+
+    /// The transactions log of `client_id`, in the order transactions were pushed
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the storage fails to read the transactions.
+    async fn load_transactions(&self, client_id: ClientId) -> Result<Vec<Transaction>, StoreError>;
+
+    /// Apply all the changes of `batch`, or none of them
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the storage fails to write the changes, leaving it as it was.
+    async fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError>;
+
+    /// Make the written batches durable, for storages that delay it
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the storage fails to write to disk.
+    async fn sync(&mut self) -> Result<(), StoreError> {
+        Ok(())
+    }
+}
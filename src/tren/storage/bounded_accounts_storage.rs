@@ -0,0 +1,371 @@
+// in-memory storage keeping a bounded window of each client's transactions log
+// dispute, resolve and chargeback rows are never kept: what they did is recorded on the
+// transaction they refer to. When a unit of work is committed, the logs it grew past
+// `HistoryPolicy::max_in_memory` are trimmed from the oldest transactions: those that can be
+// referred to by a later row are spilled to disk first, the others (fees, reversals, ...) are
+// just dropped. Transactions still needed in memory are never trimmed: open disputes and
+// authorizations, which can expire, and withdrawals and captures of the last 24 hours, for
+// velocity limits
+// a row referring to a spilled transaction brings it back to memory, at the end of the log
+// only memory is looked into by the read-only methods, e.g. `get_transactions` and
+// `find_non_disputing_transaction` only see the window: so do the API and dry runs over this
+// storage, which don't find spilled transactions
+
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use chrono::TimeDelta;
+
+use crate::tren::{
+    account::Account,
+    client::ClientId,
+    transactions::{Timestamp, Transaction, TransactionId, TransactionStatus, TransactionType},
+};
+
+use super::{
+    history::{HistoryPolicy, SpillFile},
+    in_memory_accounts_storage::InMemoryAccountsStorage,
+    store::{AccountsScope, AccountsStorage, StoreError},
+};
+
+pub struct BoundedAccountsStorage {
+    hot: InMemoryAccountsStorage,
+    max_in_memory: usize,
+    spill: SpillFile,
+    // clients whose log grew in the unit of work
+    grown: HashSet<ClientId>,
+    // spilled transactions brought back to memory in the unit of work
+    promoted: HashSet<(ClientId, TransactionId)>,
+    // a spilled transaction could not be read back, the unit of work can't be committed
+    read_failed: bool,
+}
+
+impl BoundedAccountsStorage {
+    /// An empty storage, spilling to `policy.spill_path`
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the spill file can't be created, e.g. because it already exists
+    pub fn new(policy: &HistoryPolicy) -> Result<Self, StoreError> {
+        Ok(BoundedAccountsStorage {
+            hot: InMemoryAccountsStorage::default(),
+            max_in_memory: policy.max_in_memory,
+            spill: SpillFile::create(&policy.spill_path).map_err(|_| StoreError::WriteError)?,
+            grown: HashSet::new(),
+            promoted: HashSet::new(),
+            read_failed: false,
+        })
+    }
+
+    /// Number of transactions on disk
+    #[must_use]
+    pub fn spilled(&self) -> usize {
+        self.spill.len()
+    }
+
+    // positions of the transactions trimmed from the log of `client_id`, oldest first
+    fn trimmed(&self, client_id: ClientId) -> Vec<usize> {
+        let Some(log) = self.hot.get_transactions(client_id) else {
+            return vec![];
+        };
+        let excess = log.len().saturating_sub(self.max_in_memory);
+        if excess == 0 {
+            return vec![];
+        }
+        let newest = log
+            .iter()
+            .filter_map(|transaction| transaction.timestamp)
+            .max();
+        log.iter()
+            .enumerate()
+            .filter(|(_, transaction)| !Self::needed(transaction, newest))
+            .map(|(position, _)| position)
+            .take(excess)
+            .collect()
+    }
+
+    // whether the transaction must stay in memory, `newest` being the latest time in its log
+    fn needed(transaction: &Transaction, newest: Option<Timestamp>) -> bool {
+        match (&transaction.transaction_type, &transaction.status) {
+            (_, TransactionStatus::Disputed)
+            | (TransactionType::Authorize, TransactionStatus::Executed) => true,
+            (
+                TransactionType::Withdrawal | TransactionType::Capture,
+                TransactionStatus::Executed,
+            ) => match (transaction.timestamp, newest) {
+                (Some(timestamp), Some(newest)) => newest - timestamp < TimeDelta::hours(24),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn forget_changes(&mut self) {
+        self.grown.clear();
+        self.promoted.clear();
+        self.read_failed = false;
+    }
+}
+
+#[async_trait]
+impl AccountsStorage for BoundedAccountsStorage {
+    async fn load(&mut self, scope: &AccountsScope) -> Result<(), StoreError> {
+        self.hot.load(scope).await
+    }
+
+    fn begin(&mut self) {
+        self.forget_changes();
+        self.hot.begin();
+    }
+
+    async fn commit(&mut self) -> Result<(), StoreError> {
+        if self.read_failed {
+            self.rollback();
+            return Err(StoreError::ReadError);
+        }
+
+        let trimmed = self
+            .grown
+            .iter()
+            .map(|client_id| (*client_id, self.trimmed(*client_id)))
+            .filter(|(_, positions)| !positions.is_empty())
+            .collect::<Vec<(ClientId, Vec<usize>)>>();
+        let spilled = trimmed
+            .iter()
+            .flat_map(|(client_id, positions)| {
+                let log = self
+                    .hot
+                    .get_transactions(*client_id)
+                    .map_or(&[][..], Vec::as_slice);
+                positions.iter().map(|position| &log[*position])
+            })
+            .filter(|transaction| !transaction.is_disputing())
+            .collect::<Vec<&Transaction>>();
+        let Ok(file_positions) = self.spill.append(&spilled) else {
+            self.rollback();
+            return Err(StoreError::WriteError);
+        };
+        for (client_id, transaction_id) in self.promoted.drain() {
+            self.spill.forget(client_id, transaction_id);
+        }
+        self.spill.index(&spilled, &file_positions);
+
+        self.hot.commit().await?;
+        for (client_id, positions) in trimmed {
+            if let Some(log) = self.hot.get_transactions_mut(client_id) {
+                let mut position = 0;
+                log.retain(|_| {
+                    let keep = positions.binary_search(&position).is_err();
+                    position += 1;
+                    keep
+                });
+            }
+        }
+        self.forget_changes();
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+        self.forget_changes();
+        self.hot.rollback();
+    }
+
+    fn count_accounts(&self) -> usize {
+        self.hot.count_accounts()
+    }
+
+    fn all_accounts_iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        self.hot.all_accounts_iter()
+    }
+
+    fn get_or_create(&mut self, client_id: ClientId) -> Result<&mut Account, StoreError> {
+        self.hot.get_or_create(client_id)
+    }
+
+    fn get(&self, client_id: ClientId) -> Result<Option<&Account>, StoreError> {
+        self.hot.get(client_id)
+    }
+
+    fn put(&mut self, account: Account) -> Result<(), StoreError> {
+        self.hot.put(account)
+    }
+
+    fn list(&self) -> Vec<&Account> {
+        self.hot.list()
+    }
+
+    fn push_transaction(&mut self, client_id: ClientId, transaction: Transaction) {
+        if matches!(
+            transaction.transaction_type,
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+        ) {
+            return;
+        }
+        self.grown.insert(client_id);
+        self.hot.push_transaction(client_id, transaction);
+    }
+
+    fn get_transactions(&self, client_id: ClientId) -> Option<&Vec<Transaction>> {
+        self.hot.get_transactions(client_id)
+    }
+
+    fn get_transactions_mut(&mut self, client_id: ClientId) -> Option<&mut Vec<Transaction>> {
+        self.hot.get_transactions_mut(client_id)
+    }
+
+    fn find_non_disputing_transaction(
+        &self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> Option<&Transaction> {
+        self.hot
+            .find_non_disputing_transaction(client_id, transaction_id)
+    }
+
+    fn find_non_disputing_transaction_mut(
+        &mut self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> Option<&mut Transaction> {
+        // spilled transactions are older than those in memory, they are found first
+        if self.spill.contains(client_id, transaction_id)
+            && !self.promoted.contains(&(client_id, transaction_id))
+        {
+            match self.spill.read(client_id, transaction_id) {
+                Ok(Some(transaction)) => {
+                    self.grown.insert(client_id);
+                    self.promoted.insert((client_id, transaction_id));
+                    self.hot.push_transaction(client_id, transaction);
+                }
+                Ok(None) => {}
+                Err(_) => {
+                    self.read_failed = true;
+                    return None;
+                }
+            }
+        }
+        self.hot
+            .find_non_disputing_transaction_mut(client_id, transaction_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::storage::overlay_accounts_storage::OverlayAccountsStorage;
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn history_is_bounded_test() {
+        // with
+        let spill_path =
+            std::env::temp_dir().join(format!("tren_bounded_{}.bin", std::process::id()));
+        let mut store = BoundedAccountsStorage::new(&HistoryPolicy {
+            max_in_memory: 2,
+            spill_path: spill_path.clone(),
+        })
+        .expect("Expected a storage");
+
+        // when
+        for transaction_id in 1..=4 {
+            let mut deposit =
+                Transaction::new(TransactionType::Deposit, 1, transaction_id, Some(dec!(10)));
+            deposit.executed();
+            store.begin();
+            store.push_transaction(1, deposit);
+            store
+                .commit()
+                .await
+                .expect("Expected changes to be committed");
+        }
+        store.begin();
+        store.push_transaction(1, Transaction::new(TransactionType::Dispute, 1, 1, None));
+
+        // then: the oldest deposits are on disk, the dispute row is not kept
+        assert_eq!(store.spilled(), 2);
+        assert_eq!(store.get_transactions(1).map(Vec::len), Some(2));
+        assert!(store.find_non_disputing_transaction(1, 1).is_none());
+
+        // when: the dispute refers to a spilled deposit
+        store
+            .find_non_disputing_transaction_mut(1, 1)
+            .expect("Expected the spilled deposit")
+            .dispute(dec!(10));
+        store
+            .commit()
+            .await
+            .expect("Expected changes to be committed");
+
+        // then: back in memory, and kept there while disputed
+        assert_eq!(store.spilled(), 2);
+        let log = store
+            .get_transactions(1)
+            .expect("Transactions should exist");
+        assert_eq!(log.len(), 2);
+        assert_eq!(
+            store
+                .find_non_disputing_transaction(1, 1)
+                .expect("Expected the disputed deposit")
+                .status,
+            TransactionStatus::Disputed
+        );
+
+        // the spill file goes with the storage
+        drop(store);
+        assert!(!spill_path.exists());
+    }
+
+    #[tokio::test]
+    async fn read_only_view_test() {
+        // with: the same rows in an unbounded and a bounded storage
+        let spill_path =
+            std::env::temp_dir().join(format!("tren_bounded_view_{}.bin", std::process::id()));
+        let mut bounded = BoundedAccountsStorage::new(&HistoryPolicy {
+            max_in_memory: 2,
+            spill_path,
+        })
+        .expect("Expected a storage");
+        let mut unbounded = InMemoryAccountsStorage::default();
+
+        // when
+        let mut rows = vec![];
+        for transaction_id in 1..=3 {
+            let mut deposit =
+                Transaction::new(TransactionType::Deposit, 1, transaction_id, Some(dec!(10)));
+            deposit.executed();
+            rows.push(deposit);
+        }
+        rows.push(Transaction::new(TransactionType::Dispute, 1, 3, None));
+        rows.push(Transaction::new(TransactionType::Resolve, 1, 3, None));
+        for row in rows {
+            bounded.begin();
+            bounded.push_transaction(1, row.clone());
+            bounded
+                .commit()
+                .await
+                .expect("Expected changes to be committed");
+            unbounded.push_transaction(1, row);
+        }
+
+        // then: the bounded log only has the window, without the dispute and resolve rows
+        let ids = |log: Option<&Vec<Transaction>>| {
+            log.expect("Transactions should exist")
+                .iter()
+                .map(|t| (t.transaction_type.clone(), t.transaction_id))
+                .collect::<Vec<(TransactionType, TransactionId)>>()
+        };
+        assert_eq!(ids(unbounded.get_transactions(1)).len(), 5);
+        assert_eq!(
+            ids(bounded.get_transactions(1)),
+            vec![(TransactionType::Deposit, 2), (TransactionType::Deposit, 3)]
+        );
+
+        // and the spilled deposit is only found by a row referring to it
+        assert!(unbounded.find_non_disputing_transaction(1, 1).is_some());
+        assert!(bounded.find_non_disputing_transaction(1, 1).is_none());
+        assert!(OverlayAccountsStorage::new(&bounded)
+            .find_non_disputing_transaction_mut(1, 1)
+            .is_none());
+        assert!(bounded.find_non_disputing_transaction_mut(1, 1).is_some());
+    }
+}
@@ -0,0 +1,309 @@
+// synchronous storage over an async one, for handlers to work on borrowed accounts
+// `load` copies the accounts a transaction needs, with their transaction logs, from the backend
+// into memory; handlers change them there, and `commit` writes back only what changed, in a
+// single batch. If the backend refuses it, the cache is rolled back too, staying in line with it
+// loaded accounts stay cached: the backend is supposed to be changed only through this storage
+// the sync methods only see what has been loaded, e.g. `count_accounts` counts the cached accounts
+
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+
+use crate::tren::{
+    account::Account,
+    client::ClientId,
+    transactions::{Transaction, TransactionId},
+};
+
+use super::{
+    async_store::{AsyncAccountsStorage, WriteBatch},
+    in_memory_accounts_storage::InMemoryAccountsStorage,
+    store::{AccountsScope, AccountsStorage, StoreError},
+};
+
+pub struct CachedAccountsStorage<B: AsyncAccountsStorage> {
+    backend: B,
+    cache: InMemoryAccountsStorage,
+    // clients whose account and log have been read from the backend
+    loaded: HashSet<ClientId>,
+    all_loaded: bool,
+    // per client, how many transactions of the cached log are in the backend
+    persisted: HashMap<ClientId, usize>,
+    // changes of the unit of work
+    changed_accounts: HashSet<ClientId>,
+    changed_transactions: HashSet<(ClientId, TransactionId)>,
+    // clients whose whole log has been handed out mutably
+    changed_logs: HashSet<ClientId>,
+    appended: HashSet<ClientId>,
+}
+
+impl<B: AsyncAccountsStorage> CachedAccountsStorage<B> {
+    #[must_use]
+    pub fn new(backend: B) -> Self {
+        CachedAccountsStorage {
+            backend,
+            cache: InMemoryAccountsStorage::default(),
+            loaded: HashSet::new(),
+            all_loaded: false,
+            persisted: HashMap::new(),
+            changed_accounts: HashSet::new(),
+            changed_transactions: HashSet::new(),
+            changed_logs: HashSet::new(),
+            appended: HashSet::new(),
+        }
+    }
+
+    /// The storage changes are written to
+    #[must_use]
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    async fn load_client(&mut self, client_id: ClientId) -> Result<(), StoreError> {
+        if let Some(account) = self.backend.load_account(client_id).await? {
+            self.cache.put(account)?;
+        }
+        let transactions = self.backend.load_transactions(client_id).await?;
+        self.persisted.insert(client_id, transactions.len());
+        for transaction in transactions {
+            self.cache.push_transaction(client_id, transaction);
+        }
+        self.loaded.insert(client_id);
+        Ok(())
+    }
+
+    // what changed in the unit of work, and the new persisted length of the logs
+    fn batch(&self) -> (WriteBatch, Vec<(ClientId, usize)>) {
+        let persisted = |client_id: &ClientId| self.persisted.get(client_id).copied().unwrap_or(0);
+        let log = |client_id: &ClientId| {
+            self.cache
+                .get_transactions(*client_id)
+                .map_or(&[][..], Vec::as_slice)
+        };
+
+        let whole_logs = self.changed_logs.iter().flat_map(|client_id| {
+            log(client_id)[..persisted(client_id)]
+                .iter()
+                .filter(|transaction| !transaction.is_disputing())
+                .map(|transaction| (*client_id, transaction.clone()))
+        });
+        let single = self
+            .changed_transactions
+            .iter()
+            .filter(|(client_id, _)| !self.changed_logs.contains(client_id))
+            .filter_map(|(client_id, transaction_id)| {
+                let log = log(client_id);
+                let position = log.iter().position(|transaction| {
+                    transaction.transaction_id == *transaction_id && !transaction.is_disputing()
+                })?;
+                // not persisted yet, it will be appended
+                (position < persisted(client_id)).then(|| (*client_id, log[position].clone()))
+            });
+
+        let batch = WriteBatch {
+            accounts: self
+                .changed_accounts
+                .iter()
+                .filter_map(|client_id| self.cache.get(*client_id).ok().flatten().cloned())
+                .collect(),
+            appended: self
+                .appended
+                .iter()
+                .map(|client_id| (*client_id, log(client_id)[persisted(client_id)..].to_vec()))
+                .collect(),
+            updated: whole_logs.chain(single).collect(),
+        };
+        let lengths = self
+            .appended
+            .iter()
+            .map(|client_id| (*client_id, log(client_id).len()))
+            .collect();
+        (batch, lengths)
+    }
+
+    fn forget_changes(&mut self) {
+        self.changed_accounts.clear();
+        self.changed_transactions.clear();
+        self.changed_logs.clear();
+        self.appended.clear();
+    }
+}
+
+#[async_trait]
+impl<B: AsyncAccountsStorage> AccountsStorage for CachedAccountsStorage<B> {
+    async fn load(&mut self, scope: &AccountsScope) -> Result<(), StoreError> {
+        if self.all_loaded {
+            return Ok(());
+        }
+        match scope {
+            AccountsScope::Clients(client_ids) => {
+                for client_id in client_ids {
+                    if !self.loaded.contains(client_id) {
+                        self.load_client(*client_id).await?;
+                    }
+                }
+            }
+            AccountsScope::All => {
+                for account in self.backend.load_accounts().await? {
+                    if !self.loaded.contains(&account.client_id) {
+                        self.load_client(account.client_id).await?;
+                    }
+                }
+                self.all_loaded = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn begin(&mut self) {
+        self.forget_changes();
+        self.cache.begin();
+    }
+
+    async fn commit(&mut self) -> Result<(), StoreError> {
+        let (batch, lengths) = self.batch();
+        self.forget_changes();
+        if !batch.is_empty() {
+            if let Err(err) = self.backend.write(batch).await {
+                self.cache.rollback();
+                return Err(err);
+            }
+        }
+        self.persisted.extend(lengths);
+        self.cache.commit().await
+    }
+
+    fn rollback(&mut self) {
+        self.forget_changes();
+        self.cache.rollback();
+    }
+
+    async fn sync(&mut self) -> Result<(), StoreError> {
+        self.backend.sync().await
+    }
+
+    fn count_accounts(&self) -> usize {
+        self.cache.count_accounts()
+    }
+
+    fn all_accounts_iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        self.cache.all_accounts_iter()
+    }
+
+    fn get_or_create(&mut self, client_id: ClientId) -> Result<&mut Account, StoreError> {
+        self.changed_accounts.insert(client_id);
+        self.cache.get_or_create(client_id)
+    }
+
+    fn get(&self, client_id: ClientId) -> Result<Option<&Account>, StoreError> {
+        self.cache.get(client_id)
+    }
+
+    fn put(&mut self, account: Account) -> Result<(), StoreError> {
+        self.changed_accounts.insert(account.client_id);
+        self.cache.put(account)
+    }
+
+    fn list(&self) -> Vec<&Account> {
+        self.cache.list()
+    }
+
+    fn push_transaction(&mut self, client_id: ClientId, transaction: Transaction) {
+        self.appended.insert(client_id);
+        self.cache.push_transaction(client_id, transaction);
+    }
+
+    fn get_transactions(&self, client_id: ClientId) -> Option<&Vec<Transaction>> {
+        self.cache.get_transactions(client_id)
+    }
+
+    fn get_transactions_mut(&mut self, client_id: ClientId) -> Option<&mut Vec<Transaction>> {
+        self.changed_logs.insert(client_id);
+        self.cache.get_transactions_mut(client_id)
+    }
+
+    fn find_non_disputing_transaction(
+        &self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> Option<&Transaction> {
+        self.cache
+            .find_non_disputing_transaction(client_id, transaction_id)
+    }
+
+    fn find_non_disputing_transaction_mut(
+        &mut self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> Option<&mut Transaction> {
+        self.changed_transactions
+            .insert((client_id, transaction_id));
+        self.cache
+            .find_non_disputing_transaction_mut(client_id, transaction_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::transactions::TransactionType;
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn only_changes_are_written_back_test() {
+        // with: client 1 in the backend
+        let mut backend = InMemoryAccountsStorage::default();
+        let mut deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(10)));
+        deposit.executed();
+        backend
+            .get_or_create(1)
+            .expect("Expected an account")
+            .balance_mut(None)
+            .deposit(dec!(10));
+        backend.push_transaction(1, deposit);
+        let mut store = CachedAccountsStorage::new(backend);
+
+        // when: nothing is loaded
+        // then
+        assert!(store.get(1).expect("Get should work").is_none());
+
+        // when
+        store
+            .load(&AccountsScope::client(1))
+            .await
+            .expect("Expected accounts to be loaded");
+        store.begin();
+        store
+            .find_non_disputing_transaction_mut(1, 1)
+            .expect("Expected the deposit")
+            .dispute(dec!(10));
+        store.push_transaction(
+            1,
+            Transaction::new(TransactionType::Dispute, 1, 1, Some(dec!(10))),
+        );
+        store
+            .get_or_create(2)
+            .expect("Expected an account")
+            .balance_mut(None)
+            .deposit(dec!(1));
+
+        // then: nothing written yet
+        assert_eq!(store.backend().count_accounts(), 1);
+
+        // when
+        store
+            .commit()
+            .await
+            .expect("Expected changes to be committed");
+
+        // then
+        let backend = store.backend();
+        assert_eq!(backend.count_accounts(), 2);
+        let log = backend
+            .get_transactions(1)
+            .expect("Transactions should exist");
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].disputed, dec!(10));
+        assert_eq!(log[1].transaction_type, TransactionType::Dispute);
+    }
+}
@@ -0,0 +1,382 @@
+// compact binary encoding of what storages write to disk
+// fixed size little endian integers, decimals as their 16 bytes representation, options and
+// enums as one byte tags, strings prefixed by their length. There is no versioning: records are
+// read back by the same version, built with the same id features, as the one that wrote them
+
+use chrono::DateTime;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::tren::{
+    account::{Account, AccountStatus, Balance},
+    client::ClientId,
+    transactions::{
+        Currency, Timestamp, Transaction, TransactionId, TransactionStatus, TransactionType,
+    },
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CodecError {
+    #[error("Record is truncated")]
+    Truncated,
+    #[error("Invalid record [{0}]")]
+    Invalid(String),
+}
+
+/// Append the encoding of `transaction` to `out`
+pub fn encode_transaction(transaction: &Transaction, out: &mut Vec<u8>) {
+    out.push(transaction_type_tag(&transaction.transaction_type));
+    out.extend(transaction.client_id.to_le_bytes());
+    out.extend(transaction.transaction_id.to_le_bytes());
+    encode_option(transaction.amount.as_ref(), out, encode_decimal);
+    out.push(status_tag(&transaction.status));
+    encode_option(transaction.timestamp.as_ref(), out, encode_timestamp);
+    encode_option(transaction.currency.as_ref(), out, encode_string);
+    encode_option(transaction.to_currency.as_ref(), out, encode_string);
+    encode_option(transaction.rate.as_ref(), out, encode_decimal);
+    encode_decimal(&transaction.disputed, out);
+    encode_decimal(&transaction.resolved, out);
+    encode_decimal(&transaction.charged_back, out);
+    encode_option(transaction.disputed_at.as_ref(), out, encode_timestamp);
+}
+
+/// The transaction encoded in `bytes`
+///
+/// # Errors
+///
+/// Returns a `CodecError` if `bytes` is not the encoding of a transaction
+pub fn decode_transaction(bytes: &[u8]) -> Result<Transaction, CodecError> {
+    let mut reader = Reader::new(bytes);
+    let transaction_type = transaction_type(reader.byte()?)?;
+    let client_id = ClientId::from_le_bytes(reader.array()?);
+    let transaction_id = TransactionId::from_le_bytes(reader.array()?);
+    let mut transaction = Transaction::new(
+        transaction_type,
+        client_id,
+        transaction_id,
+        reader.option(Reader::decimal)?,
+    );
+    transaction.status = status(reader.byte()?)?;
+    transaction.timestamp = reader.option(Reader::timestamp)?;
+    transaction.currency = reader.option(Reader::string)?;
+    transaction.to_currency = reader.option(Reader::string)?;
+    transaction.rate = reader.option(Reader::decimal)?;
+    transaction.disputed = reader.decimal()?;
+    transaction.resolved = reader.decimal()?;
+    transaction.charged_back = reader.decimal()?;
+    transaction.disputed_at = reader.option(Reader::timestamp)?;
+    reader.end()?;
+    Ok(transaction)
+}
+
+/// Append the encoding of `account` to `out`
+pub fn encode_account(account: &Account, out: &mut Vec<u8>) {
+    out.extend(account.client_id.to_le_bytes());
+    out.push(account_status_tag(&account.status));
+    encode_option(account.last_activity.as_ref(), out, encode_timestamp);
+    out.extend(
+        u32::try_from(account.balances.len())
+            .unwrap_or(u32::MAX)
+            .to_le_bytes(),
+    );
+    for (currency, balance) in &account.balances {
+        encode_option(currency.as_ref(), out, encode_string);
+        encode_decimal(&balance.available, out);
+        encode_decimal(&balance.held, out);
+        encode_decimal(&balance.reserved, out);
+        encode_decimal(&balance.credit_limit, out);
+    }
+}
+
+/// The account encoded in `bytes`
+///
+/// # Errors
+///
+/// Returns a `CodecError` if `bytes` is not the encoding of an account
+pub fn decode_account(bytes: &[u8]) -> Result<Account, CodecError> {
+    let mut reader = Reader::new(bytes);
+    let mut account = Account::new(ClientId::from_le_bytes(reader.array()?));
+    account.status = account_status(reader.byte()?)?;
+    account.last_activity = reader.option(Reader::timestamp)?;
+    for _ in 0..u32::from_le_bytes(reader.array()?) {
+        let currency = reader.option(Reader::string)?;
+        let balance = Balance {
+            available: reader.decimal()?,
+            held: reader.decimal()?,
+            reserved: reader.decimal()?,
+            credit_limit: reader.decimal()?,
+        };
+        account.balances.insert(currency, balance);
+    }
+    reader.end()?;
+    Ok(account)
+}
+
+pub(crate) fn encode_option<T>(value: Option<&T>, out: &mut Vec<u8>, encode: fn(&T, &mut Vec<u8>)) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            encode(value, out);
+        }
+        None => out.push(0),
+    }
+}
+
+pub(crate) fn encode_decimal(decimal: &Decimal, out: &mut Vec<u8>) {
+    out.extend(decimal.serialize());
+}
+
+pub(crate) fn encode_timestamp(timestamp: &Timestamp, out: &mut Vec<u8>) {
+    out.extend(timestamp.timestamp().to_le_bytes());
+    out.extend(timestamp.timestamp_subsec_nanos().to_le_bytes());
+}
+
+pub(crate) fn encode_string(string: &Currency, out: &mut Vec<u8>) {
+    // longer strings are cut, currencies are three letters anyway
+    let bytes = &string.as_bytes()[..string.len().min(usize::from(u16::MAX))];
+    out.extend(u16::try_from(bytes.len()).unwrap_or(u16::MAX).to_le_bytes());
+    out.extend(bytes);
+}
+
+// reads values one after the other from a record
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], CodecError> {
+        if self.bytes.len() < len {
+            return Err(CodecError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        self.take(N)?.try_into().map_err(|_| CodecError::Truncated)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8, CodecError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub(crate) fn option<T>(
+        &mut self,
+        decode: fn(&mut Self) -> Result<T, CodecError>,
+    ) -> Result<Option<T>, CodecError> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => decode(self).map(Some),
+            tag => Err(CodecError::Invalid(format!("option tag {tag}"))),
+        }
+    }
+
+    pub(crate) fn decimal(&mut self) -> Result<Decimal, CodecError> {
+        Ok(Decimal::deserialize(self.array()?))
+    }
+
+    pub(crate) fn timestamp(&mut self) -> Result<Timestamp, CodecError> {
+        let seconds = i64::from_le_bytes(self.array()?);
+        let nanos = u32::from_le_bytes(self.array()?);
+        DateTime::from_timestamp(seconds, nanos)
+            .ok_or_else(|| CodecError::Invalid(format!("timestamp {seconds}.{nanos}")))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<Currency, CodecError> {
+        let len = u16::from_le_bytes(self.array()?);
+        let bytes = self.take(usize::from(len))?;
+        String::from_utf8(bytes.to_vec()).map_err(|err| CodecError::Invalid(err.to_string()))
+    }
+
+    /// Nothing must be left once a record is read
+    pub(crate) fn end(&self) -> Result<(), CodecError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(CodecError::Invalid(format!(
+                "{} bytes left",
+                self.bytes.len()
+            )))
+        }
+    }
+}
+
+// tags are part of the format: new variants get new tags, existing ones never change
+fn transaction_type_tag(transaction_type: &TransactionType) -> u8 {
+    match transaction_type {
+        TransactionType::Deposit => 0,
+        TransactionType::Withdrawal => 1,
+        TransactionType::Dispute => 2,
+        TransactionType::Resolve => 3,
+        TransactionType::Chargeback => 4,
+        TransactionType::Authorize => 5,
+        TransactionType::Capture => 6,
+        TransactionType::Void => 7,
+        TransactionType::Reversal => 8,
+        TransactionType::Exchange => 9,
+        TransactionType::Accrue => 10,
+        TransactionType::Fee => 11,
+        TransactionType::CreditLimit => 12,
+        TransactionType::Open => 13,
+        TransactionType::Suspend => 14,
+        TransactionType::Activate => 15,
+        TransactionType::Close => 16,
+        TransactionType::Tick => 17,
+    }
+}
+
+fn transaction_type(tag: u8) -> Result<TransactionType, CodecError> {
+    Ok(match tag {
+        0 => TransactionType::Deposit,
+        1 => TransactionType::Withdrawal,
+        2 => TransactionType::Dispute,
+        3 => TransactionType::Resolve,
+        4 => TransactionType::Chargeback,
+        5 => TransactionType::Authorize,
+        6 => TransactionType::Capture,
+        7 => TransactionType::Void,
+        8 => TransactionType::Reversal,
+        9 => TransactionType::Exchange,
+        10 => TransactionType::Accrue,
+        11 => TransactionType::Fee,
+        12 => TransactionType::CreditLimit,
+        13 => TransactionType::Open,
+        14 => TransactionType::Suspend,
+        15 => TransactionType::Activate,
+        16 => TransactionType::Close,
+        17 => TransactionType::Tick,
+        tag => return Err(CodecError::Invalid(format!("transaction type {tag}"))),
+    })
+}
+
+fn status_tag(status: &TransactionStatus) -> u8 {
+    match status {
+        TransactionStatus::Ready => 0,
+        TransactionStatus::Executed => 1,
+        TransactionStatus::Disputed => 2,
+        TransactionStatus::ChargedBack => 3,
+        TransactionStatus::Reversed => 4,
+        TransactionStatus::Captured => 5,
+        TransactionStatus::Voided => 6,
+        TransactionStatus::Skipped => 7,
+    }
+}
+
+fn status(tag: u8) -> Result<TransactionStatus, CodecError> {
+    Ok(match tag {
+        0 => TransactionStatus::Ready,
+        1 => TransactionStatus::Executed,
+        2 => TransactionStatus::Disputed,
+        3 => TransactionStatus::ChargedBack,
+        4 => TransactionStatus::Reversed,
+        5 => TransactionStatus::Captured,
+        6 => TransactionStatus::Voided,
+        7 => TransactionStatus::Skipped,
+        tag => return Err(CodecError::Invalid(format!("transaction status {tag}"))),
+    })
+}
+
+fn account_status_tag(status: &AccountStatus) -> u8 {
+    match status {
+        AccountStatus::Operational => 0,
+        AccountStatus::Suspended => 1,
+        AccountStatus::Dormant => 2,
+        AccountStatus::Closed => 3,
+        AccountStatus::Frozen => 4,
+    }
+}
+
+fn account_status(tag: u8) -> Result<AccountStatus, CodecError> {
+    Ok(match tag {
+        0 => AccountStatus::Operational,
+        1 => AccountStatus::Suspended,
+        2 => AccountStatus::Dormant,
+        3 => AccountStatus::Closed,
+        4 => AccountStatus::Frozen,
+        tag => return Err(CodecError::Invalid(format!("account status {tag}"))),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn transaction_round_trip_test() {
+        // with
+        let mut deposit = Transaction::new(TransactionType::Deposit, 1, 2, Some(dec!(10.5)))
+            .with_timestamp(
+                "2025-02-18T10:00:00.123Z"
+                    .parse()
+                    .expect("Expected a timestamp"),
+            )
+            .with_currency("EUR");
+        deposit.executed();
+        deposit.dispute(dec!(4));
+        deposit.disputed_at = deposit.timestamp;
+        let fee = Transaction::new(TransactionType::Fee, 1, 2, None);
+
+        for transaction in [deposit, fee] {
+            // when
+            let mut bytes = vec![];
+            encode_transaction(&transaction, &mut bytes);
+
+            // then
+            assert_eq!(decode_transaction(&bytes), Ok(transaction));
+        }
+    }
+
+    #[test]
+    fn account_round_trip_test() {
+        // with
+        let mut account = Account::new(3);
+        account.balance_mut(None).deposit(dec!(10));
+        account
+            .balance_mut(Some(&Currency::from("EUR")))
+            .credit_limit = dec!(50);
+        account.status = AccountStatus::Suspended;
+        account.last_activity = Some(
+            "2025-02-18T10:00:00Z"
+                .parse()
+                .expect("Expected a timestamp"),
+        );
+
+        // when
+        let mut bytes = vec![];
+        encode_account(&account, &mut bytes);
+        let decoded = decode_account(&bytes).expect("Expected an account");
+
+        // then
+        assert_eq!(decoded.client_id, account.client_id);
+        assert_eq!(decoded.balances, account.balances);
+        assert_eq!(decoded.status, account.status);
+        assert_eq!(decoded.last_activity, account.last_activity);
+    }
+
+    #[test]
+    fn invalid_records_test() {
+        // with
+        let mut bytes = vec![];
+        encode_transaction(
+            &Transaction::new(TransactionType::Deposit, 1, 2, Some(dec!(1))),
+            &mut bytes,
+        );
+
+        // when, then
+        assert_eq!(
+            decode_transaction(&bytes[..bytes.len() - 1]),
+            Err(CodecError::Truncated)
+        );
+        bytes[0] = 200;
+        assert!(matches!(
+            decode_transaction(&bytes),
+            Err(CodecError::Invalid(_))
+        ));
+    }
+}
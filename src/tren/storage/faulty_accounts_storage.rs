@@ -0,0 +1,289 @@
+// storage failing on purpose, to test how storage failures are handled
+// it wraps another storage and makes its reads or its writes fail following a `Fault`: on given
+// calls, randomly or after some calls. A failing call doesn't reach the wrapped storage, and a
+// failing commit rolls it back, like a real storage refusing the changes would
+// reads are `load`, `get` and the `load_*` methods of async storages, writes are `get_or_create`,
+// `put`, `commit` and `write`. Other methods can't fail and are just forwarded
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+use async_trait::async_trait;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::tren::{
+    account::Account,
+    client::ClientId,
+    transactions::{Transaction, TransactionId},
+};
+
+use super::{
+    async_store::{AsyncAccountsStorage, WriteBatch},
+    store::{AccountsScope, AccountsStorage, StoreError},
+};
+
+/// Which calls fail
+#[derive(Debug, Clone, Default)]
+pub enum Fault {
+    #[default]
+    Never,
+    /// the given calls, counting from 1
+    OnCalls(Vec<usize>),
+    /// each call with the given probability, drawn from a generator seeded with `seed` so that
+    /// failing runs can be reproduced
+    Randomly { probability: f64, seed: u64 },
+    /// every call after the first `n`
+    After(usize),
+}
+
+// counts the calls of a kind, deciding which ones fail
+struct Injector {
+    fault: Fault,
+    error: fn() -> StoreError,
+    calls: AtomicUsize,
+    failed: AtomicUsize,
+    rng: Mutex<StdRng>,
+}
+
+impl Injector {
+    fn new(fault: Fault, error: fn() -> StoreError) -> Self {
+        let seed = match fault {
+            Fault::Randomly { seed, .. } => seed,
+            _ => 0,
+        };
+        Injector {
+            fault,
+            error,
+            calls: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+        }
+    }
+
+    fn check(&self) -> Result<(), StoreError> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        let fails = match &self.fault {
+            Fault::Never => false,
+            Fault::OnCalls(calls) => calls.contains(&call),
+            Fault::Randomly { probability, .. } => self
+                .rng
+                .lock()
+                .is_ok_and(|mut rng| rng.random::<f64>() < *probability),
+            Fault::After(n) => call > *n,
+        };
+        if fails {
+            self.failed.fetch_add(1, Ordering::SeqCst);
+            return Err((self.error)());
+        }
+        Ok(())
+    }
+}
+
+pub struct FaultyAccountsStorage<S> {
+    inner: S,
+    reads: Injector,
+    writes: Injector,
+}
+
+impl<S> FaultyAccountsStorage<S> {
+    /// A storage behaving exactly like `inner`, until faults are set
+    #[must_use]
+    pub fn new(inner: S) -> Self {
+        FaultyAccountsStorage {
+            inner,
+            reads: Injector::new(Fault::Never, || StoreError::ReadError),
+            writes: Injector::new(Fault::Never, || StoreError::WriteError),
+        }
+    }
+
+    /// Make reads fail, with a `ReadError`
+    #[must_use]
+    pub fn failing_reads(mut self, fault: Fault) -> Self {
+        self.reads = Injector::new(fault, || StoreError::ReadError);
+        self
+    }
+
+    /// Make writes fail, with a `WriteError`
+    #[must_use]
+    pub fn failing_writes(mut self, fault: Fault) -> Self {
+        self.writes = Injector::new(fault, || StoreError::WriteError);
+        self
+    }
+
+    /// The wrapped storage, which can be read without failures
+    #[must_use]
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// How many calls failed so far, reads and writes
+    #[must_use]
+    pub fn failures(&self) -> usize {
+        self.reads.failed.load(Ordering::SeqCst) + self.writes.failed.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl<S: AccountsStorage> AccountsStorage for FaultyAccountsStorage<S> {
+    async fn load(&mut self, scope: &AccountsScope) -> Result<(), StoreError> {
+        self.reads.check()?;
+        self.inner.load(scope).await
+    }
+
+    fn begin(&mut self) {
+        self.inner.begin();
+    }
+
+    async fn commit(&mut self) -> Result<(), StoreError> {
+        if let Err(err) = self.writes.check() {
+            self.inner.rollback();
+            return Err(err);
+        }
+        self.inner.commit().await
+    }
+
+    fn rollback(&mut self) {
+        self.inner.rollback();
+    }
+
+    async fn sync(&mut self) -> Result<(), StoreError> {
+        self.inner.sync().await
+    }
+
+    fn count_accounts(&self) -> usize {
+        self.inner.count_accounts()
+    }
+
+    fn all_accounts_iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        self.inner.all_accounts_iter()
+    }
+
+    fn get_or_create(&mut self, client_id: ClientId) -> Result<&mut Account, StoreError> {
+        self.writes.check()?;
+        self.inner.get_or_create(client_id)
+    }
+
+    fn get(&self, client_id: ClientId) -> Result<Option<&Account>, StoreError> {
+        self.reads.check()?;
+        self.inner.get(client_id)
+    }
+
+    fn put(&mut self, account: Account) -> Result<(), StoreError> {
+        self.writes.check()?;
+        self.inner.put(account)
+    }
+
+    fn list(&self) -> Vec<&Account> {
+        self.inner.list()
+    }
+
+    fn push_transaction(&mut self, client_id: ClientId, transaction: Transaction) {
+        self.inner.push_transaction(client_id, transaction);
+    }
+
+    fn get_transactions(&self, client_id: ClientId) -> Option<&Vec<Transaction>> {
+        self.inner.get_transactions(client_id)
+    }
+
+    fn get_transactions_mut(&mut self, client_id: ClientId) -> Option<&mut Vec<Transaction>> {
+        self.inner.get_transactions_mut(client_id)
+    }
+
+    fn find_non_disputing_transaction(
+        &self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> Option<&Transaction> {
+        self.inner
+            .find_non_disputing_transaction(client_id, transaction_id)
+    }
+
+    fn find_non_disputing_transaction_mut(
+        &mut self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> Option<&mut Transaction> {
+        self.inner
+            .find_non_disputing_transaction_mut(client_id, transaction_id)
+    }
+}
+
+#[async_trait]
+impl<S: AsyncAccountsStorage> AsyncAccountsStorage for FaultyAccountsStorage<S> {
+    async fn load_account(&self, client_id: ClientId) -> Result<Option<Account>, StoreError> {
+        self.reads.check()?;
+        self.inner.load_account(client_id).await
+    }
+
+    async fn load_accounts(&self) -> Result<Vec<Account>, StoreError> {
+        self.reads.check()?;
+        self.inner.load_accounts().await
+    }
+
+    async fn load_transactions(&self, client_id: ClientId) -> Result<Vec<Transaction>, StoreError> {
+        self.reads.check()?;
+        self.inner.load_transactions(client_id).await
+    }
+
+    async fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        self.writes.check()?;
+        self.inner.write(batch).await
+    }
+
+    async fn sync(&mut self) -> Result<(), StoreError> {
+        self.inner.sync().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::storage::in_memory_accounts_storage::InMemoryAccountsStorage;
+
+    #[test]
+    fn faults_test() {
+        // with
+        let mut store = FaultyAccountsStorage::new(InMemoryAccountsStorage::default())
+            .failing_reads(Fault::OnCalls(vec![2, 4]))
+            .failing_writes(Fault::After(1));
+
+        // when
+        let reads = (0..5).map(|_| store.get(1).is_ok()).collect::<Vec<bool>>();
+        let writes = (0..3)
+            .map(|_| store.get_or_create(1).is_ok())
+            .collect::<Vec<bool>>();
+
+        // then
+        assert_eq!(reads, vec![true, false, true, false, true]);
+        assert_eq!(writes, vec![true, false, false]);
+        assert_eq!(store.failures(), 4);
+        assert_eq!(store.inner().count_accounts(), 1);
+    }
+
+    #[test]
+    fn random_faults_are_reproducible_test() {
+        // with
+        let fault = Fault::Randomly {
+            probability: 0.5,
+            seed: 42,
+        };
+        let first = FaultyAccountsStorage::new(InMemoryAccountsStorage::default())
+            .failing_reads(fault.clone());
+        let second =
+            FaultyAccountsStorage::new(InMemoryAccountsStorage::default()).failing_reads(fault);
+
+        // when
+        let first_reads = (0..100)
+            .map(|_| first.get(1).is_ok())
+            .collect::<Vec<bool>>();
+        let second_reads = (0..100)
+            .map(|_| second.get(1).is_ok())
+            .collect::<Vec<bool>>();
+
+        // then
+        assert_eq!(first_reads, second_reads);
+        assert!(first.failures() > 0 && first.failures() < 100);
+    }
+}
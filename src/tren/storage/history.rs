@@ -0,0 +1,224 @@
+// how much of the transactions logs is kept in memory, and where the rest goes
+// transactions leaving memory are appended to a spill file, in the format of `codec`, prefixed
+// by their length. Only where they are in the file is kept in memory, so that they can be read
+// back when a later row refers to them
+// the spill file is scratch space: it must not exist beforehand, so that no file is ever
+// overwritten, and it is removed once dropped
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::tren::{
+    client::ClientId,
+    transactions::{Transaction, TransactionId},
+};
+
+use super::codec::{decode_transaction, encode_transaction};
+
+/// Bounds of the transactions history kept in memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryPolicy {
+    /// transactions kept in memory per client, past those the oldest ones are spilled to disk
+    pub max_in_memory: usize,
+    /// file the spilled transactions are written to, which must not exist yet
+    pub spill_path: PathBuf,
+}
+
+/// Append-only file of the transactions spilled from memory, indexed by client and id
+pub struct SpillFile {
+    file: File,
+    path: PathBuf,
+    len: u64,
+    index: HashMap<(ClientId, TransactionId), u64>,
+}
+
+impl SpillFile {
+    /// An empty spill file at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file can't be created, `AlreadyExists` if there is already
+    /// a file at `path`
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        Ok(SpillFile {
+            file,
+            path: path.to_path_buf(),
+            len: 0,
+            index: HashMap::new(),
+        })
+    }
+
+    /// Number of transactions that can be read back
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    #[must_use]
+    pub fn contains(&self, client_id: ClientId, transaction_id: TransactionId) -> bool {
+        self.index.contains_key(&(client_id, transaction_id))
+    }
+
+    /// Write `transactions` at the end of the file, all of them or none. They can be read back
+    /// once `index`ed with the positions returned
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file can't be written, leaving it as it was
+    pub fn append(&mut self, transactions: &[&Transaction]) -> io::Result<Vec<u64>> {
+        let mut buffer = vec![];
+        let mut positions = vec![];
+        let mut record = vec![];
+        for transaction in transactions {
+            record.clear();
+            encode_transaction(transaction, &mut record);
+            positions.push(self.len + buffer.len() as u64);
+            let len = u32::try_from(record.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "record too long"))?;
+            buffer.extend(len.to_le_bytes());
+            buffer.extend(&record);
+        }
+
+        // after a failure what has been written is past `len`, overwritten by the next append
+        self.file.seek(SeekFrom::Start(self.len))?;
+        self.file.write_all(&buffer)?;
+        self.len += buffer.len() as u64;
+        Ok(positions)
+    }
+
+    /// Make the transactions `append`ed at `positions` readable. The first transaction spilled
+    /// with a given id is the one read back, like it would be found first in a log
+    pub fn index(&mut self, transactions: &[&Transaction], positions: &[u64]) {
+        for (transaction, position) in transactions.iter().zip(positions) {
+            self.index
+                .entry((transaction.client_id, transaction.transaction_id))
+                .or_insert(*position);
+        }
+    }
+
+    /// Stop reading back the transaction, e.g. because it is back in memory
+    pub fn forget(&mut self, client_id: ClientId, transaction_id: TransactionId) {
+        self.index.remove(&(client_id, transaction_id));
+    }
+
+    /// The spilled transaction of `client_id` with `transaction_id`, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns an `io::Error` if the file can't be read or the record is not valid
+    pub fn read(
+        &mut self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> io::Result<Option<Transaction>> {
+        let Some(position) = self.index.get(&(client_id, transaction_id)) else {
+            return Ok(None);
+        };
+        self.file.seek(SeekFrom::Start(*position))?;
+        let mut len = [0; 4];
+        self.file.read_exact(&mut len)?;
+        let mut record = vec![0; u32::from_le_bytes(len) as usize];
+        self.file.read_exact(&mut record)?;
+        decode_transaction(&record)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        // nothing to do if it is already gone
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::transactions::TransactionType;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn spill_file_test() {
+        // with
+        let path = std::env::temp_dir().join(format!("tren_spill_{}.bin", std::process::id()));
+        let mut spill = SpillFile::create(&path).expect("Expected the file to be created");
+        let first = Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(10)));
+        let duplicate = Transaction::new(TransactionType::Withdrawal, 1, 1, Some(dec!(5)));
+        let other = Transaction::new(TransactionType::Deposit, 2, 7, Some(dec!(1)));
+
+        // when
+        let spilled = [&first, &duplicate, &other];
+        let positions = spill
+            .append(&spilled)
+            .expect("Expected the file to be written");
+
+        // then: nothing readable until indexed
+        assert!(spill.is_empty());
+
+        // when
+        spill.index(&spilled, &positions);
+
+        // then
+        assert_eq!(spill.len(), 2);
+        assert_eq!(
+            spill.read(1, 1).expect("Expected the file to be read"),
+            Some(first)
+        );
+        assert_eq!(
+            spill.read(2, 7).expect("Expected the file to be read"),
+            Some(other)
+        );
+
+        // when
+        spill.forget(1, 1);
+
+        // then
+        assert!(!spill.contains(1, 1));
+        assert_eq!(
+            spill.read(1, 1).expect("Expected the file to be read"),
+            None
+        );
+
+        // when
+        drop(spill);
+
+        // then
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn existing_file_test() {
+        // with
+        let path =
+            std::env::temp_dir().join(format!("tren_spill_existing_{}.bin", std::process::id()));
+        std::fs::write(&path, "type, client, tx, amount\n")
+            .expect("Expected the file to be written");
+
+        // when
+        let result = SpillFile::create(&path);
+
+        // then: the file is left as it was
+        assert!(matches!(result, Err(err) if err.kind() == io::ErrorKind::AlreadyExists));
+        assert_eq!(
+            std::fs::read_to_string(&path).expect("Expected the file to be read"),
+            "type, client, tx, amount\n"
+        );
+
+        std::fs::remove_file(path).expect("Expected the file to be removed");
+    }
+}
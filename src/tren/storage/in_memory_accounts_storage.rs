@@ -4,21 +4,45 @@
 
 use std::collections::HashMap;
 
+use async_trait::async_trait;
+
 use crate::tren::{
     account::Account,
     client::ClientId,
     transactions::{Transaction, TransactionId},
 };
 
-use super::store::{AccountsStorage, StoreError};
+use super::{
+    async_store::{AsyncAccountsStorage, WriteBatch},
+    journal::Journal,
+    store::{AccountsStorage, StoreError},
+};
 
 #[derive(Default)]
 pub struct InMemoryAccountsStorage {
     accounts: HashMap<ClientId, Account>,
     accounts_transactions: HashMap<ClientId, Vec<Transaction>>,
+    // set while in a unit of work
+    journal: Option<Journal>,
 }
 
+#[async_trait]
 impl AccountsStorage for InMemoryAccountsStorage {
+    fn begin(&mut self) {
+        self.journal = Some(Journal::default());
+    }
+
+    async fn commit(&mut self) -> Result<(), StoreError> {
+        self.journal = None;
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+        if let Some(journal) = self.journal.take() {
+            journal.rollback(&mut self.accounts, &mut self.accounts_transactions);
+        }
+    }
+
     fn count_accounts(&self) -> usize {
         self.accounts.len()
     }
@@ -28,6 +52,9 @@ impl AccountsStorage for InMemoryAccountsStorage {
     }
 
     fn get_or_create(&mut self, client_id: ClientId) -> Result<&mut Account, StoreError> {
+        if let Some(journal) = &mut self.journal {
+            journal.account(&self.accounts, client_id);
+        }
         let account = self
             .accounts
             .entry(client_id)
@@ -41,6 +68,9 @@ impl AccountsStorage for InMemoryAccountsStorage {
     }
 
     fn put(&mut self, account: Account) -> Result<(), StoreError> {
+        if let Some(journal) = &mut self.journal {
+            journal.account(&self.accounts, account.client_id);
+        }
         self.accounts.insert(account.client_id, account);
         Ok(())
     }
@@ -50,6 +80,9 @@ impl AccountsStorage for InMemoryAccountsStorage {
     }
 
     fn push_transaction(&mut self, client_id: ClientId, transaction: Transaction) {
+        if let Some(journal) = &mut self.journal {
+            journal.log(&self.accounts_transactions, client_id);
+        }
         self.accounts_transactions
             .entry(client_id)
             .or_default()
@@ -61,6 +94,9 @@ impl AccountsStorage for InMemoryAccountsStorage {
     }
 
     fn get_transactions_mut(&mut self, client_id: ClientId) -> Option<&mut Vec<Transaction>> {
+        if let Some(journal) = &mut self.journal {
+            journal.entries(&self.accounts_transactions, client_id);
+        }
         self.accounts_transactions.get_mut(&client_id)
     }
 
@@ -85,20 +121,78 @@ impl AccountsStorage for InMemoryAccountsStorage {
         client_id: ClientId,
         transaction_id: TransactionId,
     ) -> Option<&mut Transaction> {
+        let position = self
+            .accounts_transactions
+            .get(&client_id)?
+            .iter()
+            .position(|t| (t.transaction_id == transaction_id) && (!t.is_disputing()))?;
+        if let Some(journal) = &mut self.journal {
+            journal.entry(&self.accounts_transactions, client_id, position);
+        }
         self.accounts_transactions
-            .get_mut(&client_id)?
-            .iter_mut()
-            .find(|t| (t.transaction_id == transaction_id) && (!t.is_disputing()))
+            .get_mut(&client_id)
+            .map(|log| &mut log[position])
+    }
+}
+
+// the in memory storage can stand in for an async one, e.g. as the backend of a
+// `CachedAccountsStorage`: nothing here ever waits
+#[async_trait]
+impl AsyncAccountsStorage for InMemoryAccountsStorage {
+    async fn load_account(&self, client_id: ClientId) -> Result<Option<Account>, StoreError> {
+        Ok(self.accounts.get(&client_id).cloned())
+    }
+
+    async fn load_accounts(&self) -> Result<Vec<Account>, StoreError> {
+        Ok(self.accounts.values().cloned().collect())
+    }
+
+    async fn load_transactions(&self, client_id: ClientId) -> Result<Vec<Transaction>, StoreError> {
+        Ok(self
+            .accounts_transactions
+            .get(&client_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        // all or nothing: check the updated transactions are there before writing anything
+        let updatable = batch.updated.iter().all(|(client_id, transaction)| {
+            self.find_non_disputing_transaction(*client_id, transaction.transaction_id)
+                .is_some()
+        });
+        if !updatable {
+            return Err(StoreError::WriteError);
+        }
+
+        for (client_id, transaction) in batch.updated {
+            if let Some(stored) =
+                self.find_non_disputing_transaction_mut(client_id, transaction.transaction_id)
+            {
+                *stored = transaction;
+            }
+        }
+        for (client_id, transactions) in batch.appended {
+            self.accounts_transactions
+                .entry(client_id)
+                .or_default()
+                .extend(transactions);
+        }
+        for account in batch.accounts {
+            self.accounts.insert(account.client_id, account);
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::tren::account::AccountStatus;
+    use std::collections::BTreeMap;
+
+    use crate::tren::account::{AccountStatus, Balance};
 
     use super::*;
 
-    use rust_decimal::Decimal;
     use rust_decimal_macros::dec;
 
     // super anti pattern of testing three things together but let me be time aware for this exercise :)
@@ -110,18 +204,17 @@ mod tests {
 
         let account = store.get_or_create(client_id).expect("Expected an account");
         assert_eq!(account.client_id, client_id);
-        assert_eq!(account.amount, Decimal::default());
-        assert_eq!(account.held_amount, Decimal::default());
+        assert_eq!(account.balance(None), Balance::default());
 
-        account.amount = dec!(100.50);
-        account.held_amount = dec!(10.00);
+        account.balance_mut(None).available = dec!(100.50);
+        account.balance_mut(None).held = dec!(10.00);
 
         let account = store
             .get(client_id)
             .expect("Expected an account")
             .expect("An account should have been found");
-        assert_eq!(account.amount, dec!(100.50));
-        assert_eq!(account.held_amount, dec!(10.00));
+        assert_eq!(account.balance(None).available, dec!(100.50));
+        assert_eq!(account.balance(None).held, dec!(10.00));
     }
 
     #[test]
@@ -143,8 +236,8 @@ mod tests {
             .expect("Expected success")
             .expect("An account should have been found");
 
-        assert_eq!(account.amount, dec!(0));
-        assert_eq!(account.held_amount, dec!(0));
+        assert_eq!(account.balance(None).available, dec!(0));
+        assert_eq!(account.balance(None).held, dec!(0));
 
         assert!(store
             .get(another_client_id)
@@ -160,17 +253,31 @@ mod tests {
         store
             .put(Account {
                 client_id: 1,
-                amount: dec!(100.0),
-                held_amount: dec!(5.0),
+                balances: BTreeMap::from([(
+                    None,
+                    Balance {
+                        available: dec!(100.0),
+                        held: dec!(5.0),
+                        ..Default::default()
+                    },
+                )]),
                 status: AccountStatus::Operational,
+                last_activity: None,
             })
             .expect("Account should have been put there");
         store
             .put(Account {
                 client_id: 2,
-                amount: dec!(200.0),
-                held_amount: dec!(15.0),
+                balances: BTreeMap::from([(
+                    None,
+                    Balance {
+                        available: dec!(200.0),
+                        held: dec!(15.0),
+                        ..Default::default()
+                    },
+                )]),
                 status: AccountStatus::Frozen,
+                last_activity: None,
             })
             .expect("Account should have been put there");
 
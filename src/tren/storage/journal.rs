@@ -0,0 +1,102 @@
+// undo journal of a unit of work over in-memory accounts and transaction logs
+// the first change to an account or to a log records what it was before: rolling back puts
+// those back, committing just forgets them
+// logs only grow, so the length they had is enough to undo pushes; entries changed in place
+// are recorded one by one
+
+use std::collections::HashMap;
+
+use crate::tren::{account::Account, client::ClientId, transactions::Transaction};
+
+#[derive(Default)]
+pub struct Journal {
+    // none if the account was not there
+    accounts: HashMap<ClientId, Option<Account>>,
+    logs: HashMap<ClientId, LogImage>,
+}
+
+struct LogImage {
+    // none if the log was not there
+    len: Option<usize>,
+    // entries changed in place, by position
+    entries: HashMap<usize, Transaction>,
+}
+
+impl Journal {
+    /// Record the account of `client_id`, before it changes
+    pub fn account(&mut self, accounts: &HashMap<ClientId, Account>, client_id: ClientId) {
+        self.accounts
+            .entry(client_id)
+            .or_insert_with(|| accounts.get(&client_id).cloned());
+    }
+
+    /// Record the length of the log of `client_id`, before something is pushed to it
+    pub fn log(&mut self, logs: &HashMap<ClientId, Vec<Transaction>>, client_id: ClientId) {
+        self.log_image(logs, client_id);
+    }
+
+    /// Record the entry of the log of `client_id` at `position`, before it changes
+    pub fn entry(
+        &mut self,
+        logs: &HashMap<ClientId, Vec<Transaction>>,
+        client_id: ClientId,
+        position: usize,
+    ) {
+        let image = self.log_image(logs, client_id);
+        // entries pushed in this unit of work go away anyway
+        if image.len.is_some_and(|len| position < len) {
+            if let Some(transaction) = logs.get(&client_id).and_then(|log| log.get(position)) {
+                image
+                    .entries
+                    .entry(position)
+                    .or_insert_with(|| transaction.clone());
+            }
+        }
+    }
+
+    /// Record the whole log of `client_id`, before it is handed out mutably
+    pub fn entries(&mut self, logs: &HashMap<ClientId, Vec<Transaction>>, client_id: ClientId) {
+        let len = logs.get(&client_id).map_or(0, Vec::len);
+        for position in 0..len {
+            self.entry(logs, client_id, position);
+        }
+    }
+
+    /// Put back everything recorded
+    pub fn rollback(
+        self,
+        accounts: &mut HashMap<ClientId, Account>,
+        logs: &mut HashMap<ClientId, Vec<Transaction>>,
+    ) {
+        for (client_id, account) in self.accounts {
+            match account {
+                Some(account) => accounts.insert(client_id, account),
+                None => accounts.remove(&client_id),
+            };
+        }
+
+        for (client_id, image) in self.logs {
+            let Some(len) = image.len else {
+                logs.remove(&client_id);
+                continue;
+            };
+            if let Some(log) = logs.get_mut(&client_id) {
+                log.truncate(len);
+                for (position, transaction) in image.entries {
+                    log[position] = transaction;
+                }
+            }
+        }
+    }
+
+    fn log_image(
+        &mut self,
+        logs: &HashMap<ClientId, Vec<Transaction>>,
+        client_id: ClientId,
+    ) -> &mut LogImage {
+        self.logs.entry(client_id).or_insert_with(|| LogImage {
+            len: logs.get(&client_id).map(Vec::len),
+            entries: HashMap::new(),
+        })
+    }
+}
@@ -0,0 +1,447 @@
+// storage backed by an embedded key-value store (redb, a B-tree in a single file)
+// accounts and transactions are written in the binary format of `codec`, under big-endian keys so
+// that the entries of a client are next to each other and in order:
+// - `accounts`: client -> account
+// - `transactions`: (client, position) -> transaction, positions counting the pushes to the log
+// - `positions`: (client, tx) -> position of the first non disputing transaction with that tx
+// logs are keyed by position rather than by tx because a tx is not unique in a log: disputes,
+// resolves, chargebacks, reversals, captures, voids and fees share the tx of the transaction
+// they refer to, and the input may repeat a tx. Keyed by position, the log is read back in the
+// order it was pushed, duplicates included, like the logs of the in-memory storages, and
+// `positions` finds the transaction a later row refers to, like `find_non_disputing_transaction`
+// a `WriteBatch` is a single write transaction. For batch runs to write fast, transactions are
+// committed in memory and only made durable every `SYNC_EVERY` writes and on `sync`, which is
+// to be called at the end of a run: a crash loses the last writes, the run is to be restarted
+// from its input anyway. Dropping the storage syncs too, as a fallback with no error to report
+// the store does blocking I/O, it is run on the blocking threads of the runtime. Handlers use it
+// through a `CachedAccountsStorage`
+
+use std::{path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use redb::{Database, Durability, ReadableTable, Table, TableDefinition, WriteTransaction};
+use tokio::runtime::RuntimeFlavor;
+
+use crate::tren::{
+    account::Account,
+    client::ClientId,
+    transactions::{Transaction, TransactionId},
+};
+
+use super::{
+    async_store::{AsyncAccountsStorage, WriteBatch},
+    codec::{decode_account, decode_transaction, encode_account, encode_transaction},
+    store::StoreError,
+};
+
+const ACCOUNTS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("accounts");
+const TRANSACTIONS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("transactions");
+const POSITIONS: TableDefinition<&[u8], u64> = TableDefinition::new("positions");
+
+const SYNC_EVERY: usize = 1000;
+
+pub struct KvAccountsStorage {
+    database: Arc<Database>,
+    // writes committed since the last durable one
+    unsynced: usize,
+}
+
+impl KvAccountsStorage {
+    /// The storage in the file at `path`, created if it doesn't exist
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the file can't be opened, or is not a store
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        let database = Database::create(path).map_err(|_| StoreError::ReadError)?;
+        // tables must exist for reads
+        let txn = database.begin_write().map_err(|_| StoreError::WriteError)?;
+        {
+            txn.open_table(ACCOUNTS)
+                .map_err(|_| StoreError::WriteError)?;
+            txn.open_table(TRANSACTIONS)
+                .map_err(|_| StoreError::WriteError)?;
+            txn.open_table(POSITIONS)
+                .map_err(|_| StoreError::WriteError)?;
+        }
+        txn.commit().map_err(|_| StoreError::WriteError)?;
+        Ok(KvAccountsStorage {
+            database: Arc::new(database),
+            unsynced: 0,
+        })
+    }
+
+    // run `f` on the database, off the runtime threads
+    async fn blocking<T, F>(&self, f: F, error: StoreError) -> Result<T, StoreError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database) -> Result<T, StoreError> + Send + 'static,
+    {
+        let database = Arc::clone(&self.database);
+        tokio::task::spawn_blocking(move || f(&database))
+            .await
+            .map_err(|_| error)?
+    }
+}
+
+// an empty durable commit makes the commits before it durable as well
+fn sync(database: &Database) -> Result<(), StoreError> {
+    let txn = database.begin_write().map_err(|_| StoreError::WriteError)?;
+    txn.commit().map_err(|_| StoreError::WriteError)
+}
+
+fn client_key(client_id: ClientId) -> Vec<u8> {
+    client_id.to_be_bytes().to_vec()
+}
+
+fn transaction_key(client_id: ClientId, position: u64) -> Vec<u8> {
+    let mut key = client_key(client_id);
+    key.extend(position.to_be_bytes());
+    key
+}
+
+fn position_key(client_id: ClientId, transaction_id: TransactionId) -> Vec<u8> {
+    let mut key = client_key(client_id);
+    key.extend(transaction_id.to_be_bytes());
+    key
+}
+
+fn load_account(database: &Database, client_id: ClientId) -> Result<Option<Account>, StoreError> {
+    let txn = database.begin_read().map_err(|_| StoreError::ReadError)?;
+    let table = txn
+        .open_table(ACCOUNTS)
+        .map_err(|_| StoreError::ReadError)?;
+    let Some(value) = table
+        .get(client_key(client_id).as_slice())
+        .map_err(|_| StoreError::ReadError)?
+    else {
+        return Ok(None);
+    };
+    decode_account(value.value())
+        .map(Some)
+        .map_err(|_| StoreError::ReadError)
+}
+
+fn load_accounts(database: &Database) -> Result<Vec<Account>, StoreError> {
+    let txn = database.begin_read().map_err(|_| StoreError::ReadError)?;
+    let table = txn
+        .open_table(ACCOUNTS)
+        .map_err(|_| StoreError::ReadError)?;
+    table
+        .iter()
+        .map_err(|_| StoreError::ReadError)?
+        .map(|entry| {
+            let (_, value) = entry.map_err(|_| StoreError::ReadError)?;
+            decode_account(value.value()).map_err(|_| StoreError::ReadError)
+        })
+        .collect()
+}
+
+fn load_transactions(
+    database: &Database,
+    client_id: ClientId,
+) -> Result<Vec<Transaction>, StoreError> {
+    let txn = database.begin_read().map_err(|_| StoreError::ReadError)?;
+    let table = txn
+        .open_table(TRANSACTIONS)
+        .map_err(|_| StoreError::ReadError)?;
+    let (first, last) = (
+        transaction_key(client_id, 0),
+        transaction_key(client_id, u64::MAX),
+    );
+    table
+        .range(first.as_slice()..=last.as_slice())
+        .map_err(|_| StoreError::ReadError)?
+        .map(|entry| {
+            let (_, value) = entry.map_err(|_| StoreError::ReadError)?;
+            decode_transaction(value.value()).map_err(|_| StoreError::ReadError)
+        })
+        .collect()
+}
+
+// position the next transaction pushed to the log of `client_id` goes to
+fn next_position(
+    transactions: &Table<&[u8], &[u8]>,
+    client_id: ClientId,
+) -> Result<u64, StoreError> {
+    let (first, last) = (
+        transaction_key(client_id, 0),
+        transaction_key(client_id, u64::MAX),
+    );
+    let Some(entry) = transactions
+        .range(first.as_slice()..=last.as_slice())
+        .map_err(|_| StoreError::WriteError)?
+        .next_back()
+    else {
+        return Ok(0);
+    };
+    let (key, _) = entry.map_err(|_| StoreError::WriteError)?;
+    let position = key
+        .value()
+        .last_chunk()
+        .copied()
+        .ok_or(StoreError::WriteError)?;
+    Ok(u64::from_be_bytes(position) + 1)
+}
+
+fn apply(txn: &WriteTransaction, batch: WriteBatch) -> Result<(), StoreError> {
+    let mut accounts = txn
+        .open_table(ACCOUNTS)
+        .map_err(|_| StoreError::WriteError)?;
+    let mut transactions = txn
+        .open_table(TRANSACTIONS)
+        .map_err(|_| StoreError::WriteError)?;
+    let mut positions = txn
+        .open_table(POSITIONS)
+        .map_err(|_| StoreError::WriteError)?;
+    let mut value = vec![];
+
+    // updates refer to what was there before the batch, like in the other storages
+    for (client_id, transaction) in batch.updated {
+        let position = positions
+            .get(position_key(client_id, transaction.transaction_id).as_slice())
+            .map_err(|_| StoreError::WriteError)?
+            .ok_or(StoreError::WriteError)?
+            .value();
+        value.clear();
+        encode_transaction(&transaction, &mut value);
+        transactions
+            .insert(
+                transaction_key(client_id, position).as_slice(),
+                value.as_slice(),
+            )
+            .map_err(|_| StoreError::WriteError)?;
+    }
+    for (client_id, appended) in batch.appended {
+        let first = next_position(&transactions, client_id)?;
+        for (position, transaction) in (first..).zip(appended) {
+            if !transaction.is_disputing() {
+                let key = position_key(client_id, transaction.transaction_id);
+                let indexed = positions
+                    .get(key.as_slice())
+                    .map_err(|_| StoreError::WriteError)?
+                    .is_some();
+                if !indexed {
+                    positions
+                        .insert(key.as_slice(), position)
+                        .map_err(|_| StoreError::WriteError)?;
+                }
+            }
+            value.clear();
+            encode_transaction(&transaction, &mut value);
+            transactions
+                .insert(
+                    transaction_key(client_id, position).as_slice(),
+                    value.as_slice(),
+                )
+                .map_err(|_| StoreError::WriteError)?;
+        }
+    }
+    for account in batch.accounts {
+        value.clear();
+        encode_account(&account, &mut value);
+        accounts
+            .insert(client_key(account.client_id).as_slice(), value.as_slice())
+            .map_err(|_| StoreError::WriteError)?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl AsyncAccountsStorage for KvAccountsStorage {
+    async fn load_account(&self, client_id: ClientId) -> Result<Option<Account>, StoreError> {
+        self.blocking(
+            move |database| load_account(database, client_id),
+            StoreError::ReadError,
+        )
+        .await
+    }
+
+    async fn load_accounts(&self) -> Result<Vec<Account>, StoreError> {
+        self.blocking(load_accounts, StoreError::ReadError).await
+    }
+
+    async fn load_transactions(&self, client_id: ClientId) -> Result<Vec<Transaction>, StoreError> {
+        self.blocking(
+            move |database| load_transactions(database, client_id),
+            StoreError::ReadError,
+        )
+        .await
+    }
+
+    async fn write(&mut self, batch: WriteBatch) -> Result<(), StoreError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.unsynced += 1;
+        let durability = if self.unsynced >= SYNC_EVERY {
+            self.unsynced = 0;
+            Durability::Immediate
+        } else {
+            Durability::None
+        };
+        self.blocking(
+            move |database| {
+                let mut txn = database.begin_write().map_err(|_| StoreError::WriteError)?;
+                txn.set_durability(durability);
+                match apply(&txn, batch) {
+                    Ok(()) => txn.commit().map_err(|_| StoreError::WriteError),
+                    Err(err) => {
+                        txn.abort().map_err(|_| StoreError::WriteError)?;
+                        Err(err)
+                    }
+                }
+            },
+            StoreError::WriteError,
+        )
+        .await
+    }
+
+    /// Make the writes so far durable, which is otherwise done every `SYNC_EVERY` writes
+    async fn sync(&mut self) -> Result<(), StoreError> {
+        if self.unsynced == 0 {
+            return Ok(());
+        }
+        self.blocking(sync, StoreError::WriteError).await?;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
+
+impl Drop for KvAccountsStorage {
+    fn drop(&mut self) {
+        if self.unsynced == 0 {
+            return;
+        }
+        // not synced at the end of the run, e.g. after an error. Nothing to report the error
+        // to, the last writes are lost like after a crash. The runtime threads are told they
+        // are blocked, where they can be
+        let multi_thread = tokio::runtime::Handle::try_current()
+            .is_ok_and(|handle| handle.runtime_flavor() == RuntimeFlavor::MultiThread);
+        if multi_thread {
+            let _ = tokio::task::block_in_place(|| sync(&self.database));
+        } else {
+            let _ = sync(&self.database);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tren::transactions::{TransactionStatus, TransactionType};
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn kv_storage_test() {
+        // with
+        let path = std::env::temp_dir().join(format!("tren_kv_{}.redb", std::process::id()));
+        let mut store = KvAccountsStorage::open(&path).expect("Expected a storage");
+        let mut account = Account::new(1);
+        account.balance_mut(None).deposit(dec!(10));
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(10)));
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        let other = Transaction::new(TransactionType::Deposit, 2, 1, Some(dec!(3)));
+
+        // when
+        store
+            .write(WriteBatch {
+                accounts: vec![account.clone()],
+                appended: vec![
+                    (1, vec![deposit.clone(), dispute.clone()]),
+                    (2, vec![other]),
+                ],
+                updated: vec![],
+            })
+            .await
+            .expect("Expected the batch to be written");
+        let mut disputed = deposit.clone();
+        disputed.dispute(dec!(10));
+        store
+            .write(WriteBatch {
+                updated: vec![(1, disputed.clone())],
+                ..WriteBatch::default()
+            })
+            .await
+            .expect("Expected the batch to be written");
+
+        // then: all or nothing
+        let missing = Transaction::new(TransactionType::Deposit, 1, 9, Some(dec!(1)));
+        let refused = store
+            .write(WriteBatch {
+                appended: vec![(3, vec![deposit.clone()])],
+                updated: vec![(1, missing)],
+                ..WriteBatch::default()
+            })
+            .await;
+        assert!(matches!(refused, Err(StoreError::WriteError)));
+
+        // when
+        store
+            .sync()
+            .await
+            .expect("Expected the writes to be synced");
+        assert_eq!(store.unsynced, 0);
+        drop(store);
+        let store = KvAccountsStorage::open(&path).expect("Expected a storage");
+
+        // then
+        let loaded = store
+            .load_account(1)
+            .await
+            .expect("Expected the account to be read")
+            .expect("Expected an account");
+        assert_eq!(loaded.balances, account.balances);
+        assert_eq!(
+            store
+                .load_accounts()
+                .await
+                .expect("Expected the accounts to be read")
+                .len(),
+            1
+        );
+        let log = store
+            .load_transactions(1)
+            .await
+            .expect("Expected the transactions to be read");
+        assert_eq!(log, vec![disputed, dispute]);
+        assert_eq!(log[0].status, TransactionStatus::Disputed);
+        assert_eq!(
+            store
+                .load_transactions(3)
+                .await
+                .expect("Expected the transactions to be read"),
+            vec![]
+        );
+
+        std::fs::remove_file(path).expect("Expected the file to be removed");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn kv_storage_dropped_test() {
+        // with: writes never synced
+        let path =
+            std::env::temp_dir().join(format!("tren_kv_dropped_{}.redb", std::process::id()));
+        let mut store = KvAccountsStorage::open(&path).expect("Expected a storage");
+        store
+            .write(WriteBatch {
+                accounts: vec![Account::new(1)],
+                ..WriteBatch::default()
+            })
+            .await
+            .expect("Expected the batch to be written");
+
+        // when
+        drop(store);
+        let store = KvAccountsStorage::open(&path).expect("Expected a storage");
+
+        // then: synced on the way out, from a runtime thread, without panicking
+        assert!(store
+            .load_account(1)
+            .await
+            .expect("Expected the account to be read")
+            .is_some());
+
+        std::fs::remove_file(path).expect("Expected the file to be removed");
+    }
+}
@@ -1,2 +1,13 @@
+pub mod async_store;
+pub mod bounded_accounts_storage;
+pub mod cached_accounts_storage;
+pub mod codec;
+// failing storages are only there to test failures, not shipped in release builds
+#[cfg(any(test, feature = "chaos"))]
+pub mod faulty_accounts_storage;
+pub mod history;
 pub mod in_memory_accounts_storage;
+pub mod journal;
+pub mod kv_accounts_storage;
+pub mod overlay_accounts_storage;
 pub mod store;
@@ -0,0 +1,358 @@
+// copy-on-write storage layered over another storage, which is never modified
+// used for dry runs: the base is only read, every change lands in the overlay and is
+// discarded together with it
+// copies happen per client: the first write on a client copies its account and its
+// transaction log from the base
+// the base is never loaded: it must already hold what the dry run reads
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::tren::{
+    account::Account,
+    client::ClientId,
+    diff::balance_diff::{AccountSnapshot, BalanceDiff},
+    transactions::{Transaction, TransactionId},
+};
+
+use super::{
+    journal::Journal,
+    store::{AccountsStorage, StoreError},
+};
+
+pub struct OverlayAccountsStorage<'a, S: AccountsStorage> {
+    base: &'a S,
+    accounts: HashMap<ClientId, Account>,
+    accounts_transactions: HashMap<ClientId, Vec<Transaction>>,
+    // set while in a unit of work, undoing a change puts back the base's version
+    journal: Option<Journal>,
+}
+
+impl<'a, S: AccountsStorage> OverlayAccountsStorage<'a, S> {
+    #[must_use]
+    pub fn new(base: &'a S) -> Self {
+        OverlayAccountsStorage {
+            base,
+            accounts: HashMap::new(),
+            accounts_transactions: HashMap::new(),
+            journal: None,
+        }
+    }
+
+    /// Changes of the overlay with respect to the base, ordered by client id
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the base storage fails to read an account
+    pub fn diff(&self) -> Result<BalanceDiff, StoreError> {
+        let mut before = vec![];
+        let mut after = vec![];
+
+        for account in self.accounts.values() {
+            if let Some(base_account) = self.base.get(account.client_id)? {
+                before.extend(AccountSnapshot::of(base_account));
+            }
+            after.extend(AccountSnapshot::of(account));
+        }
+
+        Ok(BalanceDiff::compare(before, after))
+    }
+
+    // copy on write of a client's transactions log
+    fn transactions_mut(&mut self, client_id: ClientId) -> &mut Vec<Transaction> {
+        if let Some(journal) = &mut self.journal {
+            journal.log(&self.accounts_transactions, client_id);
+        }
+        let base = self.base;
+        self.accounts_transactions
+            .entry(client_id)
+            .or_insert_with(|| {
+                base.get_transactions(client_id)
+                    .cloned()
+                    .unwrap_or_default()
+            })
+    }
+
+    fn shadowed(&self, client_id: ClientId) -> bool {
+        self.accounts.contains_key(&client_id)
+    }
+}
+
+#[async_trait]
+impl<S: AccountsStorage> AccountsStorage for OverlayAccountsStorage<'_, S> {
+    fn begin(&mut self) {
+        self.journal = Some(Journal::default());
+    }
+
+    async fn commit(&mut self) -> Result<(), StoreError> {
+        self.journal = None;
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+        if let Some(journal) = self.journal.take() {
+            journal.rollback(&mut self.accounts, &mut self.accounts_transactions);
+        }
+    }
+
+    fn count_accounts(&self) -> usize {
+        let created = self
+            .accounts
+            .keys()
+            .filter(|client_id| matches!(self.base.get(**client_id), Ok(None)))
+            .count();
+        self.base.count_accounts() + created
+    }
+
+    fn all_accounts_iter(&self) -> Box<dyn Iterator<Item = &Account> + '_> {
+        Box::new(
+            self.accounts.values().chain(
+                self.base
+                    .all_accounts_iter()
+                    .filter(|account| !self.shadowed(account.client_id)),
+            ),
+        )
+    }
+
+    fn get_or_create(&mut self, client_id: ClientId) -> Result<&mut Account, StoreError> {
+        if let Some(journal) = &mut self.journal {
+            journal.account(&self.accounts, client_id);
+        }
+        if !self.shadowed(client_id) {
+            let account = self
+                .base
+                .get(client_id)?
+                .cloned()
+                .unwrap_or_else(|| Account::new(client_id));
+            self.accounts.insert(client_id, account);
+        }
+
+        self.accounts
+            .get_mut(&client_id)
+            .ok_or(StoreError::ReadError)
+    }
+
+    fn get(&self, client_id: ClientId) -> Result<Option<&Account>, StoreError> {
+        match self.accounts.get(&client_id) {
+            Some(account) => Ok(Some(account)),
+            None => self.base.get(client_id),
+        }
+    }
+
+    fn put(&mut self, account: Account) -> Result<(), StoreError> {
+        if let Some(journal) = &mut self.journal {
+            journal.account(&self.accounts, account.client_id);
+        }
+        self.accounts.insert(account.client_id, account);
+        Ok(())
+    }
+
+    fn list(&self) -> Vec<&Account> {
+        self.all_accounts_iter().collect()
+    }
+
+    fn push_transaction(&mut self, client_id: ClientId, transaction: Transaction) {
+        self.transactions_mut(client_id).push(transaction);
+    }
+
+    fn get_transactions(&self, client_id: ClientId) -> Option<&Vec<Transaction>> {
+        match self.accounts_transactions.get(&client_id) {
+            Some(transactions) => Some(transactions),
+            None => self.base.get_transactions(client_id),
+        }
+    }
+
+    fn get_transactions_mut(&mut self, client_id: ClientId) -> Option<&mut Vec<Transaction>> {
+        self.get_transactions(client_id)?;
+        // copied first, entries copied in this unit of work go away with the copy anyway
+        self.transactions_mut(client_id);
+        if let Some(journal) = &mut self.journal {
+            journal.entries(&self.accounts_transactions, client_id);
+        }
+        Some(self.transactions_mut(client_id))
+    }
+
+    fn find_non_disputing_transaction(
+        &self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> Option<&Transaction> {
+        match self.accounts_transactions.get(&client_id) {
+            Some(transactions) => transactions
+                .iter()
+                .find(|t| (t.transaction_id == transaction_id) && (!t.is_disputing())),
+            None => self
+                .base
+                .find_non_disputing_transaction(client_id, transaction_id),
+        }
+    }
+
+    fn find_non_disputing_transaction_mut(
+        &mut self,
+        client_id: ClientId,
+        transaction_id: TransactionId,
+    ) -> Option<&mut Transaction> {
+        // don't copy anything if there is nothing to be found
+        self.find_non_disputing_transaction(client_id, transaction_id)?;
+
+        let position = self
+            .transactions_mut(client_id)
+            .iter()
+            .position(|t| (t.transaction_id == transaction_id) && (!t.is_disputing()))?;
+        if let Some(journal) = &mut self.journal {
+            journal.entry(&self.accounts_transactions, client_id, position);
+        }
+        self.transactions_mut(client_id).get_mut(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tren::storage::in_memory_accounts_storage::InMemoryAccountsStorage;
+    use crate::tren::transactions::{TransactionStatus, TransactionType};
+    use rust_decimal_macros::dec;
+
+    fn base() -> InMemoryAccountsStorage {
+        let mut base = InMemoryAccountsStorage::default();
+        let mut account = Account::new(1);
+        account.balance_mut(None).deposit(dec!(10));
+        base.put(account)
+            .expect("Account should have been put there");
+
+        let mut deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(dec!(10)));
+        deposit.executed();
+        base.push_transaction(1, deposit);
+        base
+    }
+
+    #[test]
+    fn base_is_never_modified_test() {
+        // with
+        let base = base();
+        let mut overlay = OverlayAccountsStorage::new(&base);
+
+        // when
+        overlay
+            .get_or_create(1)
+            .expect("Expected an account")
+            .balance_mut(None)
+            .deposit(dec!(5));
+        overlay
+            .find_non_disputing_transaction_mut(1, 1)
+            .expect("Transaction should have been found")
+            .dispute(dec!(10));
+        overlay.get_or_create(2).expect("Expected an account");
+
+        // then
+        assert_eq!(overlay.count_accounts(), 2);
+        assert_eq!(
+            overlay
+                .get(1)
+                .expect("Expected success")
+                .expect("Expected account")
+                .balance(None)
+                .available,
+            dec!(15)
+        );
+        assert_eq!(
+            overlay
+                .find_non_disputing_transaction(1, 1)
+                .expect("Transaction should have been found")
+                .status,
+            TransactionStatus::Disputed
+        );
+
+        assert_eq!(base.count_accounts(), 1);
+        assert_eq!(
+            base.get(1)
+                .expect("Expected success")
+                .expect("Expected account")
+                .balance(None)
+                .available,
+            dec!(10)
+        );
+        assert_eq!(
+            base.find_non_disputing_transaction(1, 1)
+                .expect("Transaction should have been found")
+                .status,
+            TransactionStatus::Executed
+        );
+    }
+
+    #[test]
+    fn diff_test() {
+        // with
+        let base = base();
+        let mut overlay = OverlayAccountsStorage::new(&base);
+
+        // when
+        overlay
+            .get_or_create(1)
+            .expect("Expected an account")
+            .balance_mut(None)
+            .withdraw(dec!(4))
+            .expect("Expected enough funds");
+        overlay
+            .get_or_create(2)
+            .expect("Expected an account")
+            .balance_mut(None)
+            .deposit(dec!(1));
+        let diff = overlay.diff().expect("Expected a diff");
+
+        // then
+        assert_eq!(diff.changes.len(), 2);
+        assert_eq!(diff.changes[0].client, 1);
+        assert_eq!(diff.changes[0].available_delta(), dec!(-4));
+        assert!(diff.changes[1].before.is_none());
+        assert_eq!(diff.changes[1].total_delta(), dec!(1));
+    }
+
+    #[tokio::test]
+    async fn rollback_test() {
+        // with
+        let base = base();
+        let mut overlay = OverlayAccountsStorage::new(&base);
+        overlay
+            .get_or_create(1)
+            .expect("Expected an account")
+            .balance_mut(None)
+            .deposit(dec!(5));
+
+        // when
+        overlay.begin();
+        overlay
+            .get_or_create(1)
+            .expect("Expected an account")
+            .balance_mut(None)
+            .deposit(dec!(1));
+        overlay
+            .find_non_disputing_transaction_mut(1, 1)
+            .expect("Transaction should have been found")
+            .dispute(dec!(10));
+        overlay.push_transaction(
+            1,
+            Transaction::new(TransactionType::Dispute, 1, 1, Some(dec!(10))),
+        );
+        overlay.get_or_create(2).expect("Expected an account");
+        overlay.rollback();
+
+        // then: only the change before the unit of work is left
+        assert_eq!(overlay.count_accounts(), 1);
+        assert_eq!(
+            overlay
+                .get(1)
+                .expect("Expected success")
+                .expect("Expected account")
+                .balance(None)
+                .available,
+            dec!(15)
+        );
+        let log = overlay
+            .get_transactions(1)
+            .expect("Transactions should exist");
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].status, TransactionStatus::Executed);
+    }
+}
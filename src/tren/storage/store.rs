@@ -1,7 +1,12 @@
 // trait to define handlers for storing account data
 // here may be put stuff like databases ecc
-// avoiding transactional concepts for now, let's suppose locks happen
-// at business logic level
+// handlers work on borrowed accounts synchronously: storages backed by something slower than
+// memory fetch what a transaction needs in `load` and write the changes back in `commit`, both
+// awaited by the runner around each transaction (see `AsyncAccountsStorage`)
+// each transaction is a unit of work: its changes to accounts and logs are either all kept or
+// all undone, so that a failure halfway through never leaves an account half updated
+
+use async_trait::async_trait;
 
 use crate::tren::{
     account::Account,
@@ -18,7 +23,65 @@ pub enum StoreError {
     ReadError,
 }
 
-pub trait AccountsStorage {
+/// Accounts a transaction may read or write
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccountsScope {
+    Clients(Vec<ClientId>),
+    /// e.g. for rows going through all the accounts, like ticks
+    All,
+}
+
+impl AccountsScope {
+    /// Just the account of `client_id`
+    #[must_use]
+    pub fn client(client_id: ClientId) -> Self {
+        AccountsScope::Clients(vec![client_id])
+    }
+
+    /// No account at all
+    #[must_use]
+    pub const fn none() -> Self {
+        AccountsScope::Clients(Vec::new())
+    }
+}
+
+#[async_trait]
+pub trait AccountsStorage: Send + Sync {
+    /// Make the accounts in `scope` and their transactions available to the other methods.
+    /// Storages holding everything in memory have nothing to do
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the storage fails to read the accounts.
+    async fn load(&mut self, _scope: &AccountsScope) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    /// Start a unit of work: the changes made from now on are either all kept by `commit`
+    /// or all undone by `rollback`
+    fn begin(&mut self);
+
+    /// Keep, and persist where needed, the changes of the unit of work
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the storage fails to write the changes. Nothing is kept then,
+    /// like after a `rollback`
+    async fn commit(&mut self) -> Result<(), StoreError>;
+
+    /// Undo all the changes of the unit of work
+    fn rollback(&mut self);
+
+    /// Make the committed changes durable, for storages that delay it. Nothing to do for the
+    /// others
+    ///
+    /// # Errors
+    ///
+    /// Returns a `StoreError` if the storage fails to write to disk
+    async fn sync(&mut self) -> Result<(), StoreError> {
+        Ok(())
+    }
+
     // Accounts
     /// just count accounts
     fn count_accounts(&self) -> usize;
@@ -1,14 +1,31 @@
 // Transaction types and utils
 //
 
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::tren::client::ClientId;
+use crate::tren::{client::ClientId, ids};
 
 pub type Amount = Decimal;
+/// 32 bit by default, see `ids` for wider representations
+#[cfg(not(any(feature = "wide-ids", feature = "uuid-ids")))]
 pub type TransactionId = u32;
+#[cfg(all(feature = "wide-ids", not(feature = "uuid-ids")))]
+pub type TransactionId = u64;
+#[cfg(feature = "uuid-ids")]
+pub type TransactionId = u128;
+/// When a transaction happened, e.g. `2025-02-18T10:00:00Z`
+pub type Timestamp = DateTime<Utc>;
+/// ISO 4217 code of the currency an amount is in, e.g. `EUR`
+pub type Currency = String;
+
+/// Whether `currency` is a three uppercase letters code, e.g. `EUR`
+#[must_use]
+pub fn is_valid_currency(currency: &str) -> bool {
+    currency.len() == 3 && currency.chars().all(|c| c.is_ascii_uppercase())
+}
 
 #[derive(Error, Debug)]
 pub enum TransactionError {
@@ -17,7 +34,7 @@ pub enum TransactionError {
 }
 
 // I know I could probably rename_all but I prefer to be explicit to avoid renaming/adding confusion
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Clone)]
 pub enum TransactionType {
     /// an amount is being added to the funds
     #[serde(rename = "deposit")]
@@ -32,14 +49,61 @@ pub enum TransactionType {
     Resolve,
     #[serde(rename = "chargeback")]
     Chargeback,
+    /// an amount is reserved for a card payment, until it is captured or voided
+    #[serde(rename = "authorize")]
+    Authorize,
+    /// the authorization with the same tx is settled, for its amount or the amount of the row
+    #[serde(rename = "capture")]
+    Capture,
+    /// the authorization with the same tx is cancelled
+    #[serde(rename = "void")]
+    Void,
+    /// a mistaken deposit or withdrawal is cancelled, without freezing the account
+    #[serde(rename = "reversal")]
+    Reversal,
+    /// an amount in `currency` is converted to `to_currency` in the same account
+    #[serde(rename = "exchange")]
+    Exchange,
+    /// control row telling the engine to post interest on positive balances.
+    /// client and tx are ignored
+    #[serde(rename = "accrue")]
+    Accrue,
+    /// fee charged for the transaction with the same tx. Generated by the engine, never read
+    #[serde(rename = "fee")]
+    Fee,
+    /// admin row setting the credit limit of the `currency` balance to `amount`
+    #[serde(rename = "credit_limit")]
+    CreditLimit,
+    /// admin row opening an account for the client, e.g. when only known clients are allowed
+    #[serde(rename = "open")]
+    Open,
+    /// admin row suspending the account: deposits are allowed, withdrawals are blocked
+    #[serde(rename = "suspend")]
+    Suspend,
+    /// admin row making a suspended, dormant or frozen account operational again
+    #[serde(rename = "activate")]
+    Activate,
+    /// admin row closing the account for good, its balance must be zero
+    #[serde(rename = "close")]
+    Close,
+    /// control row telling the engine what time it is, e.g. at the end of a day.
+    /// client and tx are ignored
+    #[serde(rename = "tick")]
+    Tick,
 }
 
-#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
 pub enum TransactionStatus {
     Ready,
     Executed,
     Disputed,
     ChargedBack,
+    /// cancelled by a reversal, can't be disputed anymore
+    Reversed,
+    /// authorization settled by a capture
+    Captured,
+    /// authorization cancelled by a void, or expired
+    Voided,
     Skipped,
 }
 
@@ -47,18 +111,43 @@ fn default_status() -> TransactionStatus {
     TransactionStatus::Ready
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct Transaction {
     #[serde(rename = "type")]
     pub transaction_type: TransactionType,
-    #[serde(rename = "client")]
+    #[serde(rename = "client", with = "ids")]
     pub client_id: ClientId,
-    #[serde(rename = "tx")]
+    #[serde(rename = "tx", with = "ids")]
     pub transaction_id: TransactionId,
     #[serde(rename = "amount")]
     pub amount: Option<Amount>,
     #[serde(default = "default_status")]
     pub status: TransactionStatus,
+    /// optional, files without a timestamp column are processed in file order only
+    #[serde(rename = "timestamp", default)]
+    pub timestamp: Option<Timestamp>,
+    /// optional, rows without a currency all move the same unnamed balance.
+    /// dispute, resolve and chargeback always act on the currency of the disputed transaction
+    #[serde(rename = "currency", default)]
+    pub currency: Option<Currency>,
+    /// only for exchanges, the currency the amount is converted to
+    #[serde(rename = "to_currency", default)]
+    pub to_currency: Option<Currency>,
+    /// only for exchanges, the rate applied, recorded once executed
+    #[serde(rename = "rate", default)]
+    pub rate: Option<Decimal>,
+    /// only for deposits and withdrawals, the part of the amount currently held by disputes
+    #[serde(skip_deserializing, skip_serializing_if = "Decimal::is_zero")]
+    pub disputed: Amount,
+    /// only for deposits and withdrawals, the part of the amount released by resolves
+    #[serde(skip_deserializing, skip_serializing_if = "Decimal::is_zero")]
+    pub resolved: Amount,
+    /// only for deposits and withdrawals, the part of the amount lost to chargebacks
+    #[serde(skip_deserializing, skip_serializing_if = "Decimal::is_zero")]
+    pub charged_back: Amount,
+    /// only for deposits and withdrawals, when the latest dispute was opened
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub disputed_at: Option<Timestamp>,
 }
 
 impl Transaction {
@@ -76,45 +165,193 @@ impl Transaction {
             transaction_id,
             amount,
             status: default_status(),
+            timestamp: None,
+            currency: None,
+            to_currency: None,
+            rate: None,
+            disputed: Decimal::ZERO,
+            resolved: Decimal::ZERO,
+            charged_back: Decimal::ZERO,
+            disputed_at: None,
         }
     }
 
-    /// Only some transactions should have an amount
+    /// The same transaction, happened at `timestamp`
+    #[must_use]
+    pub fn with_timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// The same transaction, in `currency`
+    #[must_use]
+    pub fn with_currency(mut self, currency: &str) -> Self {
+        self.currency = Some(Currency::from(currency));
+        self
+    }
+
+    /// The same transaction, converting `currency` to `to_currency`
+    #[must_use]
+    pub fn with_exchange(mut self, currency: &str, to_currency: &str) -> Self {
+        self.currency = Some(Currency::from(currency));
+        self.to_currency = Some(Currency::from(to_currency));
+        self
+    }
+
+    /// Checks a transaction as submitted:
+    /// - only some transactions have an amount: deposits and withdrawals need one, exchanges and
+    ///   authorizations a positive one and credit limits one that is not negative, while disputes,
+    ///   resolves, chargebacks and captures may have a positive one
+    /// - ticks need a timestamp
+    /// - currencies are three uppercase letters codes, and exchanges need two different ones
+    /// - fees are generated by the engine, not submitted
     ///
     /// # Errors
     ///
-    /// Returns an error if the amount is not correct for this transaction (only Deposit and Withrawal can have amounts)
+    /// Returns an error telling the first rule the transaction breaks
     pub fn validate(self) -> Result<Self, TransactionError> {
-        match self.transaction_type {
-            TransactionType::Deposit | TransactionType::Withdrawal => self.amount.is_some(),
-            TransactionType::Chargeback | TransactionType::Dispute | TransactionType::Resolve => self.amount.is_none(),
+        for currency in [&self.currency, &self.to_currency].into_iter().flatten() {
+            if !is_valid_currency(currency) {
+                return Err(TransactionError::InvalidTransaction(format!(
+                    "Invalid currency [{currency}]"
+                )));
+            }
         }
-        .then_some(self)
-        .ok_or(
-            TransactionError::InvalidTransaction(String::from("Amount is not correct for this transaction (only Deposit and Withdrawal can have amounts)")))
+
+        let broken_rule = match self.transaction_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => self
+                .amount
+                .is_none()
+                .then_some("Deposits and withdrawals need an amount"),
+            TransactionType::Exchange | TransactionType::Authorize => self
+                .amount
+                .is_none_or(|amount| amount <= Decimal::ZERO)
+                .then_some("Exchanges and authorizations need a positive amount"),
+            TransactionType::CreditLimit => self
+                .amount
+                .is_none_or(|amount| amount < Decimal::ZERO)
+                .then_some("Credit limits need an amount that is not negative"),
+            TransactionType::Chargeback
+            | TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Capture => self
+                .amount
+                .is_some_and(|amount| amount <= Decimal::ZERO)
+                .then_some(
+                    "Disputes, resolves, chargebacks and captures may only have a positive amount",
+                ),
+            TransactionType::Reversal
+            | TransactionType::Void
+            | TransactionType::Accrue
+            | TransactionType::Open
+            | TransactionType::Suspend
+            | TransactionType::Activate
+            | TransactionType::Close => self
+                .amount
+                .is_some()
+                .then_some("Amount is not expected for this transaction"),
+            TransactionType::Tick => {
+                if self.amount.is_some() {
+                    Some("Amount is not expected for this transaction")
+                } else {
+                    self.timestamp.is_none().then_some("Ticks need a timestamp")
+                }
+            }
+            TransactionType::Fee => Some("Fees are charged by the engine, they can't be submitted"),
+        };
+        if let Some(rule) = broken_rule {
+            return Err(TransactionError::InvalidTransaction(String::from(rule)));
+        }
+
+        if self.transaction_type == TransactionType::Exchange
+            && (self.currency.is_none()
+                || self.to_currency.is_none()
+                || self.currency == self.to_currency)
+        {
+            return Err(TransactionError::InvalidTransaction(String::from(
+                "An exchange needs two different currencies",
+            )));
+        }
+
+        Ok(self)
     }
 
     #[must_use]
     /// Checks wether a transaction has a tx or refers to a tx, for searching purposes
     pub fn is_disputing(&self) -> bool {
         match self.transaction_type {
-            TransactionType::Chargeback | TransactionType::Dispute | TransactionType::Resolve => {
-                true
-            }
-            TransactionType::Deposit | TransactionType::Withdrawal => false,
+            // a fee shares the tx of the transaction it is charged for, and can't be disputed
+            TransactionType::Chargeback
+            | TransactionType::Dispute
+            | TransactionType::Resolve
+            | TransactionType::Reversal
+            | TransactionType::Capture
+            | TransactionType::Void
+            | TransactionType::Fee => true,
+            TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::Exchange
+            | TransactionType::Authorize
+            | TransactionType::CreditLimit
+            | TransactionType::Open
+            | TransactionType::Suspend
+            | TransactionType::Activate
+            | TransactionType::Close
+            | TransactionType::Tick
+            | TransactionType::Accrue => false,
         }
     }
 
-    pub fn dispute(&mut self) {
+    /// What can still be disputed: what is neither held by a dispute nor charged back.
+    /// Resolved amounts can be disputed again
+    #[must_use]
+    pub fn disputable(&self) -> Amount {
+        self.amount.unwrap_or_default() - self.disputed - self.charged_back
+    }
+
+    /// `amount` more is held by a dispute
+    pub fn dispute(&mut self, amount: Amount) {
+        self.disputed += amount;
         self.status = TransactionStatus::Disputed;
     }
 
-    pub fn resolve(&mut self) {
-        self.status = TransactionStatus::Executed;
+    /// `amount` of the disputed part is released. The transaction is disputed until
+    /// nothing is held anymore
+    pub fn resolve(&mut self, amount: Amount) {
+        self.disputed -= amount;
+        self.resolved += amount;
+        self.settle();
+    }
+
+    /// `amount` of the disputed part is lost. The transaction is disputed until nothing is
+    /// held anymore
+    pub fn chargeback(&mut self, amount: Amount) {
+        self.disputed -= amount;
+        self.charged_back += amount;
+        self.settle();
+    }
+
+    // once nothing is held, the transaction is charged back if any part of it was
+    fn settle(&mut self) {
+        if self.disputed.is_zero() {
+            self.status = if self.charged_back.is_zero() {
+                TransactionStatus::Executed
+            } else {
+                TransactionStatus::ChargedBack
+            };
+        }
     }
 
-    pub fn chargeback(&mut self) {
-        self.status = TransactionStatus::ChargedBack;
+    pub fn reversed(&mut self) {
+        self.status = TransactionStatus::Reversed;
+    }
+
+    pub fn captured(&mut self) {
+        self.status = TransactionStatus::Captured;
+    }
+
+    pub fn voided(&mut self) {
+        self.status = TransactionStatus::Voided;
     }
 
     pub fn skipped(&mut self) {
@@ -136,19 +373,88 @@ mod test {
         let mut transaction =
             Transaction::new(TransactionType::Withdrawal, 10, 32, Some(dec!(100.0)));
 
-        transaction.dispute();
+        transaction.dispute(dec!(100.0));
         assert_eq!(transaction.status, TransactionStatus::Disputed);
 
-        transaction.resolve();
+        transaction.resolve(dec!(100.0));
         assert_eq!(transaction.status, TransactionStatus::Executed);
 
-        transaction.chargeback();
+        transaction.dispute(dec!(100.0));
+        transaction.chargeback(dec!(100.0));
         assert_eq!(transaction.status, TransactionStatus::ChargedBack);
 
+        transaction.reversed();
+        assert_eq!(transaction.status, TransactionStatus::Reversed);
+
+        transaction.captured();
+        assert_eq!(transaction.status, TransactionStatus::Captured);
+
+        transaction.voided();
+        assert_eq!(transaction.status, TransactionStatus::Voided);
+
         transaction.skipped();
         assert_eq!(transaction.status, TransactionStatus::Skipped);
 
         transaction.executed();
         assert_eq!(transaction.status, TransactionStatus::Executed);
     }
+
+    #[test]
+    fn partial_disputes_test() {
+        let mut transaction = Transaction::new(TransactionType::Deposit, 10, 32, Some(dec!(100.0)));
+
+        transaction.dispute(dec!(30.0));
+        transaction.dispute(dec!(20.0));
+        assert_eq!(transaction.disputable(), dec!(50.0));
+
+        transaction.resolve(dec!(30.0));
+        assert_eq!(transaction.status, TransactionStatus::Disputed);
+        assert_eq!(transaction.disputable(), dec!(80.0));
+
+        transaction.resolve(dec!(20.0));
+        assert_eq!(transaction.status, TransactionStatus::Executed);
+        assert_eq!(transaction.resolved, dec!(50.0));
+
+        transaction.dispute(dec!(10.0));
+        transaction.chargeback(dec!(10.0));
+        assert_eq!(transaction.status, TransactionStatus::ChargedBack);
+        assert_eq!(transaction.disputable(), dec!(90.0));
+        assert_eq!(transaction.charged_back, dec!(10.0));
+
+        // still disputed while a part is held, charged back once released
+        transaction.dispute(dec!(40.0));
+        transaction.chargeback(dec!(15.0));
+        assert_eq!(transaction.status, TransactionStatus::Disputed);
+        transaction.resolve(dec!(25.0));
+        assert_eq!(transaction.status, TransactionStatus::ChargedBack);
+    }
+
+    #[test]
+    fn currency_validation_test() {
+        let deposit = Transaction::new(TransactionType::Deposit, 10, 32, Some(dec!(100.0)));
+
+        assert!(deposit.clone().with_currency("EUR").validate().is_ok());
+        assert!(deposit.clone().with_currency("eur").validate().is_err());
+        assert!(deposit.with_currency("EURO").validate().is_err());
+    }
+
+    #[test]
+    fn exchange_validation_test() {
+        let exchange = Transaction::new(TransactionType::Exchange, 10, 32, Some(dec!(100.0)));
+
+        assert!(exchange
+            .clone()
+            .with_exchange("EUR", "USD")
+            .validate()
+            .is_ok());
+        assert!(exchange
+            .clone()
+            .with_exchange("EUR", "EUR")
+            .validate()
+            .is_err());
+        assert!(exchange.with_currency("EUR").validate().is_err());
+
+        let negative = Transaction::new(TransactionType::Exchange, 10, 32, Some(dec!(-100.0)));
+        assert!(negative.with_exchange("EUR", "USD").validate().is_err());
+    }
 }
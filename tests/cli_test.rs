@@ -3,6 +3,7 @@ mod tests {
     use std::process::Command;
 
     #[test]
+    #[allow(clippy::option_env_unwrap)]
     fn successful_cli_invocation() {
         // CARGO_BIN_EXE_<name> env set by cargo on tests
         let binary = option_env!("CARGO_BIN_EXE_tren").unwrap();
@@ -15,7 +16,7 @@ mod tests {
 
         let stdout = String::from_utf8_lossy(&output.stdout);
         println!("{:?}", stdout);
-        assert!(stdout.contains("locked"), "Output did not contain `locked`");
+        assert!(stdout.contains("status"), "Output did not contain `status`");
         // not exhaustive of course but "good enough": the file has a 1.00009 - 5 digits after decimals
         // that by assumptions will be accepted and then rounded
         assert!(
@@ -25,6 +26,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::option_env_unwrap)]
     fn unsuccessful_cli_invocation() {
         // Cargo sets the CARGO_BIN_EXE_<name> environment variable for tests.
         let binary = option_env!("CARGO_BIN_EXE_tren").unwrap();
@@ -38,4 +40,324 @@ mod tests {
             "Process exited normally, but should have failed"
         );
     }
+
+    #[test]
+    fn currencies_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let output = Command::new(binary)
+            .arg("src/tests/currencies.csv")
+            .output()
+            .expect("failed to execute process");
+
+        assert!(output.status.success(), "Process exited abnormally");
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.starts_with("client, currency, available, held, total, status"),
+            "Output did not have a currency column"
+        );
+        assert!(
+            stdout.contains("1, USD, 0.0000, 5.0000, 5.0000, operational"),
+            "Output did not contain one row per currency"
+        );
+        assert!(
+            stdout.contains("2, , 1.0000, 0.0000, 1.0000, frozen"),
+            "Output did not contain the balance without currency"
+        );
+    }
+
+    #[test]
+    fn what_if_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let output = Command::new(binary)
+            .arg("src/tests/base_transactions.csv")
+            .arg("--what-if")
+            .arg("src/tests/simple_disputed_chargeback.csv")
+            .output()
+            .expect("failed to execute process");
+
+        assert!(output.status.success(), "Process exited abnormally");
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("1, 3.5000, 0.0000, 3.5000, frozen"),
+            "Output did not contain the resulting balances"
+        );
+        assert!(
+            stdout.contains("1, changed, 2.0000, 0.0000, 2.0000, operational, frozen"),
+            "Output did not contain the diff"
+        );
+    }
+
+    #[test]
+    fn diff_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let output = Command::new(binary)
+            .args([
+                "diff",
+                "src/tests/accounts_before.csv",
+                "src/tests/accounts_after.csv",
+                "--format",
+                "json",
+            ])
+            .output()
+            .expect("failed to execute process");
+
+        assert_eq!(output.status.code(), Some(1), "Differences were expected");
+
+        let changes: serde_json::Value =
+            serde_json::from_slice(&output.stdout).expect("Output should be json");
+        let changes = changes.as_array().expect("Output should be a json array");
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0]["status_changed"], true);
+        assert_eq!(changes[2]["change"], "added");
+    }
+
+    #[test]
+    fn diff_within_tolerance_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let output = Command::new(binary)
+            .args([
+                "diff",
+                "src/tests/accounts_before.csv",
+                "src/tests/accounts_before.csv",
+            ])
+            .output()
+            .expect("failed to execute process");
+
+        assert!(output.status.success(), "No difference was expected");
+
+        // client 2 drifted by 0.0005
+        let diff_with_tolerance = |tolerance| {
+            Command::new(binary)
+                .args([
+                    "diff",
+                    "src/tests/accounts_before.csv",
+                    "src/tests/accounts_before_drift.csv",
+                    "--tolerance",
+                    tolerance,
+                ])
+                .output()
+                .expect("failed to execute process")
+        };
+
+        let output = diff_with_tolerance("0.001");
+        assert!(
+            output.status.success(),
+            "A difference below the tolerance was not expected to count"
+        );
+
+        let output = diff_with_tolerance("0.0001");
+        assert_eq!(
+            output.status.code(),
+            Some(1),
+            "A difference above the tolerance was expected"
+        );
+    }
+
+    #[test]
+    fn diff_error_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let output = Command::new(binary)
+            .args([
+                "diff",
+                "src/tests/accounts_before.csv",
+                "src/tests/random_nonexistent_file0989072839743.csv",
+            ])
+            .output()
+            .expect("failed to execute process");
+
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "Errors should be told apart from differences"
+        );
+    }
+
+    #[test]
+    fn fees_without_house_account_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let output = Command::new(binary)
+            .args(["src/tests/cli.csv", "--fees", "src/tests/fees.csv"])
+            .output()
+            .expect("failed to execute process");
+
+        assert_eq!(
+            output.status.code(),
+            Some(2),
+            "Fees should not be credited to a default account"
+        );
+    }
+
+    #[test]
+    fn out_of_range_days_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        for flag in [
+            "--dispute-window",
+            "--dispute-expiry",
+            "--authorization-expiry",
+            "--dormant-after",
+        ] {
+            for days in ["99999999999999", "-1"] {
+                let output = Command::new(binary)
+                    .args(["src/tests/cli.csv", flag, days])
+                    .output()
+                    .expect("failed to execute process");
+
+                assert_eq!(
+                    output.status.code(),
+                    Some(2),
+                    "{flag} {days} should be refused as a usage error"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bounded_history_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let spill_file = std::env::temp_dir().join(format!("tren_cli_{}.bin", std::process::id()));
+        let bounded = Command::new(binary)
+            .arg("src/tests/partial_disputes.csv")
+            .arg("--max-history")
+            .arg("1")
+            .arg("--spill-file")
+            .arg(&spill_file)
+            .output()
+            .expect("failed to execute process");
+        let unbounded = Command::new(binary)
+            .arg("src/tests/partial_disputes.csv")
+            .output()
+            .expect("failed to execute process");
+
+        assert!(bounded.status.success(), "Process exited abnormally");
+        assert_eq!(
+            bounded.stdout, unbounded.stdout,
+            "Bounding the history changed the balances"
+        );
+        assert!(!spill_file.exists(), "Spill file should have been removed");
+    }
+
+    #[test]
+    fn existing_spill_file_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let spill_file =
+            std::env::temp_dir().join(format!("tren_cli_existing_{}.csv", std::process::id()));
+        std::fs::copy("src/tests/cli.csv", &spill_file).expect("Input should have been copied");
+        for command in [vec![], vec!["serve", "--input"]] {
+            let output = Command::new(binary)
+                .args(command)
+                .arg("src/tests/cli.csv")
+                .arg("--max-history")
+                .arg("1")
+                .arg("--spill-file")
+                .arg(&spill_file)
+                .output()
+                .expect("failed to execute process");
+
+            assert!(!output.status.success(), "Process should have failed");
+            assert!(String::from_utf8_lossy(&output.stderr).contains("already exists"));
+            assert_eq!(
+                std::fs::read(&spill_file).expect("Spill file should be there"),
+                std::fs::read("src/tests/cli.csv").expect("Input should be there"),
+                "Spill file should have been left as it was"
+            );
+        }
+        std::fs::remove_file(spill_file).expect("Spill file should have been created");
+    }
+
+    #[test]
+    fn kv_storage_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let kv_file = std::env::temp_dir().join(format!("tren_cli_{}.redb", std::process::id()));
+        let no_transactions =
+            std::env::temp_dir().join(format!("tren_cli_empty_{}.csv", std::process::id()));
+        std::fs::write(&no_transactions, "type, client, tx, amount\n")
+            .expect("Input should have been written");
+        let stored = Command::new(binary)
+            .arg("src/tests/partial_disputes.csv")
+            .arg("--kv-file")
+            .arg(&kv_file)
+            .output()
+            .expect("failed to execute process");
+        let reopened = Command::new(binary)
+            .arg(&no_transactions)
+            .arg("--kv-file")
+            .arg(&kv_file)
+            .output()
+            .expect("failed to execute process");
+        let in_memory = Command::new(binary)
+            .arg("src/tests/partial_disputes.csv")
+            .output()
+            .expect("failed to execute process");
+
+        assert!(stored.status.success(), "Process exited abnormally");
+        assert_eq!(
+            stored.stdout, in_memory.stdout,
+            "The key-value store changed the balances"
+        );
+        assert_eq!(
+            reopened.stdout, in_memory.stdout,
+            "The balances were not read back from the key-value store"
+        );
+        std::fs::remove_file(kv_file).expect("Key-value file should have been created");
+        std::fs::remove_file(no_transactions).expect("Input should have been created");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn serve_kv_storage_invocation() {
+        let binary = env!("CARGO_BIN_EXE_tren");
+        let kv_file =
+            std::env::temp_dir().join(format!("tren_cli_serve_{}.redb", std::process::id()));
+        let no_transactions =
+            std::env::temp_dir().join(format!("tren_cli_serve_{}.csv", std::process::id()));
+        std::fs::write(&no_transactions, "type, client, tx, amount\n")
+            .expect("Input should have been written");
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Expected a free local port");
+
+        // the server loads the transactions, and stores them when stopped
+        let mut server = Command::new(binary)
+            .args([
+                "serve",
+                "--input",
+                "src/tests/partial_disputes.csv",
+                "--bind",
+            ])
+            .arg(address.to_string())
+            .arg("--kv-file")
+            .arg(&kv_file)
+            .spawn()
+            .expect("failed to execute process");
+        let listening = (0..50).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            std::net::TcpStream::connect(address).is_ok()
+        });
+        Command::new("kill")
+            .args(["-INT", &server.id().to_string()])
+            .status()
+            .expect("failed to stop the server");
+        let stopped = server.wait().expect("failed to wait for the server");
+        let reopened = Command::new(binary)
+            .arg(&no_transactions)
+            .arg("--kv-file")
+            .arg(&kv_file)
+            .output()
+            .expect("failed to execute process");
+        let in_memory = Command::new(binary)
+            .arg("src/tests/partial_disputes.csv")
+            .output()
+            .expect("failed to execute process");
+
+        assert!(listening, "Server never listened");
+        assert!(stopped.success(), "Server exited abnormally");
+        assert_eq!(
+            reopened.stdout, in_memory.stdout,
+            "The balances served were not stored in the key-value store"
+        );
+        std::fs::remove_file(kv_file).expect("Key-value file should have been created");
+        std::fs::remove_file(no_transactions).expect("Input should have been created");
+    }
 }
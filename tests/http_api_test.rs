@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use rust_decimal_macros::dec;
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tren::tren::account::AccountStatus;
+    use tren::tren::api::http_server;
+    use tren::tren::api::views::{AccountView, AccountsPage, OutcomeView, SubmissionResult};
+    use tren::tren::client::ClientId;
+    use tren::tren::engine::runner::Runner;
+    use tren::tren::handlers::execute_handler::ExecuteHandler;
+    use tren::tren::storage::in_memory_accounts_storage::InMemoryAccountsStorage;
+    use tren::tren::transactions::{Transaction, TransactionStatus};
+
+    /// Start a server on a random local port, returning its base url
+    async fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Expected to bind a local port");
+        let address = listener.local_addr().expect("Expected a local address");
+
+        let runner = Runner::new(
+            ExecuteHandler::default(),
+            InMemoryAccountsStorage::default(),
+        );
+        tokio::spawn(http_server::serve(listener, runner));
+
+        format!("http://{address}")
+    }
+
+    #[tokio::test]
+    async fn submit_and_query_account_test() {
+        let base_url = start_server().await;
+        let client = reqwest::Client::new();
+
+        // single submission
+        let result: SubmissionResult = client
+            .post(format!("{base_url}/transactions"))
+            .json(&json!({"type": "deposit", "client": 1, "tx": 1, "amount": "10.5"}))
+            .send()
+            .await
+            .expect("Expected a response")
+            .json()
+            .await
+            .expect("Expected a json body");
+        assert_eq!(result.outcome, OutcomeView::Success);
+
+        // batch submission, second withdrawal is skipped for insufficient funds
+        let results: Vec<SubmissionResult> = client
+            .post(format!("{base_url}/transactions"))
+            .json(&json!([
+                {"type": "withdrawal", "client": 1, "tx": 2, "amount": "0.5"},
+                {"type": "withdrawal", "client": 1, "tx": 3, "amount": "100"},
+                {"type": "dispute", "client": 1, "tx": 1},
+            ]))
+            .send()
+            .await
+            .expect("Expected a response")
+            .json()
+            .await
+            .expect("Expected a json body");
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| &result.outcome)
+                .collect::<Vec<&OutcomeView>>(),
+            vec![
+                &OutcomeView::Success,
+                &OutcomeView::Skipped,
+                &OutcomeView::Success
+            ]
+        );
+
+        let account: AccountView = client
+            .get(format!("{base_url}/accounts/1"))
+            .send()
+            .await
+            .expect("Expected a response")
+            .json()
+            .await
+            .expect("Expected a json body");
+        assert_eq!(account.available, dec!(-0.5));
+        assert_eq!(account.held, dec!(10.5));
+        assert_eq!(account.total, dec!(10));
+        assert_eq!(account.status, AccountStatus::Operational);
+
+        let transactions: Vec<Transaction> = client
+            .get(format!("{base_url}/accounts/1/transactions"))
+            .send()
+            .await
+            .expect("Expected a response")
+            .json()
+            .await
+            .expect("Expected a json body");
+        assert_eq!(transactions.len(), 4);
+        assert_eq!(transactions[0].status, TransactionStatus::Disputed);
+    }
+
+    #[tokio::test]
+    async fn list_accounts_is_paged_test() {
+        let base_url = start_server().await;
+        let client = reqwest::Client::new();
+
+        let batch = (1..=5)
+            .map(|id| json!({"type": "deposit", "client": id, "tx": id, "amount": "1"}))
+            .collect::<Vec<serde_json::Value>>();
+        let response = client
+            .post(format!("{base_url}/transactions"))
+            .json(&batch)
+            .send()
+            .await
+            .expect("Expected a response");
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let page: AccountsPage = client
+            .get(format!("{base_url}/accounts?offset=1&limit=2"))
+            .send()
+            .await
+            .expect("Expected a response")
+            .json()
+            .await
+            .expect("Expected a json body");
+        assert_eq!(page.total, 5);
+        assert_eq!(
+            page.accounts
+                .iter()
+                .map(|account| account.client)
+                .collect::<Vec<ClientId>>(),
+            vec![2, 3]
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_test() {
+        let base_url = start_server().await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{base_url}/accounts/42"))
+            .send()
+            .await
+            .expect("Expected a response");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = client
+            .get(format!("{base_url}/accounts/42/transactions"))
+            .send()
+            .await
+            .expect("Expected a response");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // a deposit without amount is not valid, and the whole batch is refused
+        let response = client
+            .post(format!("{base_url}/transactions"))
+            .json(&json!([
+                {"type": "deposit", "client": 1, "tx": 1, "amount": "1"},
+                {"type": "deposit", "client": 1, "tx": 2},
+            ]))
+            .send()
+            .await
+            .expect("Expected a response");
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = client
+            .get(format!("{base_url}/accounts/1"))
+            .send()
+            .await
+            .expect("Expected a response");
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}